@@ -1,29 +1,145 @@
 // build.rs
 
 use bootloader::DiskImageBuilder;
-use std::{env, path::PathBuf};
+use common::ramdisk::{ramdisk_len, write_ramdisk, FileKind, RamdiskFile};
+use serde::Deserialize;
+use std::{
+    env,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// `images.toml`
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    image: Vec<ImageManifest>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageManifest {
+    name: String,
+    init: String,
+    #[serde(default)]
+    programs: Vec<String>,
+    #[serde(default)]
+    files: Vec<PathBuf>,
+}
+
+/// Returns the name of a program, which is written as "crate/binary" or "crate", and its ELF
+fn find_program(program: &str) -> (String, PathBuf) {
+    let (crate_name, binary) = program.split_once('/').unwrap_or((program, program));
+    // set by cargo for artifact dependencies
+    let var = format!(
+        "CARGO_BIN_FILE_{}_{binary}",
+        crate_name.to_uppercase().replace('-', "_")
+    );
+    let path = env::var(&var).unwrap_or_else(|_| {
+        panic!("{program} is not a binary of an artifact dependency ({var} is not set)")
+    });
+    (binary.into(), path.into())
+}
+
+/// Writes the ramdisk of an image and returns the path of its init program
+fn create_ramdisk(image: &ImageManifest, manifest_dir: &Path, ramdisk_path: &Path) -> PathBuf {
+    let (init_name, init_path) = find_program(&image.init);
+    let mut files = vec![(init_name, FileKind::Program, fs::read(&init_path).unwrap())];
+    for program in &image.programs {
+        let (name, path) = find_program(program);
+        files.push((name, FileKind::Program, fs::read(path).unwrap()));
+    }
+    for file in &image.files {
+        let path = manifest_dir.join(file);
+        println!("cargo:rerun-if-changed={}", path.display());
+        let name = file.file_name().unwrap().to_string_lossy().into_owned();
+        files.push((name, FileKind::Data, fs::read(&path).unwrap()));
+    }
+    for (i, (name, _, _)) in files.iter().enumerate() {
+        assert!(
+            files[..i].iter().all(|(other, _, _)| other != name),
+            "image {} has more than one file named {name}",
+            image.name
+        );
+    }
+
+    let files = files
+        .iter()
+        .map(|(name, kind, contents)| RamdiskFile {
+            name,
+            kind: *kind,
+            contents,
+        })
+        .collect::<Vec<_>>();
+    let mut ramdisk = vec![0; ramdisk_len(&files)];
+    write_ramdisk(&files, 0, &mut ramdisk).unwrap();
+    fs::write(ramdisk_path, ramdisk).unwrap();
+    init_path
+}
 
 fn main() {
     let package_name = env::var("CARGO_PKG_NAME").unwrap();
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    let manifest_path = manifest_dir.join("images.toml");
+    println!("cargo:rerun-if-changed={}", manifest_path.display());
+    let manifest: Manifest =
+        toml::from_str(&fs::read_to_string(&manifest_path).unwrap()).expect("invalid images.toml");
+    assert!(
+        manifest.image.iter().any(|image| image.name == "tests"),
+        "images.toml needs an image named tests for the kernel tests"
+    );
 
     // set by cargo for the kernel artifact dependency
     let kernel_path = env::var("CARGO_BIN_FILE_KERNEL").unwrap();
-    let mut disk_builder = DiskImageBuilder::new(PathBuf::from(&kernel_path));
-    let userspace_path = env::var("CARGO_BIN_FILE_USER_SPACE").unwrap();
-    disk_builder.set_ramdisk((&userspace_path).into());
+    let kernel_tests_path = env::var("CARGO_BIN_FILE_KERNEL_kernel_tests").unwrap();
 
-    // specify output paths
-    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
-    let uefi_path = out_dir.join(format!("{package_name}-uefi.img"));
-    let bios_path = out_dir.join(format!("{package_name}-bios.img"));
+    // the images are listed in images.rs, which src/lib.rs includes
+    let mut images_rs = String::from("pub const IMAGES: &[Image] = &[\n");
+    for image in &manifest.image {
+        let ramdisk_path = out_dir.join(format!("{}.ramdisk", image.name));
+        let init_path = create_ramdisk(image, &manifest_dir, &ramdisk_path);
+
+        let mut disk_builder = DiskImageBuilder::new(PathBuf::from(&kernel_path));
+        disk_builder.set_ramdisk(ramdisk_path.clone());
+        let uefi_path = out_dir.join(format!("{package_name}-{}-uefi.img", image.name));
+        let bios_path = out_dir.join(format!("{package_name}-{}-bios.img", image.name));
+        disk_builder.create_uefi_image(&uefi_path).unwrap();
+        disk_builder.create_bios_image(&bios_path).unwrap();
+        writeln!(
+            images_rs,
+            "    Image {{ name: {:?}, init: {:?}, uefi: {:?}, bios: {:?} }},",
+            image.name, init_path, uefi_path, bios_path
+        )
+        .unwrap();
 
-    // create the disk images
-    disk_builder.create_uefi_image(&uefi_path).unwrap();
-    disk_builder.create_bios_image(&bios_path).unwrap();
+        // the kernel tests check the programs of the tests image
+        if image.name == "tests" {
+            let mut kernel_tests_disk_builder =
+                DiskImageBuilder::new(PathBuf::from(&kernel_tests_path));
+            kernel_tests_disk_builder.set_ramdisk(ramdisk_path);
+            let kernel_tests_uefi_path =
+                out_dir.join(format!("{package_name}-kernel-tests-uefi.img"));
+            let kernel_tests_bios_path =
+                out_dir.join(format!("{package_name}-kernel-tests-bios.img"));
+            kernel_tests_disk_builder
+                .create_uefi_image(&kernel_tests_uefi_path)
+                .unwrap();
+            kernel_tests_disk_builder
+                .create_bios_image(&kernel_tests_bios_path)
+                .unwrap();
+            println!(
+                "cargo:rustc-env=KERNEL_TESTS_UEFI_IMAGE={}",
+                kernel_tests_uefi_path.display()
+            );
+            println!(
+                "cargo:rustc-env=KERNEL_TESTS_BIOS_IMAGE={}",
+                kernel_tests_bios_path.display()
+            );
+        }
+    }
+    images_rs.push_str("];\n");
+    fs::write(out_dir.join("images.rs"), images_rs).unwrap();
 
-    // pass the disk image paths via environment variables
-    println!("cargo:rustc-env=UEFI_IMAGE={}", uefi_path.display());
-    println!("cargo:rustc-env=BIOS_IMAGE={}", bios_path.display());
     println!("cargo:rustc-env=CARGO_BIN_FILE_KERNEL={}", kernel_path);
-    println!("cargo:rustc-env=USER_SPACE={}", userspace_path);
 }
@@ -0,0 +1,57 @@
+use postcard::experimental::max_size::MaxSize;
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::syscall_channel::MAX_MESSAGE_LEN;
+
+/// A type that is sent through a channel, encoded with postcard.
+/// Both ends of a channel need to agree on the types being sent.
+pub trait ChannelMessage: Serialize + DeserializeOwned + MaxSize {
+    /// Check this with a test, because it can't be checked at compile time yet
+    const FITS_IN_MESSAGE: bool = Self::POSTCARD_MAX_SIZE <= MAX_MESSAGE_LEN;
+
+    fn to_message<'a>(&self, buffer: &'a mut [u8]) -> postcard::Result<&'a mut [u8]> {
+        postcard::to_slice(self, buffer)
+    }
+
+    fn from_message(message: &[u8]) -> postcard::Result<Self> {
+        postcard::from_bytes(message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+    enum DisplayRequest {
+        Clear,
+        FillRect {
+            x: u32,
+            y: u32,
+            width: u32,
+            height: u32,
+        },
+    }
+
+    impl ChannelMessage for DisplayRequest {}
+
+    #[test]
+    fn fits_in_message() {
+        assert!(DisplayRequest::FITS_IN_MESSAGE);
+    }
+
+    #[test]
+    fn encode_and_decode() {
+        let request = DisplayRequest::FillRect {
+            x: 1,
+            y: 2,
+            width: 300,
+            height: 400,
+        };
+        let mut buffer = [0; DisplayRequest::POSTCARD_MAX_SIZE];
+        let message = request.to_message(&mut buffer).unwrap();
+        assert_eq!(DisplayRequest::from_message(message).unwrap(), request);
+    }
+}
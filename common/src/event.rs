@@ -0,0 +1,47 @@
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+/// Things that can interrupt user space with an event handler.
+/// Sorted from highest to lowest priority. An event handler can only be interrupted by event handlers of events with a higher priority, so an event handler never interrupts itself.
+#[derive(
+    Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum EventSource {
+    Timer,
+    Rtc,
+    Keyboard,
+    Mouse,
+    Serial,
+    /// A message or handle was sent to a channel endpoint that this process has a handle to
+    Channel,
+}
+
+impl EventSource {
+    pub const COUNT: usize = 6;
+    /// Sorted from highest to lowest priority
+    pub const ALL: [Self; Self::COUNT] = [
+        Self::Timer,
+        Self::Rtc,
+        Self::Keyboard,
+        Self::Mouse,
+        Self::Serial,
+        Self::Channel,
+    ];
+
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn all_is_sorted_by_priority() {
+        for (index, source) in EventSource::ALL.into_iter().enumerate() {
+            assert_eq!(source.index(), index);
+        }
+        assert!(EventSource::ALL.is_sorted());
+    }
+}
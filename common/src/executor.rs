@@ -0,0 +1,411 @@
+//! Runs many tasks at once on one thread, such as drivers, input handling and animation. The kernel
+//! and the user runtime both use this executor, and only differ in how they [`Wait`].
+//! Tasks are usually woken by interrupt or event handlers, which can't allocate or lock. So waking a
+//! task only sets flags, and the executor finds the woken tasks and puts them in its queue.
+
+use core::{
+    cell::RefCell,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use alloc::{
+    boxed::Box,
+    collections::{VecDeque, btree_map::BTreeMap},
+    rc::Rc,
+    sync::Arc,
+    task::Wake,
+    vec::Vec,
+};
+
+type TaskId = u64;
+
+/// What an [`Executor`] does when no task is woken
+pub trait Wait {
+    /// Returns once a task might have been woken, like after an interrupt.
+    /// A task can be woken at any time, even right after `woken` is checked, so check it and start
+    /// waiting in a way that doesn't miss a wake in between, like with interrupts disabled.
+    fn wait(&mut self, woken: &AtomicBool);
+}
+
+/// Woken tasks with a higher priority are polled first. Every woken task is still polled once per
+/// round, so tasks with a lower priority can't be starved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+struct TaskWaker {
+    woken: AtomicBool,
+    /// Shared by all tasks of the executor, so that it knows when to look for woken tasks
+    executor_woken: Arc<AtomicBool>,
+}
+
+impl TaskWaker {
+    /// Starts out woken, so that the task gets polled for the first time
+    fn new(executor_woken: Arc<AtomicBool>) -> Arc<Self> {
+        executor_woken.store(true, Ordering::Relaxed);
+        Arc::new(Self {
+            woken: AtomicBool::new(true),
+            executor_woken,
+        })
+    }
+
+    fn take_woken(&self) -> bool {
+        self.woken.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Relaxed);
+        self.executor_woken.store(true, Ordering::Relaxed);
+    }
+}
+
+struct Task {
+    future: Pin<Box<dyn Future<Output = ()>>>,
+    priority: Priority,
+    waker: Arc<TaskWaker>,
+}
+
+struct JoinState<T> {
+    output: Option<T>,
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+/// Resolves to the output of a spawned task. Dropping this doesn't cancel the task.
+pub struct JoinHandle<T> {
+    state: Rc<RefCell<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn is_finished(&self) -> bool {
+        self.state.borrow().finished
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.borrow_mut();
+        match state.output.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                assert!(!state.finished, "JoinHandle polled after it was ready");
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Spawns tasks on an [`Executor`], including from inside its tasks.
+/// Don't spawn in interrupt or event handlers, because spawning allocates.
+#[derive(Clone)]
+pub struct Spawner {
+    new_tasks: Rc<RefCell<Vec<Task>>>,
+    executor_woken: Arc<AtomicBool>,
+}
+
+impl Spawner {
+    pub fn spawn<F: Future + 'static>(&self, future: F) -> JoinHandle<F::Output> {
+        self.spawn_with_priority(Priority::default(), future)
+    }
+
+    pub fn spawn_with_priority<F: Future + 'static>(
+        &self,
+        priority: Priority,
+        future: F,
+    ) -> JoinHandle<F::Output> {
+        let state = Rc::new(RefCell::new(JoinState {
+            output: None,
+            finished: false,
+            waker: None,
+        }));
+        let task_state = state.clone();
+        self.new_tasks.borrow_mut().push(Task {
+            future: Box::pin(async move {
+                let output = future.await;
+                let mut state = task_state.borrow_mut();
+                state.output = Some(output);
+                state.finished = true;
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            }),
+            priority,
+            waker: TaskWaker::new(self.executor_woken.clone()),
+        });
+        JoinHandle { state }
+    }
+}
+
+/// Polls woken tasks in rounds, and calls [`Wait::wait`] when no task is woken
+pub struct Executor<W> {
+    tasks: BTreeMap<TaskId, Task>,
+    next_id: TaskId,
+    /// Tasks that were woken and will be polled next, in order of priority
+    queue: VecDeque<TaskId>,
+    spawner: Spawner,
+    wait: W,
+}
+
+impl<W: Wait + Default> Default for Executor<W> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W: Wait + Default> Executor<W> {
+    pub fn new() -> Self {
+        Self::with_wait(W::default())
+    }
+}
+
+impl<W: Wait> Executor<W> {
+    pub fn with_wait(wait: W) -> Self {
+        Self {
+            tasks: BTreeMap::new(),
+            next_id: 0,
+            queue: VecDeque::new(),
+            spawner: Spawner {
+                new_tasks: Default::default(),
+                executor_woken: Arc::new(AtomicBool::new(false)),
+            },
+            wait,
+        }
+    }
+
+    pub fn spawner(&self) -> Spawner {
+        self.spawner.clone()
+    }
+
+    pub fn spawn<F: Future + 'static>(&self, future: F) -> JoinHandle<F::Output> {
+        self.spawner.spawn(future)
+    }
+
+    /// Runs the spawned tasks until `future` is done. Tasks that aren't done by then are dropped.
+    /// `future` is polled before the tasks in each round.
+    pub fn block_on<F: Future>(mut self, future: F) -> F::Output {
+        let mut future = core::pin::pin!(future);
+        let main_waker = TaskWaker::new(self.spawner.executor_woken.clone());
+        let waker = Waker::from(main_waker.clone());
+        loop {
+            self.spawner.executor_woken.store(false, Ordering::Relaxed);
+            if main_waker.take_woken() {
+                match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+                    Poll::Ready(output) => break output,
+                    Poll::Pending => {}
+                }
+            }
+            self.poll_woken_tasks();
+            self.wait_until_woken();
+        }
+    }
+
+    /// Runs until all tasks are done
+    pub fn run(mut self) {
+        loop {
+            self.spawner.executor_woken.store(false, Ordering::Relaxed);
+            self.poll_woken_tasks();
+            if self.tasks.is_empty() && self.spawner.new_tasks.borrow().is_empty() {
+                break;
+            }
+            self.wait_until_woken();
+        }
+    }
+
+    /// Polls each woken task once. Tasks that get woken while doing this are polled next round.
+    fn poll_woken_tasks(&mut self) {
+        for task in self.spawner.new_tasks.take() {
+            self.tasks.insert(self.next_id, task);
+            self.next_id += 1;
+        }
+        let mut woken_tasks = self
+            .tasks
+            .iter()
+            .filter(|(_, task)| task.waker.take_woken())
+            .map(|(id, task)| (task.priority, *id))
+            .collect::<Vec<_>>();
+        woken_tasks.sort();
+        self.queue.extend(woken_tasks.into_iter().map(|(_, id)| id));
+        while let Some(id) = self.queue.pop_front() {
+            let Some(task) = self.tasks.get_mut(&id) else {
+                continue;
+            };
+            let waker = Waker::from(task.waker.clone());
+            if task
+                .future
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_ready()
+            {
+                self.tasks.remove(&id);
+            }
+        }
+    }
+
+    fn wait_until_woken(&mut self) {
+        if !self.spawner.executor_woken.load(Ordering::Relaxed) {
+            self.wait.wait(&self.spawner.executor_woken);
+        }
+    }
+}
+
+/// Lets the other woken tasks run before this task continues
+pub async fn yield_now() {
+    let mut yielded = false;
+    core::future::poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use core::cell::Cell;
+
+    use super::*;
+
+    /// Acts like an interrupt that happens while waiting, by waking the wakers that
+    /// [`WaitForWake`] left. Panics if nothing could ever wake a task.
+    #[derive(Default, Clone)]
+    struct WakeWhileWaiting {
+        wakers: Rc<RefCell<Vec<Waker>>>,
+        waits: Rc<Cell<usize>>,
+    }
+
+    impl Wait for WakeWhileWaiting {
+        fn wait(&mut self, woken: &AtomicBool) {
+            assert!(!woken.load(Ordering::Relaxed));
+            let wakers = self.wakers.take();
+            assert!(!wakers.is_empty(), "waiting, but nothing can wake a task");
+            self.waits.set(self.waits.get() + 1);
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+
+    /// Pending until it's woken by [`WakeWhileWaiting`]
+    fn wait_for_wake(wakers: Rc<RefCell<Vec<Waker>>>) -> impl Future<Output = ()> {
+        let mut registered = false;
+        core::future::poll_fn(move |cx| {
+            if registered {
+                Poll::Ready(())
+            } else {
+                registered = true;
+                wakers.borrow_mut().push(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+    }
+
+    #[test]
+    fn spawn_and_join() {
+        let executor = Executor::<WakeWhileWaiting>::new();
+        let spawner = executor.spawner();
+        let output = executor.block_on(async move {
+            let a = spawner.spawn(async { 1 });
+            let b = spawner.spawn({
+                let spawner = spawner.clone();
+                // Tasks can spawn tasks too
+                async move { spawner.spawn(async { 2 }).await + 1 }
+            });
+            assert!(!a.is_finished());
+            let b = b.await;
+            assert!(a.is_finished());
+            a.await + b
+        });
+        assert_eq!(output, 4);
+    }
+
+    #[test]
+    fn priorities() {
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let executor = Executor::<WakeWhileWaiting>::new();
+        let spawner = executor.spawner();
+        let tasks = [
+            (Priority::Low, 'l'),
+            (Priority::High, 'h'),
+            (Priority::Normal, 'n'),
+        ]
+        .map(|(priority, name)| {
+            let order = order.clone();
+            spawner.spawn_with_priority(priority, async move {
+                order.borrow_mut().push(name);
+                yield_now().await;
+                order.borrow_mut().push(name);
+                name
+            })
+        });
+        let outputs = executor.block_on(async move {
+            let mut outputs = Vec::new();
+            for task in tasks {
+                outputs.push(task.await);
+            }
+            outputs
+        });
+        assert_eq!(outputs, ['l', 'h', 'n']);
+        // Every task runs once per round, with the highest priority first
+        assert_eq!(*order.borrow(), ['h', 'n', 'l', 'h', 'n', 'l']);
+    }
+
+    #[test]
+    fn waits_until_woken() {
+        let wait = WakeWhileWaiting::default();
+        let executor = Executor::with_wait(wait.clone());
+        let polls = Rc::new(Cell::new(0));
+        for _ in 0..2 {
+            let wakers = wait.wakers.clone();
+            let polls = polls.clone();
+            executor.spawn(async move {
+                polls.set(polls.get() + 1);
+                wait_for_wake(wakers).await;
+                polls.set(polls.get() + 1);
+            });
+        }
+        executor.run();
+        // Both tasks were woken by the same wait, and only polled when they were woken
+        assert_eq!(wait.waits.get(), 1);
+        assert_eq!(polls.get(), 4);
+    }
+
+    #[test]
+    fn block_on_drops_unfinished_tasks() {
+        struct SetOnDrop(Rc<Cell<bool>>);
+
+        impl Drop for SetOnDrop {
+            fn drop(&mut self) {
+                self.0.set(true);
+            }
+        }
+
+        let wait = WakeWhileWaiting::default();
+        let executor = Executor::with_wait(wait.clone());
+        let dropped = Rc::new(Cell::new(false));
+        let set_on_drop = SetOnDrop(dropped.clone());
+        executor.spawn(async move {
+            let _set_on_drop = set_on_drop;
+            core::future::pending::<()>().await
+        });
+        executor.block_on(wait_for_wake(wait.wakers.clone()));
+        assert!(dropped.get());
+    }
+}
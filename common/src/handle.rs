@@ -0,0 +1,104 @@
+use core::ops::{BitAnd, BitOr};
+
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+/// An opaque reference to a kernel object. Only valid in the process that it was given to.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Handle(pub u32);
+
+/// Every process starts with these handles
+pub mod initial_handles {
+    use super::Handle;
+
+    /// The process itself
+    pub const PROCESS: Handle = Handle(0);
+    pub const FRAME_BUFFER: Handle = Handle(1);
+    pub const KEYBOARD: Handle = Handle(2);
+    /// Setting the real time needs [`Rights::WRITE`](super::Rights::WRITE) on this
+    pub const REAL_TIME_CLOCK: Handle = Handle(3);
+    pub const MOUSE: Handle = Handle(4);
+    /// Input from COM1
+    pub const SERIAL: Handle = Handle(5);
+}
+
+/// What a handle can be used for. A handle can be duplicated with less rights, but never with more rights.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct Rights(u32);
+
+impl Rights {
+    pub const NONE: Self = Self(0);
+    pub const DUPLICATE: Self = Self(1 << 0);
+    /// The handle can be sent to another process through a channel
+    pub const TRANSFER: Self = Self(1 << 1);
+    /// Receive messages, read key presses, etc.
+    pub const READ: Self = Self(1 << 2);
+    /// Send messages, etc.
+    pub const WRITE: Self = Self(1 << 3);
+    /// Map memory (shared memory, the frame buffer) into the address space
+    pub const MAP: Self = Self(1 << 4);
+    /// Send signals to a process
+    pub const SIGNAL: Self = Self(1 << 5);
+    pub const ALL: Self = Self(0b111111);
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Rights {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.union(rhs)
+    }
+}
+
+impl BitAnd for Rights {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        self.intersection(rhs)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub enum HandleError {
+    /// The handle was never given to this process or was closed
+    InvalidHandle,
+    /// The handle refers to a different type of kernel object
+    WrongObjectType,
+    /// The handle doesn't have the rights needed for the operation
+    MissingRights,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn contains() {
+        let rights = Rights::READ | Rights::WRITE;
+        assert!(rights.contains(Rights::READ));
+        assert!(rights.contains(Rights::READ | Rights::WRITE));
+        assert!(rights.contains(Rights::NONE));
+        assert!(!rights.contains(Rights::READ | Rights::MAP));
+        assert!(Rights::ALL.contains(rights));
+    }
+
+    #[test]
+    fn intersection() {
+        assert_eq!(
+            (Rights::READ | Rights::WRITE) & (Rights::WRITE | Rights::MAP),
+            Rights::WRITE
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+/// Calls `$macro!` with the name of every [`KeyCode`].
+/// The names match `pc_keyboard::KeyCode`, so that the kernel can convert between them without listing every key again.
+#[macro_export]
+macro_rules! for_each_key_code {
+    ($macro:ident) => {
+        $macro! {
+            Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, PrintScreen, SysRq,
+            ScrollLock, PauseBreak, Oem8, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+            Key0, OemMinus, OemPlus, Backspace, Insert, Home, PageUp, NumpadLock, NumpadDivide,
+            NumpadMultiply, NumpadSubtract, Tab, Q, W, E, R, T, Y, U, I, O, P, Oem4, Oem6, Oem5,
+            Oem7, Delete, End, PageDown, Numpad7, Numpad8, Numpad9, NumpadAdd, CapsLock, A, S, D,
+            F, G, H, J, K, L, Oem1, Oem3, Return, Numpad4, Numpad5, Numpad6, LShift, Z, X, C, V, B,
+            N, M, OemComma, OemPeriod, Oem2, RShift, ArrowUp, Numpad1, Numpad2, Numpad3,
+            NumpadEnter, LControl, LWin, LAlt, Spacebar, RAltGr, RWin, Apps, RControl, ArrowLeft,
+            ArrowDown, ArrowRight, Numpad0, NumpadPeriod, Oem9, Oem10, Oem11, Oem12, Oem13,
+            PrevTrack, NextTrack, Mute, Calculator, Play, Stop, VolumeDown, VolumeUp, WWWHome,
+            PowerOnTestOk, TooManyKeys, RControl2, RAlt2
+        }
+    };
+}
+
+macro_rules! define_key_code {
+    ($($name:ident),* $(,)?) => {
+        /// A physical key, named after what it is on a US keyboard. Which character it types depends on the layout.
+        #[derive(
+            Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq, PartialOrd, Ord, Hash,
+        )]
+        pub enum KeyCode {
+            $($name),*
+        }
+    };
+}
+
+for_each_key_code!(define_key_code);
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub enum KeyState {
+    Up,
+    Down,
+    /// For keys that only send a press, like the power button
+    SingleShot,
+}
+
+/// Decides which character a key types
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub enum KeyboardLayout {
+    Us,
+    Uk,
+    Dvorak,
+    Azerty,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub state: KeyState,
+    /// The character that was typed, taking the layout and modifier keys into account
+    pub unicode: Option<char>,
+}
+
+impl KeyEvent {
+    /// Writes key events from `next` back to back until there is no more room for a whole key event, and returns the number of bytes written.
+    /// `next` is only called when the next key event is guaranteed to fit, so no key events are lost.
+    pub fn encode_all(buffer: &mut [u8], mut next: impl FnMut() -> Option<Self>) -> usize {
+        let mut len = 0;
+        while buffer.len() - len >= Self::POSTCARD_MAX_SIZE {
+            let Some(key_event) = next() else {
+                break;
+            };
+            // Can't fail because there is enough room
+            len += postcard::to_slice(&key_event, &mut buffer[len..])
+                .unwrap()
+                .len();
+        }
+        len
+    }
+
+    /// The kernel writes key events back to back, each encoded with postcard
+    pub fn decode_all(mut bytes: &[u8]) -> impl Iterator<Item = Self> {
+        core::iter::from_fn(move || {
+            let (key_event, remaining) = postcard::take_from_bytes(bytes).ok()?;
+            bytes = remaining;
+            Some(key_event)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode_all() {
+        let key_events = [
+            KeyEvent {
+                code: KeyCode::LShift,
+                state: KeyState::Down,
+                unicode: None,
+            },
+            KeyEvent {
+                code: KeyCode::Q,
+                state: KeyState::Down,
+                unicode: Some('Q'),
+            },
+            KeyEvent {
+                code: KeyCode::Oem4,
+                state: KeyState::Up,
+                unicode: Some('é'),
+            },
+        ];
+        let mut buffer = [0; 3 * KeyEvent::POSTCARD_MAX_SIZE];
+        let mut iter = key_events.into_iter();
+        let len = KeyEvent::encode_all(&mut buffer, || iter.next());
+        assert!(KeyEvent::decode_all(&buffer[..len]).eq(key_events));
+    }
+
+    #[test]
+    fn encode_all_only_takes_what_fits() {
+        let key_event = KeyEvent {
+            code: KeyCode::A,
+            state: KeyState::Down,
+            unicode: Some('a'),
+        };
+        // Room for one key event, but not for a second one after it
+        let mut buffer = [0; KeyEvent::POSTCARD_MAX_SIZE + 1];
+        let mut taken = 0;
+        let len = KeyEvent::encode_all(&mut buffer, || {
+            taken += 1;
+            Some(key_event)
+        });
+        assert_eq!(taken, 1);
+        assert_eq!(KeyEvent::decode_all(&buffer[..len]).count(), 1);
+    }
+}
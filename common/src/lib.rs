@@ -1,10 +1,30 @@
 #![cfg_attr(not(test), no_std)]
+extern crate alloc;
 
+pub mod channel_message;
+pub mod event;
+pub mod executor;
+pub mod handle;
+pub mod key_event;
 pub mod mem;
+pub mod mouse;
+pub mod ramdisk;
+pub mod signal;
 pub mod syscall;
+pub mod syscall_allocate_pages;
+pub mod syscall_channel;
+pub mod syscall_configure_keyboard;
+pub mod syscall_handle;
 pub mod syscall_output;
 pub mod syscall_pointer;
 pub mod syscall_print;
+pub mod syscall_real_time;
+pub mod syscall_set_event_handler;
+pub mod syscall_shared_memory;
+pub mod syscall_signal;
 pub mod syscall_slice;
 pub mod syscall_start_recording_keyboard;
+pub mod syscall_start_recording_mouse;
 pub mod syscall_take_frame_buffer;
+pub mod syscall_timer;
+pub mod time_page;
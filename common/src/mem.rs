@@ -2,3 +2,5 @@
 pub const KERNEL_VIRT_MEM_START: u64 = 0xFFFF_8000_0000_0000;
 /// This will be used by memory mapped io like the frame buffer which doesn't need its own phys frames but needs space in the virt address space
 pub const USER_SPACE_MMIO_START: u64 = KERNEL_VIRT_MEM_START - 0x40000000;
+/// A read only page that the kernel keeps updated with the time, so that user space can get the time without a syscall
+pub const USER_SPACE_TIME_PAGE: u64 = USER_SPACE_MMIO_START - 0x1000;
@@ -0,0 +1,206 @@
+use core::ops::BitOr;
+
+/// Which mouse buttons are held down
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MouseButtons(pub u8);
+
+impl MouseButtons {
+    pub const NONE: Self = Self(0);
+    pub const LEFT: Self = Self(1 << 0);
+    pub const RIGHT: Self = Self(1 << 1);
+    pub const MIDDLE: Self = Self(1 << 2);
+    /// Only on mice with 5 buttons
+    pub const BACK: Self = Self(1 << 3);
+    /// Only on mice with 5 buttons
+    pub const FORWARD: Self = Self(1 << 4);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for MouseButtons {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// A decoded PS/2 mouse packet. User space gets a slice of these, so it is `repr(C)`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MouseEvent {
+    pub buttons: MouseButtons,
+    /// Positive is scrolling down. Always 0 on mice without a wheel.
+    pub wheel: i8,
+    /// Positive is right
+    pub dx: i16,
+    /// Positive is up
+    pub dy: i16,
+}
+
+/// What the mouse replies with when asked for its id. It decides the packet format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseId {
+    /// 3 byte packets
+    Standard,
+    /// 4 byte packets with a scroll wheel
+    Wheel,
+    /// 4 byte packets with a scroll wheel and 2 extra buttons
+    FiveButtons,
+}
+
+impl MouseId {
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::Standard),
+            3 => Some(Self::Wheel),
+            4 => Some(Self::FiveButtons),
+            _ => None,
+        }
+    }
+
+    pub fn packet_len(self) -> usize {
+        match self {
+            Self::Standard => 3,
+            Self::Wheel | Self::FiveButtons => 4,
+        }
+    }
+}
+
+/// Turns the bytes that the mouse sends into [`MouseEvent`]s.
+/// See https://wiki.osdev.org/PS/2_Mouse
+#[derive(Debug, Clone)]
+pub struct MousePacketDecoder {
+    id: MouseId,
+    bytes: [u8; 4],
+    len: usize,
+}
+
+impl MousePacketDecoder {
+    const ALWAYS_ONE: u8 = 1 << 3;
+    const X_SIGN: u8 = 1 << 4;
+    const Y_SIGN: u8 = 1 << 5;
+    const X_OVERFLOW: u8 = 1 << 6;
+    const Y_OVERFLOW: u8 = 1 << 7;
+
+    pub const fn new(id: MouseId) -> Self {
+        Self {
+            id,
+            bytes: [0; 4],
+            len: 0,
+        }
+    }
+
+    /// Returns an event when the last byte of a packet is added
+    pub fn add_byte(&mut self, byte: u8) -> Option<MouseEvent> {
+        if self.len == 0 && byte & Self::ALWAYS_ONE == 0 {
+            // We are not at the start of a packet. Skip bytes until we are back in sync.
+            return None;
+        }
+        self.bytes[self.len] = byte;
+        self.len += 1;
+        if self.len < self.id.packet_len() {
+            return None;
+        }
+        self.len = 0;
+        let [flags, x, y, extra] = self.bytes;
+        let delta = |value: u8, sign: u8, overflow: u8| -> i16 {
+            if flags & overflow != 0 {
+                // The movement is too big to be represented, so just ignore it
+                0
+            } else if flags & sign != 0 {
+                value as i16 - 0x100
+            } else {
+                value as i16
+            }
+        };
+        let mut buttons = MouseButtons(flags & 0b111);
+        let wheel = match self.id {
+            MouseId::Standard => 0,
+            MouseId::Wheel | MouseId::FiveButtons => {
+                // Sign extend the lower 4 bits
+                ((extra << 4) as i8) >> 4
+            }
+        };
+        if self.id == MouseId::FiveButtons {
+            buttons = buttons | MouseButtons((extra >> 1) & 0b1_1000);
+        }
+        Some(MouseEvent {
+            buttons,
+            wheel,
+            dx: delta(x, Self::X_SIGN, Self::X_OVERFLOW),
+            dy: delta(y, Self::Y_SIGN, Self::Y_OVERFLOW),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn standard_packet() {
+        let mut decoder = MousePacketDecoder::new(MouseId::Standard);
+        // Left button, moving left (negative x) and up
+        assert_eq!(decoder.add_byte(0b0001_1001), None);
+        assert_eq!(decoder.add_byte(0xFE), None);
+        assert_eq!(
+            decoder.add_byte(5),
+            Some(MouseEvent {
+                buttons: MouseButtons::LEFT,
+                wheel: 0,
+                dx: -2,
+                dy: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn wheel_and_extra_buttons() {
+        let mut decoder = MousePacketDecoder::new(MouseId::FiveButtons);
+        for byte in [0b0000_1010, 0, 0] {
+            assert_eq!(decoder.add_byte(byte), None);
+        }
+        assert_eq!(
+            decoder.add_byte(0b0001_1111),
+            Some(MouseEvent {
+                buttons: MouseButtons::RIGHT | MouseButtons::BACK,
+                wheel: -1,
+                dx: 0,
+                dy: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn resyncs() {
+        let mut decoder = MousePacketDecoder::new(MouseId::Standard);
+        // Doesn't have the always one bit, so it can't be the first byte
+        assert_eq!(decoder.add_byte(0), None);
+        assert_eq!(decoder.add_byte(0b0000_1100), None);
+        assert_eq!(decoder.add_byte(1), None);
+        assert_eq!(
+            decoder.add_byte(0),
+            Some(MouseEvent {
+                buttons: MouseButtons::MIDDLE,
+                wheel: 0,
+                dx: 1,
+                dy: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn overflow_is_ignored() {
+        let mut decoder = MousePacketDecoder::new(MouseId::Standard);
+        decoder.add_byte(0b0100_1000);
+        decoder.add_byte(0xFF);
+        assert_eq!(
+            decoder.add_byte(3).map(|event| (event.dx, event.dy)),
+            Some((0, 3))
+        );
+    }
+}
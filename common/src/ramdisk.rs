@@ -0,0 +1,269 @@
+//! The ramdisk holds the user programs and data files of an image. The build script writes it and
+//! the kernel reads it.
+//!
+//! All numbers are little endian `u32`s. The ramdisk starts with [`MAGIC`], the number of files and
+//! the index of the init program. Then, for each file, there is the offset and length of its name,
+//! its [`FileKind`], and the offset and length of its contents. The names and contents come after
+//! that, with contents aligned to [`CONTENTS_ALIGN`] bytes.
+
+pub const MAGIC: [u8; 8] = *b"CRRAMDSK";
+pub const CONTENTS_ALIGN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + 2 * size_of::<u32>();
+const ENTRY_LEN: usize = 5 * size_of::<u32>();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum FileKind {
+    /// An ELF that can be run in user space
+    Program = 0,
+    Data = 1,
+}
+
+impl FileKind {
+    fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Program),
+            1 => Some(Self::Data),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RamdiskFile<'a> {
+    pub name: &'a str,
+    pub kind: FileKind,
+    pub contents: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamdiskError {
+    BadMagic,
+    OutOfBounds,
+    InvalidName,
+    InvalidKind,
+    /// The init program doesn't exist or isn't a [`FileKind::Program`]
+    InvalidInit,
+    BufferTooSmall,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ramdisk<'a> {
+    bytes: &'a [u8],
+    file_count: usize,
+    init: usize,
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<usize, RamdiskError> {
+    let bytes = bytes
+        .get(offset..offset + size_of::<u32>())
+        .ok_or(RamdiskError::OutOfBounds)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+fn write_u32(buffer: &mut [u8], offset: usize, value: usize) {
+    buffer[offset..offset + size_of::<u32>()].copy_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn read_slice(bytes: &[u8], offset: usize, len: usize) -> Result<&[u8], RamdiskError> {
+    bytes
+        .get(offset..offset.checked_add(len).ok_or(RamdiskError::OutOfBounds)?)
+        .ok_or(RamdiskError::OutOfBounds)
+}
+
+impl<'a> Ramdisk<'a> {
+    /// Checks the whole ramdisk, so that reading files afterwards can't fail
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, RamdiskError> {
+        if !bytes.starts_with(&MAGIC) {
+            return Err(RamdiskError::BadMagic);
+        }
+        let file_count = read_u32(bytes, MAGIC.len())?;
+        let init = read_u32(bytes, MAGIC.len() + size_of::<u32>())?;
+        if file_count > (bytes.len() - HEADER_LEN) / ENTRY_LEN {
+            return Err(RamdiskError::OutOfBounds);
+        }
+        let ramdisk = Self {
+            bytes,
+            file_count,
+            init,
+        };
+        for index in 0..file_count {
+            ramdisk.read_file(index)?;
+        }
+        match ramdisk.read_file(init) {
+            Ok(RamdiskFile {
+                kind: FileKind::Program,
+                ..
+            }) => Ok(ramdisk),
+            _ => Err(RamdiskError::InvalidInit),
+        }
+    }
+
+    fn read_file(&self, index: usize) -> Result<RamdiskFile<'a>, RamdiskError> {
+        if index >= self.file_count {
+            return Err(RamdiskError::OutOfBounds);
+        }
+        let entry = HEADER_LEN + index * ENTRY_LEN;
+        let field = |i: usize| read_u32(self.bytes, entry + i * size_of::<u32>());
+        let name = read_slice(self.bytes, field(0)?, field(1)?)?;
+        Ok(RamdiskFile {
+            name: core::str::from_utf8(name).map_err(|_| RamdiskError::InvalidName)?,
+            kind: FileKind::from_u32(field(2)? as u32).ok_or(RamdiskError::InvalidKind)?,
+            contents: read_slice(self.bytes, field(3)?, field(4)?)?,
+        })
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.file_count
+    }
+
+    pub fn file(&self, index: usize) -> Option<RamdiskFile<'a>> {
+        self.read_file(index).ok()
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = RamdiskFile<'a>> + use<'a> {
+        let ramdisk = *self;
+        (0..self.file_count).map(move |index| ramdisk.read_file(index).unwrap())
+    }
+
+    pub fn get(&self, name: &str) -> Option<RamdiskFile<'a>> {
+        self.files().find(|file| file.name == name)
+    }
+
+    /// The program that the kernel runs first
+    pub fn init(&self) -> RamdiskFile<'a> {
+        self.read_file(self.init).unwrap()
+    }
+}
+
+/// Calls `f` with the name offset and contents offset of each file, and returns the total length
+fn layout(files: &[RamdiskFile], mut f: impl FnMut(usize, usize, usize)) -> usize {
+    let mut offset = HEADER_LEN + files.len() * ENTRY_LEN;
+    for (index, file) in files.iter().enumerate() {
+        let name_offset = offset;
+        let contents_offset = (name_offset + file.name.len()).next_multiple_of(CONTENTS_ALIGN);
+        f(index, name_offset, contents_offset);
+        offset = contents_offset + file.contents.len();
+    }
+    offset
+}
+
+/// The number of bytes needed to write a ramdisk with these files
+pub fn ramdisk_len(files: &[RamdiskFile]) -> usize {
+    layout(files, |_, _, _| {})
+}
+
+/// Writes a ramdisk to the start of `buffer`, which must be at least [`ramdisk_len`] bytes.
+/// `init` is the index of the init program in `files`.
+pub fn write_ramdisk(
+    files: &[RamdiskFile],
+    init: usize,
+    buffer: &mut [u8],
+) -> Result<(), RamdiskError> {
+    match files.get(init) {
+        Some(RamdiskFile {
+            kind: FileKind::Program,
+            ..
+        }) => {}
+        _ => return Err(RamdiskError::InvalidInit),
+    }
+    let buffer = buffer
+        .get_mut(..ramdisk_len(files))
+        .ok_or(RamdiskError::BufferTooSmall)?;
+    buffer.fill(0);
+    buffer[..MAGIC.len()].copy_from_slice(&MAGIC);
+    write_u32(buffer, MAGIC.len(), files.len());
+    write_u32(buffer, MAGIC.len() + size_of::<u32>(), init);
+    layout(files, |index, name_offset, contents_offset| {
+        let file = &files[index];
+        let entry = HEADER_LEN + index * ENTRY_LEN;
+        for (i, value) in [
+            name_offset,
+            file.name.len(),
+            file.kind as usize,
+            contents_offset,
+            file.contents.len(),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            write_u32(buffer, entry + i * size_of::<u32>(), value);
+        }
+        buffer[name_offset..name_offset + file.name.len()].copy_from_slice(file.name.as_bytes());
+        buffer[contents_offset..contents_offset + file.contents.len()]
+            .copy_from_slice(file.contents);
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        CONTENTS_ALIGN, FileKind, Ramdisk, RamdiskError, RamdiskFile, ramdisk_len, write_ramdisk,
+    };
+
+    const FILES: [RamdiskFile; 2] = [
+        RamdiskFile {
+            name: "logo.tga",
+            kind: FileKind::Data,
+            contents: &[1, 2, 3],
+        },
+        RamdiskFile {
+            name: "maze_game",
+            kind: FileKind::Program,
+            contents: b"\x7fELF",
+        },
+    ];
+
+    fn write(files: &[RamdiskFile], init: usize) -> Vec<u8> {
+        let mut buffer = vec![0; ramdisk_len(files)];
+        write_ramdisk(files, init, &mut buffer).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = write(&FILES, 1);
+        let ramdisk = Ramdisk::parse(&bytes).unwrap();
+        assert_eq!(ramdisk.file_count(), 2);
+        assert_eq!(ramdisk.files().collect::<Vec<_>>(), FILES);
+        assert_eq!(ramdisk.init(), FILES[1]);
+        assert_eq!(ramdisk.get("logo.tga"), Some(FILES[0]));
+        assert_eq!(ramdisk.get("missing"), None);
+        for file in ramdisk.files() {
+            let offset = file.contents.as_ptr() as usize - bytes.as_ptr() as usize;
+            assert_eq!(offset % CONTENTS_ALIGN, 0);
+        }
+    }
+
+    #[test]
+    fn init_must_be_program() {
+        let mut buffer = vec![0; ramdisk_len(&FILES)];
+        assert_eq!(
+            write_ramdisk(&FILES, 0, &mut buffer),
+            Err(RamdiskError::InvalidInit)
+        );
+        assert_eq!(
+            write_ramdisk(&FILES, 2, &mut buffer),
+            Err(RamdiskError::InvalidInit)
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let bytes = write(&FILES, 1);
+        assert_eq!(
+            Ramdisk::parse(&bytes[1..]).unwrap_err(),
+            RamdiskError::BadMagic
+        );
+        assert_eq!(
+            Ramdisk::parse(&bytes[..bytes.len() - 1]).unwrap_err(),
+            RamdiskError::OutOfBounds
+        );
+        let mut buffer = [0; 4];
+        assert_eq!(
+            write_ramdisk(&FILES, 1, &mut buffer),
+            Err(RamdiskError::BufferTooSmall)
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+/// Sorted from highest to lowest priority. A signal handler can only be interrupted by handlers of signals with a higher priority.
+/// Signals have a higher priority than events. If a process doesn't have a handler for a signal, the process is terminated.
+#[derive(
+    Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum Signal {
+    /// The process accessed memory that it's not allowed to access. Returning from the handler retries the instruction, so the handler should fix the problem or exit.
+    Segfault,
+    /// Sent by the `kill` command of the kernel's debug monitor
+    Terminate,
+    /// Sent when Ctrl+C is pressed on the keyboard or received over serial
+    Interrupt,
+    /// Sent when the time set with the `SetAlarm` syscall passes
+    Alarm,
+    User1,
+    User2,
+}
+
+impl Signal {
+    pub const COUNT: usize = 6;
+    /// Sorted from highest to lowest priority
+    pub const ALL: [Self; Self::COUNT] = [
+        Self::Segfault,
+        Self::Terminate,
+        Self::Interrupt,
+        Self::Alarm,
+        Self::User1,
+        Self::User2,
+    ];
+
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The same numbers as POSIX signals. Signal handlers get this number as their input.
+    pub const fn number(self) -> u8 {
+        match self {
+            Self::Interrupt => 2,
+            Self::User1 => 10,
+            Self::Segfault => 11,
+            Self::User2 => 12,
+            Self::Alarm => 14,
+            Self::Terminate => 15,
+        }
+    }
+
+    pub fn from_number(number: u64) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|signal| signal.number() as u64 == number)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn all_is_sorted_by_priority() {
+        for (index, signal) in Signal::ALL.into_iter().enumerate() {
+            assert_eq!(signal.index(), index);
+        }
+        assert!(Signal::ALL.is_sorted());
+    }
+
+    #[test]
+    fn from_number() {
+        for signal in Signal::ALL {
+            assert_eq!(Signal::from_number(signal.number().into()), Some(signal));
+        }
+        assert_eq!(Signal::from_number(0), None);
+    }
+}
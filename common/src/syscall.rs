@@ -2,25 +2,110 @@ use postcard::experimental::max_size::MaxSize;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    syscall_pointer::SyscallPointer, syscall_slice::SyscallSlice,
+    handle::Handle,
+    syscall_allocate_pages::SyscallAllocatePagesInput,
+    syscall_channel::{
+        SyscallChannelReceiveInput, SyscallChannelReplyInput, SyscallChannelSendInput,
+    },
+    syscall_configure_keyboard::SyscallConfigureKeyboardInput,
+    syscall_handle::{SyscallDuplicateHandleInput, SyscallTransferHandleInput},
+    syscall_pointer::SyscallPointer,
+    syscall_real_time::{SyscallSetRealTimeInput, SyscallStartRtcTicksInput},
+    syscall_set_event_handler::SyscallSetEventHandlerInput,
+    syscall_shared_memory::SyscallMapSharedMemoryInput,
+    syscall_signal::{SyscallSendSignalInput, SyscallSetSignalHandlerInput},
+    syscall_slice::SyscallSlice,
     syscall_start_recording_keyboard::SyscallStartRecordingKeyboardInput,
+    syscall_start_recording_mouse::SyscallStartRecordingMouseInput,
+    syscall_take_frame_buffer::SyscallTakeFrameBufferInput,
+    syscall_timer::{SyscallCreateTimerInput, TimerId},
 };
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
 pub enum Syscall {
     Print(SyscallSlice),
-    TakeFrameBuffer(SyscallPointer),
+    TakeFrameBuffer(SyscallTakeFrameBufferInput),
     Exit,
     StartRecordingKeyboard(SyscallStartRecordingKeyboardInput),
-    PollKeyboard(SyscallSlice),
+    /// Returns the number of scan codes written. Returns 0 if the keyboard handle is invalid or the keyboard isn't recording scan codes.
+    PollKeyboard(Handle, SyscallSlice),
+    /// Writes postcard encoded [`KeyEvent`](crate::key_event::KeyEvent)s back to back, and only whole events.
+    /// Returns the number of bytes written. Returns 0 if the keyboard handle is invalid or the keyboard isn't recording key events.
+    PollKeyEvents(Handle, SyscallSlice),
     /// Change the **total** number of allocated pages (the kernel increases / decreased depending on the current number and specified number)
-    AllocatePages(u64),
-    SetKeyboardInterruptHandler(Option<SyscallPointer>),
-    /// Do not return from the keyboard interrupt handler. Instead, call this syscall at the end of ur fn.
+    AllocatePages(SyscallAllocatePagesInput),
+    /// Each event source has its own handler. The handler for keyboard events is called when there are new scan codes to poll.
+    SetEventHandler(SyscallSetEventHandlerInput),
+    /// Do not return from an event handler. Instead, call this syscall at the end of ur fn.
     DoneWithInterruptHandler,
     DisableAndDeferMyInterrupts,
     EnableAndCatchUpOnMyInterrupts,
     EnableMyInterruptsAndWaitUntilOneHappens,
+    /// Creates a channel and writes handles to both of its endpoints to the `CreateChannelOutputData`
+    CreateChannel(SyscallPointer),
+    ChannelSend(SyscallChannelSendInput),
+    /// Sends a request that the other endpoint must reply to. Returns the transaction id of the call.
+    ChannelCall(SyscallChannelSendInput),
+    ChannelReply(SyscallChannelReplyInput),
+    /// Does not block. Returns `ChannelError::Empty` if there are no messages.
+    ChannelReceive(SyscallChannelReceiveInput),
+    /// Creates a new handle to the same kernel object with the same or less rights
+    DuplicateHandle(SyscallDuplicateHandleInput),
+    /// The kernel object is dropped when its last handle is closed. Closing the last handle to a channel endpoint drops the messages that were not received yet.
+    CloseHandle(Handle),
+    /// Moves a handle out of this process's handle table and sends it through a channel
+    TransferHandle(SyscallTransferHandleInput),
+    /// Takes the oldest handle sent to this channel endpoint and puts it in this process's handle table.
+    /// Does not block. Returns `ChannelError::Empty` if there are no handles.
+    ReceiveHandle(Handle),
+    /// Allocates zeroed memory that can be mapped by every process that has a handle to it. The input is the number of pages.
+    CreateSharedMemory(u64),
+    /// The memory is mapped as writable if the handle has [`Rights::WRITE`](crate::handle::Rights::WRITE)
+    MapSharedMemory(SyscallMapSharedMemoryInput),
+    /// The handler gets the signal's number as its input
+    SetSignalHandler(SyscallSetSignalHandlerInput),
+    /// If the process is sending a signal to itself, the signal handler runs before this syscall returns
+    SendSignal(SyscallSendSignalInput),
+    /// Do not return from a signal handler. Instead, call this syscall at the end of ur fn.
+    SignalReturn,
+    /// Blocks for at least the given number of nanoseconds. Events that happen while sleeping are handled after the sleep.
+    Sleep(u64),
+    /// Returns the number of nanoseconds since the kernel started. It never goes backwards.
+    GetMonotonicTime,
+    /// Every time the timer expires, a [`EventSource::Timer`](crate::event::EventSource::Timer) event happens
+    CreateTimer(SyscallCreateTimerInput),
+    /// Does not block. Returns the number of times the timer expired since the last poll.
+    PollTimer(TimerId),
+    /// Deletes the timer. Its id can be reused by new timers.
+    CancelTimer(TimerId),
+    /// Returns the number of nanoseconds since the Unix epoch
+    GetRealTime,
+    /// Changes the real time of all processes and writes it to the CMOS RTC, which only stores whole seconds
+    SetRealTime(SyscallSetRealTimeInput),
+    /// Every RTC tick is a [`EventSource::Rtc`](crate::event::EventSource::Rtc) event. Calling this again changes the rate.
+    StartRtcTicks(SyscallStartRtcTicksInput),
+    StopRtcTicks(Handle),
+    /// Does not block. Returns the number of ticks since the last poll.
+    PollRtcTicks(Handle),
+    /// Makes the mouse queue [`MouseEvent`](crate::mouse::MouseEvent)s, and every event is a [`EventSource::Mouse`](crate::event::EventSource::Mouse) event
+    StartRecordingMouse(SyscallStartRecordingMouseInput),
+    /// Drops the queued mouse events
+    StopRecordingMouse(Handle),
+    /// The slice is of [`MouseEvent`](crate::mouse::MouseEvent)s. Returns the number of events written. Returns 0 if the mouse handle is invalid.
+    PollMouse(Handle, SyscallSlice),
+    /// Stops the keyboard interrupt, drops the queued scan codes or key events, and removes the [`EventSource::Keyboard`](crate::event::EventSource::Keyboard) handler.
+    /// After this, another process can start recording the keyboard.
+    StopRecordingKeyboard(Handle),
+    /// Sends a command to the keyboard and waits for it to be acknowledged
+    ConfigureKeyboard(SyscallConfigureKeyboardInput),
+    /// Needs [`Rights::READ`](crate::handle::Rights::READ). The kernel keeps track of the lock keys even if the keyboard isn't being recorded.
+    GetKeyboardLocks(Handle),
+    /// Needs [`Rights::READ`](crate::handle::Rights::READ). Does not block. The slice is filled with bytes received on the serial port.
+    /// Returns the number of bytes written. Returns 0 if the serial handle is invalid.
+    /// Received bytes are buffered even if no one is reading them, and every time bytes are received is a [`EventSource::Serial`](crate::event::EventSource::Serial) event.
+    ReadSerial(Handle, SyscallSlice),
+    /// Sends [`Signal::Alarm`](crate::signal::Signal::Alarm) once after at least the given number of nanoseconds. Replaces the previous alarm. 0 cancels the alarm.
+    SetAlarm(u64),
 }
 
 impl Syscall {
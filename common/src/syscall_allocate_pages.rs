@@ -0,0 +1,45 @@
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+use crate::{syscall_output::SyscallOutput, syscall_pointer::SyscallPointer};
+
+/// The heap can grow up to this many pages. The kernel doesn't map anything else in the heap's
+/// address range, so that growing the heap can't run into other mappings.
+pub const MAX_HEAP_PAGES: u64 = 0x40000;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallAllocatePagesInput {
+    /// The **total** number of pages that the heap should have
+    pub total_pages: u64,
+    /// Points to a `u64` that the start of the heap is written to
+    pub heap_start: SyscallPointer,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub enum AllocatePagesError {
+    PointerIsNull,
+    PointerNotAligned,
+    /// The user space program is not allowed to access the pointer it provided
+    PointerNotAllowed,
+    /// More than [`MAX_HEAP_PAGES`] pages were requested
+    TooBig,
+    OutOfMemory,
+    /// Something is already mapped where the heap would grow
+    AlreadyMapped,
+}
+
+/// If allocating fails, the heap keeps the pages that it had before
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallAllocatePagesOutput(pub Result<(), AllocatePagesError>);
+
+impl SyscallOutput for SyscallAllocatePagesOutput {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn output_fits_in_output() {
+        assert!(SyscallAllocatePagesOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+    }
+}
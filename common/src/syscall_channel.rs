@@ -0,0 +1,120 @@
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    handle::{Handle, HandleError},
+    syscall_output::SyscallOutput,
+    syscall_pointer::SyscallPointer,
+    syscall_slice::SyscallSlice,
+};
+
+/// The kernel copies every message into its own memory, so messages can't be arbitrarily big
+pub const MAX_MESSAGE_LEN: usize = 0x1000;
+
+/// Used to match a reply to the call that it is replying to
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TransactionId(pub u32);
+
+/// Not `Copy` or `Clone` because each handle should only be owned once
+#[derive(Debug)]
+pub struct CreateChannelOutputData {
+    /// Handles to both endpoints of the channel. Messages sent on one endpoint are received on the other endpoint.
+    pub endpoints: [Handle; 2],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageKind {
+    /// Sent with `ChannelSend`. Nobody is waiting for a reply.
+    Message,
+    /// Sent with `ChannelCall`. Reply to it with `ChannelReply`.
+    Request(TransactionId),
+    /// The reply to a `ChannelCall` that was made on this endpoint
+    Reply(TransactionId),
+}
+
+/// Written by the kernel after receiving a message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReceivedMessage {
+    /// The number of bytes that were written to the buffer
+    pub len: u32,
+    pub kind: MessageKind,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub enum ChannelError {
+    Handle(HandleError),
+    PointerIsNull,
+    PointerNotAligned,
+    /// The user space program is not allowed to access the pointer it provided
+    PointerNotAllowed,
+    /// The endpoint does not exist or was closed
+    InvalidEndpoint,
+    /// The other endpoint was closed, so nothing can be sent anymore
+    PeerClosed,
+    /// There are no messages (or handles) to receive right now
+    Empty,
+    /// The message is longer than [`MAX_MESSAGE_LEN`]
+    MessageTooBig,
+    /// The receiver's queue is full. Try again after the receiver receives some messages (or handles).
+    QueueFull,
+    /// The message was not received because it doesn't fit in the buffer
+    BufferTooSmall {
+        needed: u32,
+    },
+    /// There is no call with this transaction id waiting for a reply
+    InvalidTransaction,
+}
+
+impl From<HandleError> for ChannelError {
+    fn from(value: HandleError) -> Self {
+        Self::Handle(value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallChannelSendInput {
+    pub endpoint: Handle,
+    pub message: SyscallSlice,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallChannelReplyInput {
+    pub endpoint: Handle,
+    pub transaction: TransactionId,
+    pub message: SyscallSlice,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallChannelReceiveInput {
+    pub endpoint: Handle,
+    pub buffer: SyscallSlice,
+    /// Points to a [`ReceivedMessage`]
+    pub output: SyscallPointer,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallChannelOutput(pub Result<(), ChannelError>);
+
+impl SyscallOutput for SyscallChannelOutput {}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallChannelCallOutput(pub Result<TransactionId, ChannelError>);
+
+impl SyscallOutput for SyscallChannelCallOutput {}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallChannelReceiveHandleOutput(pub Result<Handle, ChannelError>);
+
+impl SyscallOutput for SyscallChannelReceiveHandleOutput {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn outputs_fit_in_output() {
+        assert!(SyscallChannelOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+        assert!(SyscallChannelCallOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+        assert!(SyscallChannelReceiveHandleOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+    }
+}
@@ -0,0 +1,137 @@
+use core::ops::BitOr;
+
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    handle::{Handle, HandleError},
+    syscall_output::SyscallOutput,
+};
+
+/// Which lock keys are on. The bits match the ones that the keyboard uses for its LEDs.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct KeyboardLocks(pub u8);
+
+impl KeyboardLocks {
+    pub const NONE: Self = Self(0);
+    pub const SCROLL_LOCK: Self = Self(1 << 0);
+    pub const NUM_LOCK: Self = Self(1 << 1);
+    pub const CAPS_LOCK: Self = Self(1 << 2);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for KeyboardLocks {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// How long a key has to be held before it starts repeating
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub enum TypematicDelay {
+    Millis250,
+    Millis500,
+    Millis750,
+    Millis1000,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct Typematic {
+    /// From 0 (30 repeats per second) to 31 (2 repeats per second)
+    pub repeat_rate: u8,
+    pub delay: TypematicDelay,
+}
+
+impl Typematic {
+    pub const MAX_REPEAT_RATE: u8 = 0b1_1111;
+
+    /// The byte that is sent to the keyboard after the "set typematic rate and delay" command.
+    /// Returns [`None`] if the repeat rate is too big.
+    pub fn to_byte(self) -> Option<u8> {
+        if self.repeat_rate <= Self::MAX_REPEAT_RATE {
+            Some(((self.delay as u8) << 5) | self.repeat_rate)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub enum ScanCodeSet {
+    Set1,
+    Set2,
+    Set3,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub enum KeyboardCommand {
+    /// The kernel doesn't change the LEDs by itself, so a program can use this to show the lock state
+    SetLeds(KeyboardLocks),
+    SetTypematic(Typematic),
+    /// The PS/2 controller usually translates scan codes to Set 1, which is what `PollKeyboard` and `PollKeyEvents` expect.
+    /// Other sets only work if the controller doesn't translate them.
+    SetScanCodeSet(ScanCodeSet),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallConfigureKeyboardInput {
+    /// Needs [`Rights::WRITE`](crate::handle::Rights::WRITE)
+    pub keyboard: Handle,
+    pub command: KeyboardCommand,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub enum ConfigureKeyboardError {
+    Handle(HandleError),
+    InvalidRepeatRate,
+    /// The keyboard didn't respond
+    Timeout,
+    /// The keyboard replied with something other than an acknowledgement
+    NotAcknowledged(u8),
+}
+
+impl From<HandleError> for ConfigureKeyboardError {
+    fn from(value: HandleError) -> Self {
+        Self::Handle(value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallConfigureKeyboardOutput(pub Result<(), ConfigureKeyboardError>);
+
+impl SyscallOutput for SyscallConfigureKeyboardOutput {}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallKeyboardLocksOutput(pub Result<KeyboardLocks, HandleError>);
+
+impl SyscallOutput for SyscallKeyboardLocksOutput {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn outputs_fit_in_output() {
+        assert!(SyscallConfigureKeyboardOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+        assert!(SyscallKeyboardLocksOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+    }
+
+    #[test]
+    fn typematic_byte() {
+        let typematic = Typematic {
+            repeat_rate: 0b1_0100,
+            delay: TypematicDelay::Millis750,
+        };
+        assert_eq!(typematic.to_byte(), Some(0b0101_0100));
+        let typematic = Typematic {
+            repeat_rate: Typematic::MAX_REPEAT_RATE + 1,
+            delay: TypematicDelay::Millis250,
+        };
+        assert_eq!(typematic.to_byte(), None);
+    }
+}
@@ -0,0 +1,43 @@
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    handle::{Handle, HandleError, Rights},
+    syscall_output::SyscallOutput,
+};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallDuplicateHandleInput {
+    pub handle: Handle,
+    /// Must be a subset of the original handle's rights
+    pub rights: Rights,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallTransferHandleInput {
+    /// The channel endpoint to send the handle through
+    pub channel: Handle,
+    /// Removed from this process's handle table
+    pub handle: Handle,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallHandleOutput(pub Result<(), HandleError>);
+
+impl SyscallOutput for SyscallHandleOutput {}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallNewHandleOutput(pub Result<Handle, HandleError>);
+
+impl SyscallOutput for SyscallNewHandleOutput {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn outputs_fit_in_output() {
+        assert!(SyscallHandleOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+        assert!(SyscallNewHandleOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+    }
+}
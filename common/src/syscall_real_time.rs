@@ -0,0 +1,63 @@
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    handle::{Handle, HandleError},
+    syscall_output::SyscallOutput,
+};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallSetRealTimeInput {
+    /// Needs [`Rights::WRITE`](crate::handle::Rights::WRITE)
+    pub clock: Handle,
+    /// Nanoseconds since the Unix epoch
+    pub unix_nanos: u64,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallSetRealTimeOutput(pub Result<(), HandleError>);
+
+impl SyscallOutput for SyscallSetRealTimeOutput {}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallStartRtcTicksInput {
+    /// Needs [`Rights::READ`](crate::handle::Rights::READ)
+    pub clock: Handle,
+    /// The RTC ticks at `32768 >> (divider_value - 1)` Hz. Must be between 3 (8192 Hz) and 15 (2 Hz).
+    pub divider_value: u8,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub enum RtcTicksError {
+    Handle(HandleError),
+    InvalidDividerValue,
+}
+
+impl From<HandleError> for RtcTicksError {
+    fn from(value: HandleError) -> Self {
+        Self::Handle(value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallRtcTicksOutput(pub Result<(), RtcTicksError>);
+
+impl SyscallOutput for SyscallRtcTicksOutput {}
+
+/// The number of ticks since the last poll
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallPollRtcTicksOutput(pub Result<u32, HandleError>);
+
+impl SyscallOutput for SyscallPollRtcTicksOutput {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn outputs_fit_in_output() {
+        assert!(SyscallSetRealTimeOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+        assert!(SyscallRtcTicksOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+        assert!(SyscallPollRtcTicksOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+    }
+}
@@ -0,0 +1,11 @@
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+use crate::{event::EventSource, syscall_pointer::SyscallPointer};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallSetEventHandlerInput {
+    pub source: EventSource,
+    /// `None` removes the handler. Events that happen while there is no handler are dropped.
+    pub handler: Option<SyscallPointer>,
+}
@@ -0,0 +1,62 @@
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    handle::{Handle, HandleError},
+    syscall_output::SyscallOutput,
+    syscall_pointer::SyscallPointer,
+};
+
+/// Shared memory is only meant for big buffers (like images), but the kernel needs to make sure it doesn't run out of frames
+pub const MAX_SHARED_MEMORY_PAGES: u64 = 0x400;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub enum SharedMemoryError {
+    Handle(HandleError),
+    PointerIsNull,
+    /// The address must be page aligned
+    PointerNotAligned,
+    /// The user space program is not allowed to access the pointer it provided
+    PointerNotAllowed,
+    /// Something is already mapped in the address range
+    AlreadyMapped,
+    /// More than [`MAX_SHARED_MEMORY_PAGES`] pages were requested
+    TooBig,
+    OutOfMemory,
+    /// The address range overlaps where the heap grows or the MMIO region, which the kernel maps pages in later
+    Reserved,
+}
+
+impl From<HandleError> for SharedMemoryError {
+    fn from(value: HandleError) -> Self {
+        Self::Handle(value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallMapSharedMemoryInput {
+    pub shared_memory: Handle,
+    /// Where to map the shared memory in this process's address space. Must be page aligned.
+    pub address: SyscallPointer,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallCreateSharedMemoryOutput(pub Result<Handle, SharedMemoryError>);
+
+impl SyscallOutput for SyscallCreateSharedMemoryOutput {}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallMapSharedMemoryOutput(pub Result<(), SharedMemoryError>);
+
+impl SyscallOutput for SyscallMapSharedMemoryOutput {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn outputs_fit_in_output() {
+        assert!(SyscallCreateSharedMemoryOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+        assert!(SyscallMapSharedMemoryOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+    }
+}
@@ -0,0 +1,28 @@
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    handle::{Handle, HandleError},
+    signal::Signal,
+    syscall_output::SyscallOutput,
+    syscall_pointer::SyscallPointer,
+};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallSetSignalHandlerInput {
+    pub signal: Signal,
+    /// `None` removes the handler, which means that the signal terminates the process
+    pub handler: Option<SyscallPointer>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallSendSignalInput {
+    /// Needs [`Rights::SIGNAL`](crate::handle::Rights::SIGNAL)
+    pub process: Handle,
+    pub signal: Signal,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallSendSignalOutput(pub Result<(), HandleError>);
+
+impl SyscallOutput for SyscallSendSignalOutput {}
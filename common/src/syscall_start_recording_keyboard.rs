@@ -1,14 +1,27 @@
 use postcard::experimental::max_size::MaxSize;
 use serde::{Deserialize, Serialize};
 
+use crate::{handle::Handle, key_event::KeyboardLayout};
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
 pub enum FullQueueBehavior {
     DropOldest,
     DropNewest,
 }
 
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub enum KeyboardMode {
+    /// Queue raw Set 1 scan codes, which are polled with `PollKeyboard`
+    ScanCodes,
+    /// The kernel decodes the scan codes into [`KeyEvent`](crate::key_event::KeyEvent)s, which are polled with `PollKeyEvents`
+    KeyEvents(KeyboardLayout),
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
 pub struct SyscallStartRecordingKeyboardInput {
+    /// Needs [`Rights::READ`](crate::handle::Rights::READ)
+    pub keyboard: Handle,
     pub queue_size: u64,
     pub behavior_on_full_queue: FullQueueBehavior,
+    pub mode: KeyboardMode,
 }
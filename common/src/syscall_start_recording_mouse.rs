@@ -0,0 +1,13 @@
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+use crate::{handle::Handle, syscall_start_recording_keyboard::FullQueueBehavior};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallStartRecordingMouseInput {
+    /// Needs [`Rights::READ`](crate::handle::Rights::READ)
+    pub mouse: Handle,
+    /// The max number of [`MouseEvent`](crate::mouse::MouseEvent)s that are queued
+    pub queue_size: u64,
+    pub behavior_on_full_queue: FullQueueBehavior,
+}
@@ -4,7 +4,11 @@ use bootloader_api::info::FrameBufferInfo;
 use postcard::experimental::max_size::MaxSize;
 use serde::{Deserialize, Serialize};
 
-use crate::syscall_output::SyscallOutput;
+use crate::{
+    handle::{Handle, HandleError},
+    syscall_output::SyscallOutput,
+    syscall_pointer::SyscallPointer,
+};
 
 /// Not `Copy` or `Clone` becuase it would allow multiple mutable references to the same memory
 #[derive(Debug)]
@@ -29,8 +33,17 @@ impl TakeFrameBufferOutputData {
     }
 }
 
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallTakeFrameBufferInput {
+    /// Needs [`Rights::MAP`](crate::handle::Rights::MAP)
+    pub frame_buffer: Handle,
+    /// Points to a [`TakeFrameBufferOutputData`]
+    pub output: SyscallPointer,
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
 pub enum TakeFrameBufferError {
+    Handle(HandleError),
     PointerIsNull,
     PointerNotAligned,
     /// The user space program is not allowed to access the pointer it provided
@@ -38,6 +51,15 @@ pub enum TakeFrameBufferError {
     NoFrameBuffer,
     /// There could be other MMIO in the same frames as the frame buffer so it would be unsecure to give access to all of the frame buffer's memory-mapped frames.
     CannotSecurelyGiveAccess,
+    /// The frame buffer was already taken
+    AlreadyMapped,
+    OutOfMemory,
+}
+
+impl From<HandleError> for TakeFrameBufferError {
+    fn from(value: HandleError) -> Self {
+        Self::Handle(value)
+    }
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
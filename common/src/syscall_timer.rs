@@ -0,0 +1,69 @@
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+use crate::syscall_output::SyscallOutput;
+
+/// The max number of timers that a process can have at the same time
+pub const MAX_TIMERS: usize = 16;
+
+pub const NANOS_PER_SECOND: u64 = 1_000_000_000;
+pub const NANOS_PER_MILLI: u64 = 1_000_000;
+
+/// Identifies a timer of the process that created it
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimerId(pub u8);
+
+impl TimerId {
+    pub const fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub enum TimerError {
+    InvalidTimer,
+    /// The process already has [`MAX_TIMERS`] timers
+    TooManyTimers,
+    PeriodIsZero,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallCreateTimerInput {
+    /// Nanoseconds until the first expiration
+    pub duration: u64,
+    /// Nanoseconds between expirations after the first one. `None` makes a one-shot timer.
+    pub period: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallCreateTimerOutput(pub Result<TimerId, TimerError>);
+
+impl SyscallOutput for SyscallCreateTimerOutput {}
+
+/// The number of times that the timer expired since it was last polled
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallPollTimerOutput(pub Result<u32, TimerError>);
+
+impl SyscallOutput for SyscallPollTimerOutput {}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+pub struct SyscallTimerOutput(pub Result<(), TimerError>);
+
+impl SyscallOutput for SyscallTimerOutput {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn outputs_fit_in_output() {
+        assert!(SyscallCreateTimerOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+        assert!(SyscallPollTimerOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+        assert!(SyscallTimerOutput::POSTCARD_MAX_SIZE <= size_of::<u64>());
+    }
+
+    #[test]
+    fn max_timers_fit_in_timer_id() {
+        assert!(MAX_TIMERS <= u8::MAX as usize + 1);
+    }
+}
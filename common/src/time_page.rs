@@ -0,0 +1,149 @@
+use core::sync::atomic::{AtomicU64, Ordering, fence};
+
+use crate::syscall_timer::NANOS_PER_MILLI;
+
+/// What the time was at a specific TSC value, and how fast the TSC counts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimeBase {
+    pub tsc: u64,
+    pub monotonic_nanos: u64,
+    /// Unix time in nanoseconds minus monotonic time
+    pub real_time_offset: u64,
+    /// A 32.32 fixed point number. 0 means that the TSC can't be used.
+    pub nanos_per_tsc: u64,
+}
+
+impl TimeBase {
+    pub fn nanos_per_tsc(tsc_per_milli: u64) -> u64 {
+        ((NANOS_PER_MILLI as u128) << 32)
+            .checked_div(tsc_per_milli as u128)
+            .map_or(0, |nanos_per_tsc| {
+                nanos_per_tsc.try_into().unwrap_or(u64::MAX)
+            })
+    }
+
+    /// Monotonic time in nanoseconds at `tsc`
+    pub fn monotonic_nanos(&self, tsc: u64) -> u64 {
+        let elapsed = (tsc.saturating_sub(self.tsc) as u128 * self.nanos_per_tsc as u128) >> 32;
+        self.monotonic_nanos
+            .saturating_add(elapsed.try_into().unwrap_or(u64::MAX))
+    }
+
+    /// Nanoseconds since the Unix epoch at `tsc`
+    pub fn real_time_nanos(&self, tsc: u64) -> u64 {
+        self.monotonic_nanos(tsc)
+            .saturating_add(self.real_time_offset)
+    }
+}
+
+/// The contents of the page at [`USER_SPACE_TIME_PAGE`](crate::mem::USER_SPACE_TIME_PAGE).
+/// The kernel is the only writer. It's a seqlock, so readers never block the kernel.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct TimePage {
+    /// Odd while the kernel is writing
+    sequence: AtomicU64,
+    tsc: AtomicU64,
+    monotonic_nanos: AtomicU64,
+    real_time_offset: AtomicU64,
+    nanos_per_tsc: AtomicU64,
+}
+
+impl TimePage {
+    pub const fn new() -> Self {
+        Self {
+            sequence: AtomicU64::new(0),
+            tsc: AtomicU64::new(0),
+            monotonic_nanos: AtomicU64::new(0),
+            real_time_offset: AtomicU64::new(0),
+            nanos_per_tsc: AtomicU64::new(0),
+        }
+    }
+
+    /// There must only be 1 writer at a time
+    pub fn write(&self, time_base: TimeBase) {
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        self.sequence
+            .store(sequence.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.tsc.store(time_base.tsc, Ordering::Relaxed);
+        self.monotonic_nanos
+            .store(time_base.monotonic_nanos, Ordering::Relaxed);
+        self.real_time_offset
+            .store(time_base.real_time_offset, Ordering::Relaxed);
+        self.nanos_per_tsc
+            .store(time_base.nanos_per_tsc, Ordering::Relaxed);
+        self.sequence
+            .store(sequence.wrapping_add(2), Ordering::Release);
+    }
+
+    /// Retries until it reads a time base that wasn't being written at the same time
+    pub fn read(&self) -> TimeBase {
+        loop {
+            let sequence = self.sequence.load(Ordering::Acquire);
+            if sequence % 2 == 1 {
+                core::hint::spin_loop();
+                continue;
+            }
+            let time_base = TimeBase {
+                tsc: self.tsc.load(Ordering::Relaxed),
+                monotonic_nanos: self.monotonic_nanos.load(Ordering::Relaxed),
+                real_time_offset: self.real_time_offset.load(Ordering::Relaxed),
+                nanos_per_tsc: self.nanos_per_tsc.load(Ordering::Relaxed),
+            };
+            fence(Ordering::Acquire);
+            if self.sequence.load(Ordering::Relaxed) == sequence {
+                break time_base;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_and_read() {
+        let time_page = TimePage::new();
+        let time_base = TimeBase {
+            tsc: 1000,
+            monotonic_nanos: 2000,
+            real_time_offset: 3000,
+            nanos_per_tsc: 4000,
+        };
+        time_page.write(time_base);
+        assert_eq!(time_page.read(), time_base);
+    }
+
+    #[test]
+    fn interpolates_with_tsc() {
+        // A 3 GHz TSC
+        let time_base = TimeBase {
+            tsc: 3_000_000,
+            monotonic_nanos: 1_000_000,
+            real_time_offset: 5,
+            nanos_per_tsc: TimeBase::nanos_per_tsc(3_000_000),
+        };
+        let monotonic_nanos = time_base.monotonic_nanos(6_000_000);
+        assert!(monotonic_nanos.abs_diff(2_000_000) <= 1);
+        assert_eq!(time_base.real_time_nanos(6_000_000), monotonic_nanos + 5);
+        // The TSC being before the base doesn't go back in time
+        assert_eq!(time_base.monotonic_nanos(0), 1_000_000);
+    }
+
+    #[test]
+    fn uncalibrated_tsc_is_ignored() {
+        assert_eq!(TimeBase::nanos_per_tsc(0), 0);
+        let time_base = TimeBase {
+            monotonic_nanos: 7,
+            ..Default::default()
+        };
+        assert_eq!(time_base.monotonic_nanos(u64::MAX), 7);
+    }
+
+    #[test]
+    fn fits_in_a_page() {
+        assert!(size_of::<TimePage>() <= 0x1000);
+    }
+}
@@ -1,6 +1,10 @@
+use core::alloc::{GlobalAlloc, Layout};
+
+use alloc::vec::Vec;
 use common::mem::KERNEL_VIRT_MEM_START;
 use linked_list_allocator::LockedHeap;
 use x86_64::{
+    instructions::interrupts::without_interrupts,
     structures::paging::{
         FrameAllocator, Mapper, OffsetPageTable, PageSize, PageTableFlags, Size4KiB,
     },
@@ -11,11 +15,43 @@ use crate::{find_used_virt_addrs::find_used_virt_addrs, virt_mem_tracker::VirtMe
 
 pub const HEAP_SIZE: usize = 100 * 1024; // 100 KiB
 
+/// Interrupt handlers and kernel threads allocate too. If the heap was locked with interrupts
+/// enabled, an interrupt handler, or the main thread after the timer switched away from a kernel
+/// thread, could wait for the lock forever.
+struct LockedHeapWithoutInterrupts(LockedHeap);
+
+unsafe impl GlobalAlloc for LockedHeapWithoutInterrupts {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        without_interrupts(|| unsafe { self.0.alloc(layout) })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        without_interrupts(|| unsafe { self.0.dealloc(ptr, layout) })
+    }
+}
+
 #[global_allocator]
-static ALLOCATOR: LockedHeap = LockedHeap::empty();
+static ALLOCATOR: LockedHeapWithoutInterrupts = LockedHeapWithoutInterrupts(LockedHeap::empty());
 
 type AllocatorPageSize = Size4KiB;
 
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    pub size: usize,
+    pub used: usize,
+    pub free: usize,
+}
+
+/// Returns [`None`] if the heap is locked, which can happen when debugging a panic that happened while allocating
+pub fn heap_stats() -> Option<HeapStats> {
+    let heap = ALLOCATOR.0.try_lock()?;
+    Some(HeapStats {
+        size: heap.size(),
+        used: heap.used(),
+        free: heap.free(),
+    })
+}
+
 #[allow(clippy::result_unit_err)]
 pub fn init_heap(
     mapper: &mut OffsetPageTable<'static>,
@@ -58,7 +94,7 @@ pub fn init_heap(
     };
 
     unsafe {
-        ALLOCATOR.lock().init(
+        ALLOCATOR.0.lock().init(
             heap_start.start_address().as_mut_ptr(),
             (page_count * Size4KiB::SIZE) as usize,
         );
@@ -66,3 +102,14 @@ pub fn init_heap(
 
     Ok(virt_mem_tracker)
 }
+
+pub fn test_heap() {
+    let before = heap_stats().unwrap();
+    assert_eq!(before.size, HEAP_SIZE);
+    let vec = (0..1000u64).collect::<Vec<_>>();
+    let during = heap_stats().unwrap();
+    assert!(during.used >= before.used + 1000 * size_of::<u64>());
+    assert_eq!(vec.iter().sum::<u64>(), 999 * 1000 / 2);
+    drop(vec);
+    assert_eq!(heap_stats().unwrap().used, before.used);
+}
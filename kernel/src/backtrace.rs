@@ -0,0 +1,75 @@
+//! Walks the chain of saved frame pointers. The kernel is built with `force-frame-pointers=yes`,
+//! so every stack frame starts with the caller's `rbp` followed by the return address.
+
+use core::{arch::asm, ops::Range};
+
+use common::mem::KERNEL_VIRT_MEM_START;
+
+/// Stop after this many frames in case the chain loops
+const MAX_FRAMES: usize = 32;
+/// A caller's frame is expected to be close above its callee's frame. Anything further away is
+/// probably not a frame pointer, and reading it could page fault.
+const MAX_FRAME_SIZE: u64 = 0x4000;
+
+/// An iterator over the return addresses on the stack, starting with the most recent call
+#[derive(Debug, Clone)]
+pub struct Backtrace {
+    rbp: u64,
+    remaining: usize,
+    /// Frame pointers outside of this are not followed
+    stack: Range<u64>,
+}
+
+impl Backtrace {
+    /// Starts at the frame of the function calling this
+    #[inline(always)]
+    pub fn here() -> Self {
+        let rbp: u64;
+        unsafe { asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags)) };
+        Self::from_rbp(rbp)
+    }
+
+    /// Starts at the kernel frame `rbp` points to, for example the `rbp` of an interrupted context
+    pub fn from_rbp(rbp: u64) -> Self {
+        Self {
+            rbp,
+            remaining: MAX_FRAMES,
+            stack: KERNEL_VIRT_MEM_START..u64::MAX,
+        }
+    }
+
+    /// Starts at the user space frame `rbp` points to. User space must be mapped.
+    pub fn user_space(rbp: u64) -> Self {
+        Self {
+            rbp,
+            remaining: MAX_FRAMES,
+            // Skip the null page
+            stack: 0x1000..KERNEL_VIRT_MEM_START,
+        }
+    }
+}
+
+impl Iterator for Backtrace {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0
+            || !self.stack.contains(&self.rbp)
+            || !self.rbp.is_multiple_of(align_of::<u64>() as u64)
+        {
+            return None;
+        }
+        self.remaining -= 1;
+        let [caller_rbp, return_address] = unsafe { (self.rbp as *const [u64; 2]).read_volatile() };
+        // The stack grows down, so the caller's frame must be above this one
+        self.rbp = if return_address != 0
+            && caller_rbp > self.rbp
+            && caller_rbp - self.rbp <= MAX_FRAME_SIZE
+        {
+            caller_rbp
+        } else {
+            0
+        };
+        Some(return_address).filter(|return_address| *return_address != 0)
+    }
+}
@@ -0,0 +1,167 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use bootloader_api::{entry_point, BootInfo};
+use common::ramdisk::Ramdisk;
+use conquer_once::noblock::OnceCell;
+use core::{ops::DerefMut, panic::PanicInfo, slice};
+use kernel::{
+    allocator, channels, executor, gdb_stub, handle_table, kernel_test, kernel_thread,
+    logger::init_logger_with_framebuffer,
+    memory,
+    modules::{
+        double_fault_handler_entry::get_double_fault_entry, gdt::Gdt, idt::IdtBuilder,
+        panicking_double_fault_handler::panicking_double_fault_handler,
+        panicking_general_protection_fault_handler::panicking_general_protection_fault_handler,
+        panicking_invalid_opcode_handler::panicking_invalid_opcode_handler,
+        panicking_page_fault_handler::panicking_page_fault_handler, syscall::jmp_to_elf,
+        tss::TssBuilder,
+    },
+    symbols,
+    test_framework::{self, Test, TestResources},
+    user_pointer, user_space_state, virt_addr_from_indexes, virt_mem_tracker, BOOTLOADER_CONFIG,
+};
+use spin::Mutex;
+use x86_64::{
+    instructions::interrupts,
+    structures::{
+        idt::{self, HandlerFunc, HandlerFuncWithErrCode, PageFaultHandlerFunc},
+        tss::TaskStateSegment,
+    },
+    VirtAddr,
+};
+
+const TESTS: &[Test] = &[
+    kernel_test!(virt_addr_from_indexes::test_virt_addr_from_indexes_4_kib),
+    kernel_test!(virt_addr_from_indexes::test_virt_addr_from_indexes_2_mib),
+    kernel_test!(virt_addr_from_indexes::test_virt_addr_from_indexes_1_gib),
+    kernel_test!(virt_mem_tracker::test_virt_mem_tracker),
+    kernel_test!(memory::test_map_allocated_frame),
+    kernel_test!(memory::test_deallocate_frame),
+    kernel_test!(allocator::test_heap),
+    kernel_test!(executor::test_executor),
+    kernel_test!(kernel_thread::test_kernel_stack),
+    kernel_test!(kernel_thread::test_scheduler),
+    kernel_test!(kernel_thread::test_spawn_and_join),
+    kernel_test!(user_space_state::test_signal_interrupts),
+    kernel_test!(symbols::test_kernel_symbols),
+    kernel_test!(gdb_stub::test_memory_ranges),
+    kernel_test!(jmp_to_elf::test_elf_flags_to_page_table_flags),
+    kernel_test!(jmp_to_elf::test_user_space_elfs),
+    kernel_test!(user_pointer::test_check_user_pointer),
+    kernel_test!(handle_table::test_handle_rights),
+    kernel_test!(channels::test_channel_messages),
+    kernel_test!(channels::test_channel_transactions),
+    kernel_test!(channels::test_channel_close),
+];
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    interrupts::disable();
+    test_framework::fail(info)
+}
+
+entry_point!(kernel_tests_main, config = &BOOTLOADER_CONFIG);
+
+struct StaticStuff {
+    tss: TaskStateSegment,
+    idt_builder: IdtBuilder,
+}
+
+static STATIC_STUFF: OnceCell<StaticStuff> = OnceCell::uninit();
+static GDT: OnceCell<Gdt> = OnceCell::uninit();
+
+/// Only initializes what the tests need, so that faults fail the test instead of resetting the computer
+fn kernel_tests_main(boot_info: &'static mut BootInfo) -> ! {
+    init_logger_with_framebuffer(None);
+    let static_stuff = STATIC_STUFF
+        .try_get_or_init(|| {
+            let mut tss = TssBuilder::default();
+            let mut idt_builder = IdtBuilder::default();
+            idt_builder
+                .set_double_fault_entry(get_double_fault_entry(
+                    &mut tss,
+                    panicking_double_fault_handler,
+                ))
+                .unwrap();
+            idt_builder
+                .set_general_protection_fault_entry({
+                    let mut entry = idt::Entry::<HandlerFuncWithErrCode>::missing();
+                    entry.set_handler_fn(panicking_general_protection_fault_handler);
+                    entry
+                })
+                .unwrap();
+            idt_builder
+                .set_page_fault_entry({
+                    let mut entry = idt::Entry::<PageFaultHandlerFunc>::missing();
+                    entry.set_handler_fn(panicking_page_fault_handler);
+                    entry
+                })
+                .unwrap();
+            idt_builder
+                .set_invalid_opcode_entry({
+                    let mut entry = idt::Entry::<HandlerFunc>::missing();
+                    entry.set_handler_fn(panicking_invalid_opcode_handler);
+                    entry
+                })
+                .unwrap();
+            kernel_thread::set_yield_interrupt(&mut idt_builder).unwrap();
+            StaticStuff {
+                tss: tss.get_tss(),
+                idt_builder,
+            }
+        })
+        .unwrap();
+    let gdt = GDT.try_get_or_init(|| Gdt::new(&static_stuff.tss)).unwrap();
+    gdt.init();
+    static_stuff.idt_builder.init();
+
+    let phys_mem_offset = VirtAddr::new(
+        *boot_info
+            .physical_memory_offset
+            .as_ref()
+            .expect("No physical memory mapped"),
+    );
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    symbols::init_kernel(
+        unsafe {
+            slice::from_raw_parts(
+                (phys_mem_offset + boot_info.kernel_addr).as_ptr(),
+                boot_info.kernel_len as usize,
+            )
+        },
+        boot_info.kernel_image_offset,
+    );
+    let mut frame_allocator =
+        unsafe { memory::BootInfoFrameAllocator::init(boot_info.memory_regions.deref_mut()) };
+    let virt_mem_tracker = allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    let ramdisk = boot_info.ramdisk_addr.as_ref().map(|ramdisk_addr| {
+        Ramdisk::parse(unsafe {
+            slice::from_raw_parts(*ramdisk_addr as *const u8, boot_info.ramdisk_len as usize)
+        })
+        .expect("invalid ramdisk")
+    });
+
+    let mapper = Arc::new(Mutex::new(mapper));
+    let frame_allocator = Arc::new(Mutex::new(frame_allocator));
+    let virt_mem_tracker = Arc::new(Mutex::new(virt_mem_tracker));
+    kernel_thread::init(
+        mapper.clone(),
+        frame_allocator.clone(),
+        virt_mem_tracker.clone(),
+    );
+
+    test_framework::run_tests(
+        TESTS,
+        TestResources {
+            mapper,
+            frame_allocator,
+            virt_mem_tracker,
+            ramdisk,
+        },
+    )
+}
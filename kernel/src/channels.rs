@@ -0,0 +1,352 @@
+use alloc::{
+    boxed::Box,
+    collections::{btree_map::BTreeMap, btree_set::BTreeSet, vec_deque::VecDeque},
+    sync::Arc,
+    vec,
+};
+use common::{
+    handle::{HandleError, Rights},
+    syscall_channel::{ChannelError, MessageKind, ReceivedMessage, TransactionId, MAX_MESSAGE_LEN},
+};
+
+use crate::{
+    handle_table::{HandleEntry, KernelObject},
+    user_pointer::UserPointerError,
+};
+
+/// The kernel heap is small, so we don't let a receiver that never receives use up all of it
+const MAX_QUEUED_MESSAGES: usize = 16;
+const MAX_QUEUED_HANDLES: usize = 16;
+
+/// One end of a channel. User space only sees handles to endpoints, never the endpoint itself.
+/// It isn't `Clone`, so that the endpoint is only closed when the last `Arc` in a handle is dropped.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChannelEndpoint(u64);
+
+impl From<UserPointerError> for ChannelError {
+    fn from(value: UserPointerError) -> Self {
+        match value {
+            UserPointerError::Null => Self::PointerIsNull,
+            UserPointerError::NotAligned => Self::PointerNotAligned,
+            UserPointerError::NotAllowed => Self::PointerNotAllowed,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Message {
+    /// Copied from the sender's memory, and copied again into the receiver's memory when it is received
+    bytes: Box<[u8]>,
+    kind: MessageKind,
+}
+
+#[derive(Debug)]
+struct Side {
+    open: bool,
+    /// Messages that were sent to this side
+    queue: VecDeque<Message>,
+    /// Handles that were transferred to this side
+    handles: VecDeque<HandleEntry>,
+    /// Calls made from this side that weren't replied to yet
+    awaiting_reply: BTreeSet<TransactionId>,
+}
+
+impl Default for Side {
+    fn default() -> Self {
+        Self {
+            open: true,
+            queue: Default::default(),
+            handles: Default::default(),
+            awaiting_reply: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Channel {
+    sides: [Side; 2],
+    next_transaction_id: u32,
+}
+
+/// All channels, including ones that only have 1 endpoint left open.
+/// Endpoint `2n` and endpoint `2n + 1` are the two ends of channel `n`.
+#[derive(Debug, Default)]
+pub struct Channels {
+    channels: BTreeMap<u64, Channel>,
+    next_channel_id: u64,
+}
+
+fn channel_id_and_side(endpoint: &ChannelEndpoint) -> (u64, usize) {
+    (endpoint.0 / 2, (endpoint.0 % 2) as usize)
+}
+
+impl Channels {
+    pub fn create(&mut self) -> [ChannelEndpoint; 2] {
+        let channel_id = self.next_channel_id;
+        self.next_channel_id += 1;
+        self.channels.insert(channel_id, Default::default());
+        [
+            ChannelEndpoint(channel_id * 2),
+            ChannelEndpoint(channel_id * 2 + 1),
+        ]
+    }
+
+    /// Returns the channel and which side of the channel the endpoint is
+    fn get_mut(
+        &mut self,
+        endpoint: &ChannelEndpoint,
+    ) -> Result<(&mut Channel, usize), ChannelError> {
+        let (channel_id, side) = channel_id_and_side(endpoint);
+        let channel = self
+            .channels
+            .get_mut(&channel_id)
+            .filter(|channel| channel.sides[side].open)
+            .ok_or(ChannelError::InvalidEndpoint)?;
+        Ok((channel, side))
+    }
+
+    /// Should be called when the last handle to the endpoint is dropped.
+    /// Handles that were transferred to the endpoint but not received are dropped too, which can close more endpoints.
+    pub fn close(&mut self, endpoint: ChannelEndpoint) -> Result<(), ChannelError> {
+        self.get_mut(&endpoint)?;
+        let mut endpoints_to_close = vec![endpoint];
+        while let Some(endpoint) = endpoints_to_close.pop() {
+            let Ok((channel, side)) = self.get_mut(&endpoint) else {
+                continue;
+            };
+            let closed_side = core::mem::replace(
+                &mut channel.sides[side],
+                Side {
+                    open: false,
+                    ..Default::default()
+                },
+            );
+            if !channel.sides[1 - side].open {
+                self.channels.remove(&channel_id_and_side(&endpoint).0);
+            }
+            endpoints_to_close.extend(
+                closed_side
+                    .handles
+                    .into_iter()
+                    .filter_map(|entry| entry.object.into_last_channel_endpoint()),
+            );
+        }
+        Ok(())
+    }
+
+    fn push(
+        channel: &mut Channel,
+        side: usize,
+        message: &[u8],
+        kind: MessageKind,
+    ) -> Result<(), ChannelError> {
+        if message.len() > MAX_MESSAGE_LEN {
+            return Err(ChannelError::MessageTooBig);
+        }
+        let peer = &mut channel.sides[1 - side];
+        if !peer.open {
+            return Err(ChannelError::PeerClosed);
+        }
+        if peer.queue.len() >= MAX_QUEUED_MESSAGES {
+            return Err(ChannelError::QueueFull);
+        }
+        peer.queue.push_back(Message {
+            bytes: message.into(),
+            kind,
+        });
+        Ok(())
+    }
+
+    pub fn send(&mut self, endpoint: &ChannelEndpoint, message: &[u8]) -> Result<(), ChannelError> {
+        let (channel, side) = self.get_mut(endpoint)?;
+        Self::push(channel, side, message, MessageKind::Message)
+    }
+
+    pub fn call(
+        &mut self,
+        endpoint: &ChannelEndpoint,
+        message: &[u8],
+    ) -> Result<TransactionId, ChannelError> {
+        let (channel, side) = self.get_mut(endpoint)?;
+        let transaction = TransactionId(channel.next_transaction_id);
+        Self::push(channel, side, message, MessageKind::Request(transaction))?;
+        channel.next_transaction_id = channel.next_transaction_id.wrapping_add(1);
+        channel.sides[side].awaiting_reply.insert(transaction);
+        Ok(transaction)
+    }
+
+    pub fn reply(
+        &mut self,
+        endpoint: &ChannelEndpoint,
+        transaction: TransactionId,
+        message: &[u8],
+    ) -> Result<(), ChannelError> {
+        let (channel, side) = self.get_mut(endpoint)?;
+        if !channel.sides[1 - side]
+            .awaiting_reply
+            .contains(&transaction)
+        {
+            return Err(ChannelError::InvalidTransaction);
+        }
+        Self::push(channel, side, message, MessageKind::Reply(transaction))?;
+        channel.sides[1 - side].awaiting_reply.remove(&transaction);
+        Ok(())
+    }
+
+    /// `take_handle` is only called if the handle can be sent, so the handle doesn't get lost if sending fails
+    pub fn send_handle(
+        &mut self,
+        endpoint: &ChannelEndpoint,
+        take_handle: impl FnOnce() -> Result<HandleEntry, HandleError>,
+    ) -> Result<(), ChannelError> {
+        let (channel, side) = self.get_mut(endpoint)?;
+        let peer = &mut channel.sides[1 - side];
+        if !peer.open {
+            return Err(ChannelError::PeerClosed);
+        }
+        if peer.handles.len() >= MAX_QUEUED_HANDLES {
+            return Err(ChannelError::QueueFull);
+        }
+        peer.handles.push_back(take_handle()?);
+        Ok(())
+    }
+
+    pub fn receive_handle(
+        &mut self,
+        endpoint: &ChannelEndpoint,
+    ) -> Result<HandleEntry, ChannelError> {
+        let (channel, side) = self.get_mut(endpoint)?;
+        let peer_open = channel.sides[1 - side].open;
+        channel.sides[side]
+            .handles
+            .pop_front()
+            .ok_or(match peer_open {
+                true => ChannelError::Empty,
+                false => ChannelError::PeerClosed,
+            })
+    }
+
+    /// Copies the oldest message into `buffer`. The message stays queued if it doesn't fit.
+    pub fn receive(
+        &mut self,
+        endpoint: &ChannelEndpoint,
+        buffer: &mut [u8],
+    ) -> Result<ReceivedMessage, ChannelError> {
+        let (channel, side) = self.get_mut(endpoint)?;
+        let peer_open = channel.sides[1 - side].open;
+        let queue = &mut channel.sides[side].queue;
+        let message = queue.front().ok_or(match peer_open {
+            true => ChannelError::Empty,
+            false => ChannelError::PeerClosed,
+        })?;
+        let len = message.bytes.len();
+        if len > buffer.len() {
+            return Err(ChannelError::BufferTooSmall { needed: len as u32 });
+        }
+        let message = queue.pop_front().unwrap();
+        buffer[..len].copy_from_slice(&message.bytes);
+        Ok(ReceivedMessage {
+            len: len as u32,
+            kind: message.kind,
+        })
+    }
+}
+
+pub fn test_channel_messages() {
+    let mut channels = Channels::default();
+    let [a, b] = channels.create();
+    let mut buffer = [0; 4];
+    assert_eq!(channels.receive(&b, &mut buffer), Err(ChannelError::Empty));
+    channels.send(&a, b"hello").unwrap();
+    // The message stays queued if it doesn't fit
+    assert_eq!(
+        channels.receive(&b, &mut buffer),
+        Err(ChannelError::BufferTooSmall { needed: 5 })
+    );
+    let mut buffer = [0; 8];
+    assert_eq!(
+        channels.receive(&b, &mut buffer),
+        Ok(ReceivedMessage {
+            len: 5,
+            kind: MessageKind::Message
+        })
+    );
+    assert_eq!(&buffer[..5], b"hello");
+    assert_eq!(
+        channels.send(&a, &[0; MAX_MESSAGE_LEN + 1]),
+        Err(ChannelError::MessageTooBig)
+    );
+    for _ in 0..MAX_QUEUED_MESSAGES {
+        channels.send(&a, &[]).unwrap();
+    }
+    assert_eq!(channels.send(&a, &[]), Err(ChannelError::QueueFull));
+    // Only the receiver's queue is full
+    channels.send(&b, &[]).unwrap();
+}
+
+pub fn test_channel_transactions() {
+    let mut channels = Channels::default();
+    let [a, b] = channels.create();
+    let mut buffer = [0; 8];
+    let transaction = channels.call(&a, b"ping").unwrap();
+    assert_eq!(
+        channels.receive(&b, &mut buffer),
+        Ok(ReceivedMessage {
+            len: 4,
+            kind: MessageKind::Request(transaction)
+        })
+    );
+    // Only the side that was called can reply
+    assert_eq!(
+        channels.reply(&a, transaction, b"pong"),
+        Err(ChannelError::InvalidTransaction)
+    );
+    channels.reply(&b, transaction, b"pong").unwrap();
+    assert_eq!(
+        channels.receive(&a, &mut buffer),
+        Ok(ReceivedMessage {
+            len: 4,
+            kind: MessageKind::Reply(transaction)
+        })
+    );
+    assert_eq!(&buffer[..4], b"pong");
+    // Each call is replied to once
+    assert_eq!(
+        channels.reply(&b, transaction, b"pong"),
+        Err(ChannelError::InvalidTransaction)
+    );
+    assert_ne!(channels.call(&a, &[]).unwrap(), transaction);
+}
+
+pub fn test_channel_close() {
+    let mut channels = Channels::default();
+    let [a, b] = channels.create();
+    let [c, d] = channels.create();
+    channels.send(&a, b"before close").unwrap();
+    channels
+        .send_handle(&a, || {
+            Ok(HandleEntry {
+                object: KernelObject::ChannelEndpoint(Arc::new(c)),
+                rights: Rights::READ,
+            })
+        })
+        .unwrap();
+    channels.close(a).unwrap();
+    assert_eq!(channels.send(&b, &[]), Err(ChannelError::PeerClosed));
+    // Messages that were sent before closing can still be received
+    let mut buffer = [0; 16];
+    assert!(channels.receive(&b, &mut buffer).is_ok());
+    assert_eq!(
+        channels.receive(&b, &mut buffer),
+        Err(ChannelError::PeerClosed)
+    );
+    // Closing `b` drops the handle to `c` that `b` didn't receive, which closes `c` too
+    channels.close(b).unwrap();
+    assert_eq!(channels.send(&d, &[]), Err(ChannelError::PeerClosed));
+    assert_eq!(
+        channels.close(ChannelEndpoint(0)),
+        Err(ChannelError::InvalidEndpoint)
+    );
+    channels.close(d).unwrap();
+    assert!(channels.channels.is_empty());
+}
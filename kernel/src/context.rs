@@ -11,7 +11,7 @@ pub trait Context {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct FullContext {
     pub rbp: u64,
     pub rax: u64,
@@ -69,7 +69,7 @@ impl Context for FullContext {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 /// Context that you can use to exit from a syscall later. Everything else is saved by the user space program when calling syscall. The order doesn't really matter since the stack isn't modified by the CPU when entering and exiting a syscall handler.
 pub struct SyscallContext {
     pub r15: u64,
@@ -126,4 +126,11 @@ impl AnyContext {
             AnyContext::Syscall(syscall_context) => syscall_context,
         }
     }
+
+    pub fn rsp(&self) -> u64 {
+        match self {
+            AnyContext::Full(full_context) => full_context.rsp,
+            AnyContext::Syscall(syscall_context) => syscall_context.rsp,
+        }
+    }
 }
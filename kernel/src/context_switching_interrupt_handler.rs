@@ -0,0 +1,114 @@
+use x86_64::{
+    structures::idt::{self, HandlerFunc, InterruptStackFrame, PageFaultHandlerFunc},
+    VirtAddr,
+};
+
+/// Defines a naked interrupt handler that saves all registers as a [`FullContext`](crate::context::FullContext) and calls `$handler` with a pointer to it.
+/// `$handler` must be an `unsafe extern "sysv64" fn(*const FullContext)` that never returns. It should restore a context or enter user mode.
+macro_rules! context_switching_interrupt_handler {
+    ($vis:vis $name:ident, $handler:path) => {
+        /// # Safety
+        /// Only the CPU should call this, as an interrupt handler
+        #[naked]
+        $vis unsafe extern "sysv64" fn $name(_stack_frame: x86_64::structures::idt::InterruptStackFrame) {
+            unsafe {
+                core::arch::naked_asm!("\
+                    push r15 
+                    push r14
+                    push r13
+                    push r12
+                    push r11
+                    push r10
+                    push r9
+                    push r8
+                    push rdi
+                    push rsi
+                    push rdx
+                    push rcx
+                    push rbx
+                    push rax
+                    push rbp
+                    
+                    mov rdi, rsp   // first arg of context switch is the context which is all the registers saved above
+                    
+                    // The function should never return
+                    call {context_switch}
+                    // asm! version of unreachable!() 
+                    ud2
+                    ",
+                    context_switch = sym $handler
+                );
+            };
+        }
+    };
+}
+
+pub(crate) use context_switching_interrupt_handler;
+
+/// Like [`context_switching_interrupt_handler`], but for exceptions that push an error code.
+/// `$handler` must be an `unsafe extern "sysv64" fn(*const FullContext, u64)` that never returns, and it gets the error code as its second input.
+macro_rules! context_switching_interrupt_handler_with_error_code {
+    ($vis:vis $name:ident, $handler:path) => {
+        /// # Safety
+        /// Only the CPU should call this, as an interrupt handler
+        #[naked]
+        $vis unsafe extern "sysv64" fn $name(
+            _stack_frame: x86_64::structures::idt::InterruptStackFrame,
+            _error_code: u64,
+        ) {
+            unsafe {
+                core::arch::naked_asm!("\
+                    // Put the error code in rsi (the second input) and r15 where the error code was, so that the stack looks like it does without an error code
+                    xchg rsi, [rsp]
+                    xchg r15, [rsp]
+                    push r14
+                    push r13
+                    push r12
+                    push r11
+                    push r10
+                    push r9
+                    push r8
+                    push rdi
+                    // This is the original rsi
+                    push r15
+                    push rdx
+                    push rcx
+                    push rbx
+                    push rax
+                    push rbp
+
+                    mov rdi, rsp   // first arg of context switch is the context which is all the registers saved above
+
+                    // The function should never return
+                    call {context_switch}
+                    // asm! version of unreachable!()
+                    ud2
+                    ",
+                    context_switch = sym $handler
+                );
+            };
+        }
+    };
+}
+
+pub(crate) use context_switching_interrupt_handler_with_error_code;
+
+/// Creates an IDT entry for a handler defined with [`context_switching_interrupt_handler`]
+pub fn context_switching_idt_entry(
+    handler: unsafe extern "sysv64" fn(InterruptStackFrame),
+) -> idt::Entry<HandlerFunc> {
+    let mut entry = idt::Entry::<HandlerFunc>::missing();
+    entry.set_handler_fn(unsafe {
+        core::mem::transmute::<*const (), HandlerFunc>(handler as *const _)
+    });
+    entry
+}
+
+/// Creates a page fault IDT entry for a handler defined with [`context_switching_interrupt_handler_with_error_code`]
+pub fn context_switching_page_fault_idt_entry(
+    handler: unsafe extern "sysv64" fn(InterruptStackFrame, u64),
+) -> idt::Entry<PageFaultHandlerFunc> {
+    let mut entry = idt::Entry::<PageFaultHandlerFunc>::missing();
+    unsafe { entry.set_handler_addr(VirtAddr::from_ptr(handler as *const ())) };
+    entry
+}
@@ -1,183 +1,163 @@
-use core::{
-    arch::naked_asm,
-    ops::{Deref, DerefMut},
-};
+use core::ops::{Deref, DerefMut};
 
 use alloc::sync::Arc;
-use common::syscall_start_recording_keyboard::{
-    FullQueueBehavior, SyscallStartRecordingKeyboardInput,
+use common::{
+    event::EventSource,
+    key_event::{KeyCode, KeyEvent, KeyState, KeyboardLayout},
+    signal::Signal,
+    syscall_start_recording_keyboard::{
+        FullQueueBehavior, KeyboardMode, SyscallStartRecordingKeyboardInput,
+    },
 };
 use conquer_once::noblock::OnceCell;
 use crossbeam_queue::ArrayQueue;
+use pc_keyboard::{
+    layouts::{AnyLayout, Azerty, Dvorak104Key, Uk105Key, Us104Key},
+    DecodedKey, HandleControl, Keyboard, ScancodeSet1,
+};
 use spin::{Mutex, RwLock, RwLockReadGuard};
 use x2apic::{
     ioapic::{IoApic, RedirectionTableEntry},
     lapic::LocalApic,
 };
-use x86_64::{
-    instructions::port::Port,
-    structures::idt::{self, HandlerFunc, InterruptStackFrame},
-    VirtAddr,
-};
 
 use crate::{
-    context::{AnyContext, FullContext},
-    enter_user_mode::enter_user_mode,
+    context::FullContext,
+    context_switching_interrupt_handler::{
+        context_switching_idt_entry, context_switching_interrupt_handler,
+    },
+    kernel_thread,
     modules::idt::IdtBuilder,
     pic8259_interrupts::Pic8259Interrupts,
+    ps2_controller, ps2_keyboard,
     user_space_state::State,
 };
 
 static LOCAL_APIC: OnceCell<&'static OnceCell<Mutex<LocalApic>>> = OnceCell::uninit();
 
+enum KeyboardQueue {
+    ScanCodes(ArrayQueue<u8>),
+    KeyEvents {
+        /// Only the interrupt handler uses this, but it needs to be mutable
+        decoder: Mutex<Keyboard<AnyLayout, ScancodeSet1>>,
+        queue: ArrayQueue<KeyEvent>,
+    },
+}
+
 struct RecordingKeyboard {
     full_queue_behavior: FullQueueBehavior,
-    queue: ArrayQueue<u8>,
+    queue: KeyboardQueue,
 }
 
-static SCAN_CODE_QUEUE: RwLock<Option<RecordingKeyboard>> = RwLock::new(None);
-pub static USER_SPACE_INTERRUPT_HANDLER: Mutex<Option<VirtAddr>> = Mutex::new(None);
-static STATE: OnceCell<Arc<Mutex<State>>> = OnceCell::uninit();
+/// Pushes to a device's event queue the way that the process asked for. The mouse handler uses this too.
+pub fn push<T>(queue: &ArrayQueue<T>, full_queue_behavior: FullQueueBehavior, value: T) {
+    match full_queue_behavior {
+        FullQueueBehavior::DropNewest => {
+            let _ = queue.push(value);
+        }
+        FullQueueBehavior::DropOldest => {
+            queue.force_push(value);
+        }
+    }
+}
 
-#[naked]
-unsafe extern "sysv64" fn context_switching_keyboard_interrupt_handler(
-    _stack_frame: InterruptStackFrame,
-) {
-    unsafe {
-        naked_asm!("\
-            push r15 
-            push r14
-            push r13
-            push r12
-            push r11
-            push r10
-            push r9
-            push r8
-            push rdi
-            push rsi
-            push rdx
-            push rcx
-            push rbx
-            push rax
-            push rbp
-            
-            mov rdi, rsp   // first arg of context switch is the context which is all the registers saved above
-            
-            // The function should never return
-            call {context_switch}
-            // asm! version of unreachable!() 
-            ud2
-            ", 
-            context_switch = sym context_switching_keyboard_interrupt_handler_rust
-        );
+macro_rules! convert_key_code {
+    ($($name:ident),* $(,)?) => {
+        fn convert_key_code(key_code: pc_keyboard::KeyCode) -> KeyCode {
+            match key_code {
+                $(pc_keyboard::KeyCode::$name => KeyCode::$name,)*
+            }
+        }
     };
 }
 
+common::for_each_key_code!(convert_key_code);
+
+fn convert_key_state(key_state: pc_keyboard::KeyState) -> KeyState {
+    match key_state {
+        pc_keyboard::KeyState::Up => KeyState::Up,
+        pc_keyboard::KeyState::Down => KeyState::Down,
+        pc_keyboard::KeyState::SingleShot => KeyState::SingleShot,
+    }
+}
+
+fn decoder(layout: KeyboardLayout) -> Keyboard<AnyLayout, ScancodeSet1> {
+    let layout = match layout {
+        KeyboardLayout::Us => AnyLayout::Us104Key(Us104Key),
+        KeyboardLayout::Uk => AnyLayout::Uk105Key(Uk105Key),
+        KeyboardLayout::Dvorak => AnyLayout::Dvorak104Key(Dvorak104Key),
+        KeyboardLayout::Azerty => AnyLayout::Azerty(Azerty),
+    };
+    Keyboard::new(ScancodeSet1::new(), layout, HandleControl::Ignore)
+}
+
+/// Returns [`None`] if the scan code is only part of a key event
+fn decode(decoder: &mut Keyboard<AnyLayout, ScancodeSet1>, scan_code: u8) -> Option<KeyEvent> {
+    let key_event = decoder.add_byte(scan_code).ok()??;
+    let code = convert_key_code(key_event.code);
+    let state = convert_key_state(key_event.state);
+    // This also keeps track of the modifier keys, so it has to be called for every key event
+    let unicode = match decoder.process_keyevent(key_event) {
+        Some(DecodedKey::Unicode(character)) => Some(character),
+        _ => None,
+    };
+    Some(KeyEvent {
+        code,
+        state,
+        unicode,
+    })
+}
+
+static SCAN_CODE_QUEUE: RwLock<Option<RecordingKeyboard>> = RwLock::new(None);
+static STATE: OnceCell<Arc<Mutex<State>>> = OnceCell::uninit();
+
+context_switching_interrupt_handler!(
+    context_switching_keyboard_interrupt_handler,
+    context_switching_keyboard_interrupt_handler_rust
+);
+
 unsafe extern "sysv64" fn context_switching_keyboard_interrupt_handler_rust(
     context: *const FullContext,
 ) {
     let context = unsafe { *context };
-    {
-        // log::info!("State: {:#x?}", STATE.try_get().unwrap().lock().deref());
-    }
-    // log::info!("Context: {:#x?}", context);
     // Make sure to drop all locks before exiting
-    #[derive(Debug)]
-    enum JmpTo {
-        UserMode(VirtAddr, VirtAddr),
-        RestoreContext(AnyContext),
-    }
     let jmp_to = {
-        let mut port = Port::new(0x60);
-        let scan_code: u8 = unsafe { port.read() };
-        if let Some(RecordingKeyboard {
-            full_queue_behavior,
-            queue,
-        }) = SCAN_CODE_QUEUE.read().deref()
+        // The data was already read if it was the keyboard acknowledging a command
+        let scan_code = ps2_controller::try_read_data();
+        let ctrl_c = scan_code.is_some_and(ps2_keyboard::track_keys);
+        if let (
+            Some(scan_code),
+            Some(RecordingKeyboard {
+                full_queue_behavior,
+                queue,
+            }),
+        ) = (scan_code, SCAN_CODE_QUEUE.read().deref())
         {
-            match full_queue_behavior {
-                FullQueueBehavior::DropNewest => {
-                    let _ = queue.push(scan_code);
-                }
-                FullQueueBehavior::DropOldest => {
-                    queue.force_push(scan_code);
+            match queue {
+                KeyboardQueue::ScanCodes(queue) => push(queue, *full_queue_behavior, scan_code),
+                KeyboardQueue::KeyEvents { decoder, queue } => {
+                    if let Some(key_event) = decode(&mut decoder.lock(), scan_code) {
+                        push(queue, *full_queue_behavior, key_event);
+                    }
                 }
             }
         };
         let mut local_apic = LOCAL_APIC.try_get().unwrap().try_get().unwrap().lock();
         unsafe { local_apic.end_of_interrupt() };
 
-        let mut user_space_state = STATE.try_get().unwrap().lock();
-        let user_space_state = user_space_state.as_mut().unwrap();
-        let user_space_interrupt_handler = USER_SPACE_INTERRUPT_HANDLER.lock();
-        let user_space_interrupt_handler = user_space_interrupt_handler.as_ref();
-        // This interrupt interrupted one of two things
-        // - A hlt loop from a syscall handler. We just enter the handler then. If no handler exists then we restore context.
-        // - The user space process. We save this context and enter the handler.
-        match user_space_state.stack_of_saved_contexts.last() {
-            None => {
-                if let Some(user_space_interrupt_handler) = user_space_interrupt_handler {
-                    if !user_space_state.in_keyboard_interrupt_handler
-                        && user_space_state.interrupts_enabled
-                    {
-                        user_space_state
-                            .stack_of_saved_contexts
-                            .push_within_capacity(AnyContext::Full(context))
-                            .unwrap();
-                        // Continue the stack
-                        let interrupt_handler_stack_end = VirtAddr::new(context.rsp);
-                        user_space_state.in_keyboard_interrupt_handler = true;
-                        JmpTo::UserMode(*user_space_interrupt_handler, interrupt_handler_stack_end)
-                    } else {
-                        user_space_state.keyboard_interrupt_queued = true;
-                        JmpTo::RestoreContext(AnyContext::Full(context))
-                    }
-                } else {
-                    // Just exit this interrupt handler
-                    JmpTo::RestoreContext(AnyContext::Full(context))
-                }
-            }
-            Some(AnyContext::Syscall(syscall_context)) => {
-                if let Some(user_space_interrupt_handler) = user_space_interrupt_handler {
-                    if !user_space_state.interrupts_enabled {
-                        unreachable!("You can't disable interrupts and then wait for an interrupt to happen, because then the function would never be called. The syscall for waiting for an interrupt to happen should've returned an error.");
-                    }
-                    if !user_space_state.in_keyboard_interrupt_handler {
-                        // Continue the stack
-                        let interrupt_handler_stack_end = VirtAddr::new(syscall_context.rsp);
-                        user_space_state.in_keyboard_interrupt_handler = true;
-                        JmpTo::UserMode(*user_space_interrupt_handler, interrupt_handler_stack_end)
-                    } else {
-                        // Queue up
-                        user_space_state.keyboard_interrupt_queued = true;
-                        JmpTo::RestoreContext(AnyContext::Full(context))
-                    }
-                } else {
-                    // sysretq
-                    JmpTo::RestoreContext(AnyContext::Syscall(*syscall_context))
-                }
-            }
-            Some(AnyContext::Full(_full_context)) => {
-                // This means that we have an interrupt in the middle of a user space interrupt handler
-                // Sine we only have one interrupt handler (keyboard), we just queue another one
-                if !user_space_state.in_keyboard_interrupt_handler {
-                    unreachable!("In a user space interrupt handler that is not a keyboard interrupt handler. Impossible.");
-                } else {
-                    user_space_state.keyboard_interrupt_queued = true;
-                    JmpTo::RestoreContext(AnyContext::Full(context))
-                }
+        kernel_thread::on_interrupt(context, false, |context| {
+            let mut state = STATE.try_get().unwrap().lock();
+            let user_space_state = state.as_mut().unwrap();
+            if ctrl_c {
+                // The scan code is still recorded, and its event is handled after the signal handler
+                user_space_state.queue_event(EventSource::Keyboard);
+                user_space_state.on_signal_interrupt(Signal::Interrupt, context)
+            } else {
+                user_space_state.on_event(EventSource::Keyboard, context)
             }
-        }
+        })
     };
-    // log::info!("jmp_to: {:#?}", jmp_to);
-    match jmp_to {
-        JmpTo::UserMode(user_space_interrupt_handler, interrupt_handler_stack_end) => {
-            unsafe { enter_user_mode(user_space_interrupt_handler, interrupt_handler_stack_end) };
-        }
-        JmpTo::RestoreContext(context) => {
-            unsafe { context.context().restore() };
-        }
-    }
+    unsafe { jmp_to.jmp() };
 }
 
 unsafe fn enable_interrupts(io_apic: &mut IoApic) {
@@ -198,17 +178,9 @@ impl CoolKeyboardBuilder {
         local_apic: &'static OnceCell<Mutex<LocalApic>>,
     ) -> Option<Self> {
         LOCAL_APIC.try_init_once(|| local_apic).unwrap();
-        let interrupt_index = idt_builder.set_flexible_entry({
-            let mut entry = idt::Entry::<HandlerFunc>::missing();
-            entry.set_handler_fn({
-                unsafe {
-                    core::mem::transmute::<*const (), HandlerFunc>(
-                        context_switching_keyboard_interrupt_handler as *const _,
-                    )
-                }
-            });
-            entry
-        })?;
+        let interrupt_index = idt_builder.set_flexible_entry(context_switching_idt_entry(
+            context_switching_keyboard_interrupt_handler,
+        ))?;
         Some(Self { interrupt_index })
     }
 
@@ -241,13 +213,22 @@ impl CoolKeyboard {
     pub fn enable(&self, settings: SyscallStartRecordingKeyboardInput) {
         *SCAN_CODE_QUEUE.write() = Some(RecordingKeyboard {
             full_queue_behavior: settings.behavior_on_full_queue,
-            queue: ArrayQueue::new(settings.queue_size as usize),
+            queue: match settings.mode {
+                KeyboardMode::ScanCodes => {
+                    KeyboardQueue::ScanCodes(ArrayQueue::new(settings.queue_size as usize))
+                }
+                KeyboardMode::KeyEvents(layout) => KeyboardQueue::KeyEvents {
+                    decoder: Mutex::new(decoder(layout)),
+                    queue: ArrayQueue::new(settings.queue_size as usize),
+                },
+            },
         });
         unsafe { enable_interrupts(self.io_apic.lock().deref_mut()) };
     }
 
     pub fn disable(&self) {
         unsafe { disable_interrupts(self.io_apic.lock().deref_mut()) };
+        *SCAN_CODE_QUEUE.write() = None;
     }
 
     pub fn queue(&self) -> QueueGuard {
@@ -255,10 +236,6 @@ impl CoolKeyboard {
             guard: SCAN_CODE_QUEUE.read(),
         }
     }
-
-    pub fn set_user_space_interrupt(&self, user_space_interrupt: Option<VirtAddr>) {
-        *USER_SPACE_INTERRUPT_HANDLER.lock() = user_space_interrupt
-    }
 }
 
 pub struct QueueGuard<'a> {
@@ -266,9 +243,19 @@ pub struct QueueGuard<'a> {
 }
 
 impl QueueGuard<'_> {
+    /// Returns [`None`] if the keyboard isn't recording scan codes
     pub fn queue(&self) -> Option<&ArrayQueue<u8>> {
-        self.guard
-            .as_ref()
-            .map(|recording_keyboard| &recording_keyboard.queue)
+        match &self.guard.as_ref()?.queue {
+            KeyboardQueue::ScanCodes(queue) => Some(queue),
+            KeyboardQueue::KeyEvents { .. } => None,
+        }
+    }
+
+    /// Returns [`None`] if the keyboard isn't recording key events
+    pub fn key_events(&self) -> Option<&ArrayQueue<KeyEvent>> {
+        match &self.guard.as_ref()?.queue {
+            KeyboardQueue::ScanCodes(_) => None,
+            KeyboardQueue::KeyEvents { queue, .. } => Some(queue),
+        }
     }
 }
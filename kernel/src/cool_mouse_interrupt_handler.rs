@@ -0,0 +1,171 @@
+use core::ops::{Deref, DerefMut};
+
+use alloc::sync::Arc;
+use common::{
+    event::EventSource,
+    mouse::{MouseEvent, MouseId, MousePacketDecoder},
+    syscall_start_recording_keyboard::FullQueueBehavior,
+    syscall_start_recording_mouse::SyscallStartRecordingMouseInput,
+};
+use conquer_once::noblock::OnceCell;
+use crossbeam_queue::ArrayQueue;
+use spin::{Mutex, RwLock, RwLockReadGuard};
+use x2apic::{
+    ioapic::{IoApic, RedirectionTableEntry},
+    lapic::LocalApic,
+};
+use x86_64::instructions::port::Port;
+
+use crate::{
+    context::{AnyContext, FullContext},
+    context_switching_interrupt_handler::{
+        context_switching_idt_entry, context_switching_interrupt_handler,
+    },
+    cool_keyboard_interrupt_handler::push,
+    kernel_thread,
+    modules::idt::IdtBuilder,
+    pic8259_interrupts::Pic8259Interrupts,
+    user_space_state::{JmpTo, State},
+};
+
+static LOCAL_APIC: OnceCell<&'static OnceCell<Mutex<LocalApic>>> = OnceCell::uninit();
+
+struct RecordingMouse {
+    full_queue_behavior: FullQueueBehavior,
+    queue: ArrayQueue<MouseEvent>,
+}
+
+static EVENT_QUEUE: RwLock<Option<RecordingMouse>> = RwLock::new(None);
+static DECODER: OnceCell<Mutex<MousePacketDecoder>> = OnceCell::uninit();
+static STATE: OnceCell<Arc<Mutex<State>>> = OnceCell::uninit();
+
+context_switching_interrupt_handler!(
+    context_switching_mouse_interrupt_handler,
+    context_switching_mouse_interrupt_handler_rust
+);
+
+unsafe extern "sysv64" fn context_switching_mouse_interrupt_handler_rust(
+    context: *const FullContext,
+) {
+    let context = unsafe { *context };
+    // Make sure to drop all locks before exiting
+    let jmp_to = {
+        let mut port = Port::new(0x60);
+        let byte: u8 = unsafe { port.read() };
+        let event = DECODER.try_get().unwrap().lock().add_byte(byte);
+        if let Some(event) = event {
+            if let Some(RecordingMouse {
+                full_queue_behavior,
+                queue,
+            }) = EVENT_QUEUE.read().deref()
+            {
+                push(queue, *full_queue_behavior, event);
+            };
+        }
+        let mut local_apic = LOCAL_APIC.try_get().unwrap().try_get().unwrap().lock();
+        unsafe { local_apic.end_of_interrupt() };
+
+        match event {
+            // Only interrupt user space once there is a whole packet
+            Some(_) => kernel_thread::on_interrupt(context, false, |context| {
+                STATE
+                    .try_get()
+                    .unwrap()
+                    .lock()
+                    .as_mut()
+                    .unwrap()
+                    .on_event(EventSource::Mouse, context)
+            }),
+            None => JmpTo::RestoreContext(AnyContext::Full(context)),
+        }
+    };
+    unsafe { jmp_to.jmp() };
+}
+
+pub struct CoolMouseBuilder {
+    interrupt_index: u8,
+}
+
+impl CoolMouseBuilder {
+    pub fn set_interrupt(
+        idt_builder: &mut IdtBuilder,
+        local_apic: &'static OnceCell<Mutex<LocalApic>>,
+    ) -> Option<Self> {
+        LOCAL_APIC.try_init_once(|| local_apic).unwrap();
+        let interrupt_index = idt_builder.set_flexible_entry(context_switching_idt_entry(
+            context_switching_mouse_interrupt_handler,
+        ))?;
+        Some(Self { interrupt_index })
+    }
+
+    /// `id` is the id that the mouse was initialized with, which decides how packets are decoded
+    pub fn configure_io_apic(
+        &'static self,
+        io_apic: Arc<Mutex<IoApic>>,
+        state: Arc<Mutex<State>>,
+        id: MouseId,
+    ) -> CoolMouse {
+        unsafe {
+            io_apic
+                .lock()
+                .set_table_entry(Pic8259Interrupts::Mouse.into(), {
+                    let mut entry = RedirectionTableEntry::default();
+                    entry.set_vector(self.interrupt_index);
+                    entry
+                })
+        };
+        DECODER
+            .try_init_once(|| Mutex::new(MousePacketDecoder::new(id)))
+            .unwrap();
+        STATE.try_init_once(|| state).unwrap();
+        CoolMouse { io_apic }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CoolMouse {
+    io_apic: Arc<Mutex<IoApic>>,
+}
+
+impl CoolMouse {
+    pub fn enable(&self, settings: SyscallStartRecordingMouseInput) {
+        *EVENT_QUEUE.write() = Some(RecordingMouse {
+            full_queue_behavior: settings.behavior_on_full_queue,
+            queue: ArrayQueue::new(settings.queue_size as usize),
+        });
+        unsafe {
+            self.io_apic
+                .lock()
+                .deref_mut()
+                .enable_irq(Pic8259Interrupts::Mouse.into())
+        };
+    }
+
+    pub fn disable(&self) {
+        unsafe {
+            self.io_apic
+                .lock()
+                .deref_mut()
+                .disable_irq(Pic8259Interrupts::Mouse.into())
+        };
+        *EVENT_QUEUE.write() = None;
+    }
+
+    pub fn queue(&self) -> MouseQueueGuard {
+        MouseQueueGuard {
+            guard: EVENT_QUEUE.read(),
+        }
+    }
+}
+
+pub struct MouseQueueGuard<'a> {
+    guard: RwLockReadGuard<'a, Option<RecordingMouse>>,
+}
+
+impl MouseQueueGuard<'_> {
+    pub fn queue(&self) -> Option<&ArrayQueue<MouseEvent>> {
+        self.guard
+            .as_ref()
+            .map(|recording_mouse| &recording_mouse.queue)
+    }
+}
@@ -0,0 +1,131 @@
+use core::{
+    ops::DerefMut,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use alloc::sync::Arc;
+use common::event::EventSource;
+use conquer_once::noblock::OnceCell;
+use spin::Mutex;
+use x2apic::{
+    ioapic::{IoApic, RedirectionTableEntry},
+    lapic::LocalApic,
+};
+use x86_rtc::interrupts::{read_register_c, DividerValue};
+
+use crate::{
+    context::FullContext,
+    context_switching_interrupt_handler::{
+        context_switching_idt_entry, context_switching_interrupt_handler,
+    },
+    kernel_thread,
+    modules::idt::IdtBuilder,
+    pic8259_interrupts::Pic8259Interrupts,
+    user_space_state::State,
+};
+
+static LOCAL_APIC: OnceCell<&'static OnceCell<Mutex<LocalApic>>> = OnceCell::uninit();
+static STATE: OnceCell<Arc<Mutex<State>>> = OnceCell::uninit();
+/// Ticks since the ticks were last polled
+static TICKS: AtomicU32 = AtomicU32::new(0);
+
+context_switching_interrupt_handler!(
+    context_switching_rtc_interrupt_handler,
+    context_switching_rtc_interrupt_handler_rust
+);
+
+unsafe extern "sysv64" fn context_switching_rtc_interrupt_handler_rust(
+    context: *const FullContext,
+) {
+    let context = unsafe { *context };
+    // Make sure to drop all locks before exiting
+    let jmp_to = {
+        // The RTC doesn't interrupt again until register C is read
+        read_register_c();
+        TICKS.fetch_add(1, Ordering::Relaxed);
+        let mut local_apic = LOCAL_APIC.try_get().unwrap().try_get().unwrap().lock();
+        unsafe { local_apic.end_of_interrupt() };
+
+        kernel_thread::on_interrupt(context, false, |context| {
+            STATE
+                .try_get()
+                .unwrap()
+                .lock()
+                .as_mut()
+                .unwrap()
+                .on_event(EventSource::Rtc, context)
+        })
+    };
+    unsafe { jmp_to.jmp() };
+}
+
+pub struct CoolRtcBuilder {
+    interrupt_index: u8,
+}
+
+impl CoolRtcBuilder {
+    pub fn set_interrupt(
+        idt_builder: &mut IdtBuilder,
+        local_apic: &'static OnceCell<Mutex<LocalApic>>,
+    ) -> Option<Self> {
+        LOCAL_APIC.try_init_once(|| local_apic).unwrap();
+        let interrupt_index = idt_builder.set_flexible_entry(context_switching_idt_entry(
+            context_switching_rtc_interrupt_handler,
+        ))?;
+        Some(Self { interrupt_index })
+    }
+
+    pub fn configure_io_apic(
+        &'static self,
+        io_apic: Arc<Mutex<IoApic>>,
+        state: Arc<Mutex<State>>,
+    ) -> CoolRtc {
+        unsafe {
+            io_apic
+                .lock()
+                .set_table_entry(Pic8259Interrupts::Rtc.into(), {
+                    let mut entry = RedirectionTableEntry::default();
+                    entry.set_vector(self.interrupt_index);
+                    entry
+                })
+        };
+        STATE.try_init_once(|| state).unwrap();
+        CoolRtc { io_apic }
+    }
+}
+
+/// Periodic RTC interrupts for user space
+#[derive(Debug, Clone)]
+pub struct CoolRtc {
+    io_apic: Arc<Mutex<IoApic>>,
+}
+
+impl CoolRtc {
+    /// Starts interrupting at `32768 >> (divider_value - 1)` Hz. If it was already started, this just changes the rate.
+    pub fn enable(&self, divider_value: DividerValue) {
+        TICKS.store(0, Ordering::Relaxed);
+        x86_rtc::interrupts::set_divider_value(divider_value);
+        x86_rtc::interrupts::enable();
+        unsafe {
+            self.io_apic
+                .lock()
+                .deref_mut()
+                .enable_irq(Pic8259Interrupts::Rtc.into())
+        };
+    }
+
+    pub fn disable(&self) {
+        unsafe {
+            self.io_apic
+                .lock()
+                .deref_mut()
+                .disable_irq(Pic8259Interrupts::Rtc.into())
+        };
+        x86_rtc::interrupts::disable();
+    }
+
+    /// Returns the number of ticks since the last poll
+    pub fn poll(&self) -> u32 {
+        TICKS.swap(0, Ordering::Relaxed)
+    }
+}
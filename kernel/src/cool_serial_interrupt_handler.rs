@@ -0,0 +1,145 @@
+use alloc::sync::Arc;
+use common::{event::EventSource, signal::Signal};
+use conquer_once::noblock::OnceCell;
+use crossbeam_queue::ArrayQueue;
+use spin::Mutex;
+use x2apic::{
+    ioapic::{IoApic, RedirectionTableEntry},
+    lapic::LocalApic,
+};
+
+use crate::{
+    context::{AnyContext, FullContext},
+    context_switching_interrupt_handler::{
+        context_switching_idt_entry, context_switching_interrupt_handler,
+    },
+    debug_monitor::{self, MONITOR_KEY},
+    kernel_thread,
+    modules::idt::IdtBuilder,
+    pic8259_interrupts::Pic8259Interrupts,
+    uart::COM1,
+    user_space_state::{JmpTo, State},
+};
+
+/// Bytes that are received while the buffer is full are dropped
+const INPUT_BUFFER_SIZE: usize = 4096;
+/// Ctrl+C. It isn't buffered, and sends [`Signal::Interrupt`] instead.
+const INTERRUPT_KEY: u8 = 0x03;
+
+static LOCAL_APIC: OnceCell<&'static OnceCell<Mutex<LocalApic>>> = OnceCell::uninit();
+static STATE: OnceCell<Arc<Mutex<State>>> = OnceCell::uninit();
+/// Input is always buffered, even before a program reads it, so that input sent right after booting isn't lost
+static INPUT: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+
+context_switching_interrupt_handler!(
+    context_switching_serial_interrupt_handler,
+    context_switching_serial_interrupt_handler_rust
+);
+
+unsafe extern "sysv64" fn context_switching_serial_interrupt_handler_rust(
+    context: *const FullContext,
+) {
+    let context = unsafe { *context };
+    // Make sure to drop all locks before exiting
+    let jmp_to = {
+        let input = INPUT.try_get().unwrap();
+        let mut received = false;
+        let mut signal = None::<Signal>;
+        // The UART has a FIFO, so there can be more than 1 byte per interrupt
+        while let Some(byte) = COM1.try_read() {
+            // If more than 1 signal is sent, only the one with the highest priority is delivered
+            let mut send = |new_signal| {
+                signal = Some(signal.map_or(new_signal, |signal| signal.min(new_signal)));
+            };
+            if byte == MONITOR_KEY {
+                if let Some(monitor_signal) = debug_monitor::run(Some(&context)) {
+                    send(monitor_signal);
+                }
+            } else if byte == INTERRUPT_KEY {
+                send(Signal::Interrupt);
+            } else {
+                let _ = input.push(byte);
+                received = true;
+            }
+        }
+        let mut local_apic = LOCAL_APIC.try_get().unwrap().try_get().unwrap().lock();
+        unsafe { local_apic.end_of_interrupt() };
+        drop(local_apic);
+
+        kernel_thread::on_interrupt(context, false, |context| {
+            match (signal, received, STATE.try_get().unwrap().lock().as_mut()) {
+                (Some(signal), _, Some(user_space_state)) => {
+                    if received {
+                        // Handled after the signal handler
+                        user_space_state.queue_event(EventSource::Serial);
+                    }
+                    user_space_state.on_signal_interrupt(signal, context)
+                }
+                (None, true, Some(user_space_state)) => {
+                    user_space_state.on_event(EventSource::Serial, context)
+                }
+                // Input can arrive before user space is started
+                _ => JmpTo::RestoreContext(AnyContext::Full(context)),
+            }
+        })
+    };
+    unsafe { jmp_to.jmp() };
+}
+
+pub struct CoolSerialBuilder {
+    interrupt_index: u8,
+}
+
+impl CoolSerialBuilder {
+    pub fn set_interrupt(
+        idt_builder: &mut IdtBuilder,
+        local_apic: &'static OnceCell<Mutex<LocalApic>>,
+    ) -> Option<Self> {
+        LOCAL_APIC.try_init_once(|| local_apic).unwrap();
+        let interrupt_index = idt_builder.set_flexible_entry(context_switching_idt_entry(
+            context_switching_serial_interrupt_handler,
+        ))?;
+        Some(Self { interrupt_index })
+    }
+
+    /// Starts buffering input right away. Call this after the serial logger initialized COM1.
+    pub fn configure_io_apic(
+        &'static self,
+        io_apic: Arc<Mutex<IoApic>>,
+        state: Arc<Mutex<State>>,
+    ) -> CoolSerial {
+        INPUT
+            .try_init_once(|| ArrayQueue::new(INPUT_BUFFER_SIZE))
+            .unwrap();
+        STATE.try_init_once(|| state).unwrap();
+        let mut io_apic = io_apic.lock();
+        unsafe {
+            io_apic.set_table_entry(Pic8259Interrupts::Com1.into(), {
+                let mut entry = RedirectionTableEntry::default();
+                entry.set_vector(self.interrupt_index);
+                entry
+            });
+            io_apic.enable_irq(Pic8259Interrupts::Com1.into());
+        }
+        COM1.enable_receive_interrupt();
+        CoolSerial
+    }
+}
+
+/// Input from COM1 for user space
+#[derive(Debug, Clone)]
+pub struct CoolSerial;
+
+impl CoolSerial {
+    /// Returns the number of bytes read
+    pub fn read(&self, buffer: &mut [u8]) -> usize {
+        let input = INPUT.try_get().unwrap();
+        buffer
+            .iter_mut()
+            .map_while(|slot| {
+                *slot = input.pop()?;
+                Some(())
+            })
+            .count()
+    }
+}
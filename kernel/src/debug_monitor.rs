@@ -0,0 +1,236 @@
+//! A small interactive monitor on COM1 for looking at the kernel's state.
+//! It is entered by sending [`MONITOR_KEY`] over serial, or after a panic.
+//! It runs with interrupts disabled, so everything is frozen while it's open.
+
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::sync::Arc;
+use common::signal::Signal;
+use conquer_once::noblock::OnceCell;
+use spin::Mutex;
+use x86_64::{
+    registers::control::{Cr0, Cr2, Cr3, Cr4},
+    structures::paging::{OffsetPageTable, Translate},
+    VirtAddr,
+};
+
+use crate::{
+    allocator::heap_stats,
+    context::FullContext,
+    modules::idt::IdtBuilder,
+    uart::{UartWriter, COM1},
+    user_space_state::State,
+    virt_mem_tracker::VirtMemTracker,
+};
+
+/// Ctrl+]
+pub const MONITOR_KEY: u8 = 0x1D;
+const MAX_LINE_LEN: usize = 80;
+const HELP: &str = "\
+Commands:
+  regs          Registers of the interrupted code and control registers
+  stack         The user space context stack
+  page <addr>   How a virtual address (in hex) is mapped
+  virt          Used kernel virtual memory
+  heap          Kernel heap usage
+  idt           IDT vectors that have an entry
+  kill          Send the Terminate signal to the process when leaving the monitor
+  continue      Leave the monitor
+";
+
+struct Resources {
+    state: Arc<Mutex<State>>,
+    mapper: Arc<Mutex<OffsetPageTable<'static>>>,
+    virt_mem_tracker: Arc<Mutex<VirtMemTracker>>,
+    idt_builder: &'static IdtBuilder,
+}
+
+static RESOURCES: OnceCell<Resources> = OnceCell::uninit();
+/// So that a panic in the monitor doesn't enter it again
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Gives the monitor access to things it can show. Before this, only registers can be shown.
+pub fn init(
+    state: Arc<Mutex<State>>,
+    mapper: Arc<Mutex<OffsetPageTable<'static>>>,
+    virt_mem_tracker: Arc<Mutex<VirtMemTracker>>,
+    idt_builder: &'static IdtBuilder,
+) {
+    RESOURCES
+        .try_init_once(|| Resources {
+            state,
+            mapper,
+            virt_mem_tracker,
+            idt_builder,
+        })
+        .unwrap();
+}
+
+fn read_line(line: &mut heapless::String<MAX_LINE_LEN>) {
+    line.clear();
+    loop {
+        match COM1.read() {
+            b'\r' | b'\n' => {
+                let _ = writeln!(UartWriter(COM1));
+                break;
+            }
+            // Backspace or delete
+            0x08 | 0x7F => {
+                if line.pop().is_some() {
+                    let _ = write!(UartWriter(COM1), "\x08 \x08");
+                }
+            }
+            byte if byte.is_ascii_graphic() || byte == b' ' => {
+                if line.push(byte as char).is_ok() {
+                    COM1.write(byte);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Runs a command and returns `false` if the monitor should be left
+fn run_command(
+    w: &mut UartWriter,
+    line: &str,
+    context: Option<&FullContext>,
+    signal: &mut Option<Signal>,
+) -> bool {
+    let mut words = line.split_whitespace();
+    let resources = RESOURCES.try_get().ok();
+    match words.next() {
+        None => {}
+        Some("help") => {
+            let _ = write!(w, "{}", HELP);
+        }
+        Some("regs") => {
+            match context {
+                Some(context) => {
+                    let _ = writeln!(w, "{:#x?}", context);
+                }
+                None => {
+                    let _ = writeln!(w, "No interrupted context");
+                }
+            }
+            let _ = writeln!(w, "CR0: {:?}", Cr0::read());
+            let _ = writeln!(w, "CR2: {:?}", Cr2::read());
+            let _ = writeln!(w, "CR3: {:?}", Cr3::read());
+            let _ = writeln!(w, "CR4: {:?}", Cr4::read());
+        }
+        Some("stack") => match resources.map(|resources| resources.state.try_lock()) {
+            Some(Some(state)) => match state.as_ref() {
+                Some(user_space_state) => {
+                    let _ = writeln!(w, "{:#x?}", user_space_state.stack_of_saved_contexts);
+                }
+                None => {
+                    let _ = writeln!(w, "User space isn't running");
+                }
+            },
+            Some(None) => {
+                let _ = writeln!(w, "The user space state is locked");
+            }
+            None => {
+                let _ = writeln!(w, "Not available yet");
+            }
+        },
+        Some("page") => {
+            let addr = words
+                .next()
+                .and_then(|addr| u64::from_str_radix(addr.trim_start_matches("0x"), 16).ok())
+                .and_then(|addr| VirtAddr::try_new(addr).ok());
+            match (addr, resources.map(|resources| resources.mapper.try_lock())) {
+                (None, _) => {
+                    let _ = writeln!(w, "Usage: page <addr>");
+                }
+                (Some(addr), Some(Some(mapper))) => {
+                    let _ = writeln!(w, "{:#x?}", mapper.translate(addr));
+                }
+                (Some(_), Some(None)) => {
+                    let _ = writeln!(w, "The page table is locked");
+                }
+                (Some(_), None) => {
+                    let _ = writeln!(w, "Not available yet");
+                }
+            }
+        }
+        Some("virt") => match resources.map(|resources| resources.virt_mem_tracker.try_lock()) {
+            Some(Some(virt_mem_tracker)) => {
+                for range in virt_mem_tracker.used_ranges() {
+                    let _ = writeln!(w, "{:#x}..{:#x}", range.start.as_u64(), range.end.as_u64());
+                }
+            }
+            Some(None) => {
+                let _ = writeln!(w, "The virtual memory tracker is locked");
+            }
+            None => {
+                let _ = writeln!(w, "Not available yet");
+            }
+        },
+        Some("heap") => match heap_stats() {
+            Some(stats) => {
+                let _ = writeln!(w, "{:#?}", stats);
+            }
+            None => {
+                let _ = writeln!(w, "The heap is locked");
+            }
+        },
+        Some("idt") => match resources {
+            Some(resources) => {
+                for vector in resources.idt_builder.used_vectors() {
+                    let _ = write!(w, "{} ", vector);
+                }
+                let _ = writeln!(w);
+            }
+            None => {
+                let _ = writeln!(w, "Not available yet");
+            }
+        },
+        Some("kill") => match context {
+            Some(_) => {
+                *signal = Some(Signal::Terminate);
+                let _ = writeln!(w, "The process will be terminated when leaving the monitor");
+            }
+            None => {
+                let _ = writeln!(w, "Signals can't be sent after a panic");
+            }
+        },
+        Some("continue" | "c") => return false,
+        Some(command) => {
+            let _ = writeln!(
+                w,
+                "Unknown command: {}. Type help for a list of commands.",
+                command
+            );
+        }
+    }
+    true
+}
+
+/// Blocks until the monitor is left. Call this with interrupts disabled.
+/// `context` is the interrupted context, if there is one.
+/// Returns the signal to send to the process, if one was sent with `kill`.
+pub fn run(context: Option<&FullContext>) -> Option<Signal> {
+    if RUNNING.swap(true, Ordering::Relaxed) {
+        return None;
+    }
+    let mut w = UartWriter(COM1);
+    let _ = writeln!(
+        w,
+        "\nKernel debug monitor. Type help for a list of commands."
+    );
+    let mut line = heapless::String::new();
+    let mut signal = None;
+    loop {
+        let _ = write!(w, "monitor> ");
+        read_line(&mut line);
+        if !run_command(&mut w, &line, context, &mut signal) {
+            break;
+        }
+    }
+    RUNNING.store(false, Ordering::Relaxed);
+    signal
+}
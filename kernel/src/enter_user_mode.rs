@@ -5,6 +5,15 @@ use x86_64::{registers::rflags::RFlags, VirtAddr};
 /// Jumps to an unchecked address with an unchecked stack.
 /// You should handle any exceptions that happen in Ring3 and not crash the kernel because of exception in Ring3.
 pub unsafe fn enter_user_mode(code: VirtAddr, stack_end: VirtAddr) -> ! {
+    unsafe { enter_user_mode_with_input(code, stack_end, 0) }
+}
+
+/// Like [`enter_user_mode`], but `input` is passed as the first `sysv64` input
+///
+/// # Safety
+/// Jumps to an unchecked address with an unchecked stack.
+/// You should handle any exceptions that happen in Ring3 and not crash the kernel because of exception in Ring3.
+pub unsafe fn enter_user_mode_with_input(code: VirtAddr, stack_end: VirtAddr, input: u64) -> ! {
     // Based on https://wiki.osdev.org/Getting_to_Ring_3#sysret_method
     // 0x0002 should always be set
     // https://en.wikipedia.org/wiki/FLAGS_register
@@ -18,7 +27,8 @@ pub unsafe fn enter_user_mode(code: VirtAddr, stack_end: VirtAddr) -> ! {
             sysretq",
             in(reg) rsp,
             in("rcx") rip,
-            in("r11") eflags
+            in("r11") eflags,
+            in("rdi") input
         );
     }
     unreachable!()
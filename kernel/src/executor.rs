@@ -0,0 +1,97 @@
+//! Runs kernel tasks, such as drivers, with the executor from `common`.
+//! Tasks are usually woken by interrupt handlers. An executor on the main thread halts until an
+//! interrupt when no task is woken, and one on a kernel thread lets the other threads run instead.
+
+pub use common::executor::{yield_now, JoinHandle, Priority, Spawner, Wait};
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use alloc::sync::Arc;
+use x86_64::instructions::interrupts;
+
+use crate::kernel_thread::{self, SpawnError};
+
+pub type Executor = common::executor::Executor<HaltUntilInterrupt>;
+
+/// Halts until an interrupt. Interrupts are enabled or disabled afterwards like they were before.
+#[derive(Debug, Default)]
+pub struct HaltUntilInterrupt;
+
+impl Wait for HaltUntilInterrupt {
+    fn wait(&mut self, woken: &AtomicBool) {
+        // Disable interrupts so that an interrupt doesn't happen in between checking if a task
+        // was woken and halting
+        let were_enabled = interrupts::are_enabled();
+        interrupts::disable();
+        if !woken.load(Ordering::Relaxed) {
+            interrupts::enable_and_hlt();
+        }
+        if were_enabled {
+            interrupts::enable();
+        } else {
+            interrupts::disable();
+        }
+    }
+}
+
+/// Lets the other kernel threads run until a task is woken
+#[derive(Debug, Default)]
+pub struct YieldToOtherThreads;
+
+impl Wait for YieldToOtherThreads {
+    fn wait(&mut self, _woken: &AtomicBool) {
+        // A wake can't get lost, because the executor checks again after this returns
+        kernel_thread::yield_now();
+    }
+}
+
+/// Runs an executor in a new kernel thread until all of its tasks are done. `spawn_tasks` spawns the
+/// first tasks. It runs in the thread, because the executor can't be sent between threads.
+pub fn spawn_thread(
+    spawn_tasks: impl FnOnce(&Spawner) + Send + 'static,
+) -> Result<kernel_thread::JoinHandle, SpawnError> {
+    kernel_thread::spawn(move || {
+        let executor = common::executor::Executor::<YieldToOtherThreads>::new();
+        spawn_tasks(&executor.spawner());
+        executor.run();
+    })
+}
+
+pub fn test_executor() {
+    // Waiting doesn't change whether interrupts are enabled
+    for enabled in [false, true] {
+        interrupts::disable();
+        if enabled {
+            interrupts::enable();
+        }
+        HaltUntilInterrupt.wait(&AtomicBool::new(true));
+        assert_eq!(interrupts::are_enabled(), enabled);
+    }
+    interrupts::disable();
+
+    let executor = Executor::new();
+    let task = executor.spawn(async {
+        yield_now().await;
+        1
+    });
+    assert_eq!(executor.block_on(async { task.await + 1 }), 2);
+
+    // The tasks run on the thread while the main thread waits for it
+    let counter = Arc::new(AtomicU32::new(0));
+    spawn_thread({
+        let counter = counter.clone();
+        move |spawner| {
+            for _ in 0..2 {
+                let counter = counter.clone();
+                spawner.spawn(async move {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    yield_now().await;
+                    counter.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+        }
+    })
+    .unwrap()
+    .join();
+    assert_eq!(counter.load(Ordering::Relaxed), 4);
+}
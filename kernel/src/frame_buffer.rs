@@ -1,3 +1,5 @@
+use core::iter;
+
 use bootloader_api::info::{FrameBuffer, PixelFormat};
 use embedded_graphics::{
     draw_target::DrawTarget,
@@ -190,7 +192,7 @@ impl DrawTarget for Display<'_> {
                     );
                 }
             }
-            other => panic!("unknown pixel format {other:?}"),
+            _ => self.fill_contiguous(area, iter::repeat(color))?,
         };
         // log::info!("Clear called");
         Ok(())
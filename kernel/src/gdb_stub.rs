@@ -0,0 +1,594 @@
+//! A GDB remote serial protocol stub on COM2, so the kernel can be debugged on real hardware, where there is no QEMU gdbstub.
+//! Connect GDB to COM2 with `target remote`, and press Ctrl+C to stop the kernel.
+//! Until GDB connects, breakpoints just get logged.
+//! See https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::sync::Arc;
+use conquer_once::noblock::OnceCell;
+use spin::Mutex;
+use x2apic::{
+    ioapic::{IoApic, RedirectionTableEntry},
+    lapic::LocalApic,
+};
+use x86_64::{
+    registers::control::{Cr0, Cr0Flags},
+    structures::paging::{mapper::TranslateResult, OffsetPageTable, PageTableFlags, Translate},
+    VirtAddr,
+};
+
+use crate::{
+    context::{AnyContext, FullContext},
+    context_switching_interrupt_handler::{
+        context_switching_idt_entry, context_switching_interrupt_handler,
+    },
+    modules::idt::IdtBuilder,
+    pic8259_interrupts::Pic8259Interrupts,
+    uart::COM2,
+    user_space_state::JmpTo,
+};
+
+/// The biggest packet that GDB is allowed to send, which is also the biggest response
+const MAX_PACKET_LEN: usize = 1024;
+const MAX_BREAKPOINTS: usize = 32;
+/// What GDB sends to stop the target
+const INTERRUPT: u8 = 0x03;
+const INT3: u8 = 0xCC;
+/// The trap flag, which makes the CPU raise a debug exception after the next instruction
+const TRAP_FLAG: u64 = 1 << 8;
+/// rax, rbx, rcx, rdx, rsi, rdi, rbp, rsp, r8-r15, and rip
+const U64_REGISTERS: usize = 17;
+/// eflags, cs, ss, ds, es, fs, and gs
+const U32_REGISTERS: usize = 7;
+const REGISTERS_LEN: usize = U64_REGISTERS * 8 + U32_REGISTERS * 4;
+/// Always reported as the reason for stopping
+const STOP_REPLY: &[u8] = b"S05";
+
+#[derive(Debug, Clone, Copy)]
+struct Breakpoint {
+    addr: u64,
+    original_byte: u8,
+}
+
+struct Buffers {
+    packet: heapless::Vec<u8, MAX_PACKET_LEN>,
+    response: heapless::Vec<u8, MAX_PACKET_LEN>,
+}
+
+static LOCAL_APIC: OnceCell<&'static OnceCell<Mutex<LocalApic>>> = OnceCell::uninit();
+static MAPPER: OnceCell<Arc<Mutex<OffsetPageTable<'static>>>> = OnceCell::uninit();
+/// Set when GDB sends anything, and cleared when it detaches
+static ATTACHED: AtomicBool = AtomicBool::new(false);
+static BREAKPOINTS: Mutex<[Option<Breakpoint>; MAX_BREAKPOINTS]> =
+    Mutex::new([None; MAX_BREAKPOINTS]);
+/// Static so that they don't take up space on the interrupt stack. Also makes sure that the stub doesn't run inside itself.
+static BUFFERS: Mutex<Buffers> = Mutex::new(Buffers {
+    packet: heapless::Vec::new(),
+    response: heapless::Vec::new(),
+});
+
+context_switching_interrupt_handler!(
+    context_switching_breakpoint_handler,
+    context_switching_breakpoint_handler_rust
+);
+
+unsafe extern "sysv64" fn context_switching_breakpoint_handler_rust(context: *const FullContext) {
+    let mut context = unsafe { *context };
+    if ATTACHED.load(Ordering::Relaxed) {
+        // `rip` is after the `int3`. GDB knows this and moves it back when it's one of its breakpoints.
+        run(&mut context, None, true);
+    } else {
+        log::info!("EXCEPTION: BREAKPOINT\n{:#x?}", context);
+    }
+    unsafe { JmpTo::RestoreContext(AnyContext::Full(context)).jmp() };
+}
+
+context_switching_interrupt_handler!(
+    context_switching_debug_handler,
+    context_switching_debug_handler_rust
+);
+
+unsafe extern "sysv64" fn context_switching_debug_handler_rust(context: *const FullContext) {
+    let mut context = unsafe { *context };
+    // A single step finished
+    context.rflags &= !TRAP_FLAG;
+    if ATTACHED.load(Ordering::Relaxed) {
+        run(&mut context, None, true);
+    }
+    unsafe { JmpTo::RestoreContext(AnyContext::Full(context)).jmp() };
+}
+
+context_switching_interrupt_handler!(
+    context_switching_com2_interrupt_handler,
+    context_switching_com2_interrupt_handler_rust
+);
+
+unsafe extern "sysv64" fn context_switching_com2_interrupt_handler_rust(
+    context: *const FullContext,
+) {
+    let mut context = unsafe { *context };
+    let byte = COM2.try_read();
+    let mut local_apic = LOCAL_APIC.try_get().unwrap().try_get().unwrap().lock();
+    unsafe { local_apic.end_of_interrupt() };
+    drop(local_apic);
+    match byte {
+        Some(INTERRUPT) => run(&mut context, None, true),
+        // GDB sent a packet while the kernel was running, which it does when it connects
+        Some(b'$') => run(&mut context, Some(b'$'), false),
+        _ => {}
+    }
+    unsafe { JmpTo::RestoreContext(AnyContext::Full(context)).jmp() };
+}
+
+fn hex_digit(value: u8) -> u8 {
+    b"0123456789abcdef"[(value & 0xF) as usize]
+}
+
+fn parse_hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|digit| digit as u8)
+}
+
+fn parse_hex(hex: &[u8]) -> Option<u64> {
+    if hex.is_empty() || hex.len() > 16 {
+        return None;
+    }
+    hex.iter().try_fold(0, |value, digit| {
+        Some((value << 4) | parse_hex_digit(*digit)? as u64)
+    })
+}
+
+fn parse_hex_bytes(hex: &[u8]) -> impl Iterator<Item = Option<u8>> + '_ {
+    hex.chunks(2).map(|pair| match pair {
+        [high, low] => Some((parse_hex_digit(*high)? << 4) | parse_hex_digit(*low)?),
+        _ => None,
+    })
+}
+
+fn push_hex(response: &mut heapless::Vec<u8, MAX_PACKET_LEN>, bytes: &[u8]) {
+    for byte in bytes {
+        let _ = response.push(hex_digit(byte >> 4));
+        let _ = response.push(hex_digit(*byte));
+    }
+}
+
+/// Reads a packet's data into `packet` and acknowledges it. Returns `false` if GDB sent an interrupt instead.
+fn read_packet(
+    packet: &mut heapless::Vec<u8, MAX_PACKET_LEN>,
+    first_byte: &mut Option<u8>,
+) -> bool {
+    let mut next = || first_byte.take().unwrap_or_else(|| COM2.read());
+    loop {
+        match next() {
+            b'$' => {}
+            INTERRUPT => return false,
+            // Acknowledgements of our packets
+            _ => continue,
+        }
+        packet.clear();
+        let mut checksum = 0u8;
+        let mut too_long = false;
+        loop {
+            match next() {
+                b'#' => break,
+                byte => {
+                    checksum = checksum.wrapping_add(byte);
+                    too_long |= packet.push(byte).is_err();
+                }
+            }
+        }
+        let expected_checksum = parse_hex(&[next(), next()]);
+        if expected_checksum == Some(checksum as u64) && !too_long {
+            COM2.write(b'+');
+            return true;
+        }
+        // Ask GDB to send it again
+        COM2.write(b'-');
+    }
+}
+
+/// Sends a packet and waits for GDB to acknowledge it
+fn send_packet(data: &[u8]) {
+    let checksum = data
+        .iter()
+        .fold(0u8, |checksum, byte| checksum.wrapping_add(*byte));
+    loop {
+        COM2.write(b'$');
+        for byte in data {
+            COM2.write(*byte);
+        }
+        COM2.write(b'#');
+        COM2.write(hex_digit(checksum >> 4));
+        COM2.write(hex_digit(checksum));
+        if COM2.read() != b'-' {
+            break;
+        }
+    }
+}
+
+/// Returns the flags of the page that `addr` is in, or [`None`] if it isn't mapped
+fn page_flags(addr: u64) -> Option<PageTableFlags> {
+    let addr = VirtAddr::try_new(addr).ok()?;
+    match MAPPER.try_get().ok()?.try_lock()?.translate(addr) {
+        TranslateResult::Mapped { flags, .. } => Some(flags),
+        _ => None,
+    }
+}
+
+/// Checks that `len` bytes starting at `addr` don't go past the end of the address space
+fn is_valid_range(addr: u64, len: u64) -> bool {
+    len == 0 || addr.checked_add(len - 1).is_some()
+}
+
+/// Reads until the end of `buffer` or an address that isn't mapped, and returns the number of bytes read
+fn read_memory(addr: u64, buffer: &mut [u8]) -> usize {
+    buffer
+        .iter_mut()
+        .zip(addr..=u64::MAX)
+        .map_while(|(slot, addr)| {
+            page_flags(addr)?;
+            *slot = unsafe { (addr as *const u8).read_volatile() };
+            Some(())
+        })
+        .count()
+}
+
+/// Can also write to read only pages, like the kernel's code. Returns `false` if an address isn't mapped.
+fn write_memory(addr: u64, bytes: impl Iterator<Item = u8>) -> bool {
+    for (byte, addr) in bytes.zip(addr..=u64::MAX) {
+        let Some(flags) = page_flags(addr) else {
+            return false;
+        };
+        let read_only = !flags.contains(PageTableFlags::WRITABLE);
+        unsafe {
+            if read_only {
+                Cr0::update(|cr0| cr0.remove(Cr0Flags::WRITE_PROTECT));
+            }
+            (addr as *mut u8).write_volatile(byte);
+            if read_only {
+                Cr0::update(|cr0| cr0.insert(Cr0Flags::WRITE_PROTECT));
+            }
+        }
+    }
+    true
+}
+
+/// In the order that GDB expects for x86_64
+fn registers(context: &FullContext) -> ([u64; U64_REGISTERS], [u32; U32_REGISTERS]) {
+    (
+        [
+            context.rax,
+            context.rbx,
+            context.rcx,
+            context.rdx,
+            context.rsi,
+            context.rdi,
+            context.rbp,
+            context.rsp,
+            context.r8,
+            context.r9,
+            context.r10,
+            context.r11,
+            context.r12,
+            context.r13,
+            context.r14,
+            context.r15,
+            context.rip,
+        ],
+        // The data segment registers aren't saved, and are always 0 in 64-bit mode anyways
+        [
+            context.rflags as u32,
+            context.cs as u32,
+            context.ss as u32,
+            0,
+            0,
+            0,
+            0,
+        ],
+    )
+}
+
+fn set_registers(context: &mut FullContext, bytes: &[u8; REGISTERS_LEN]) {
+    let mut u64_registers = bytes[..U64_REGISTERS * 8]
+        .chunks_exact(8)
+        .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()));
+    for register in [
+        &mut context.rax,
+        &mut context.rbx,
+        &mut context.rcx,
+        &mut context.rdx,
+        &mut context.rsi,
+        &mut context.rdi,
+        &mut context.rbp,
+        &mut context.rsp,
+        &mut context.r8,
+        &mut context.r9,
+        &mut context.r10,
+        &mut context.r11,
+        &mut context.r12,
+        &mut context.r13,
+        &mut context.r14,
+        &mut context.r15,
+        &mut context.rip,
+    ] {
+        *register = u64_registers.next().unwrap();
+    }
+    let mut u32_registers = bytes[U64_REGISTERS * 8..]
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()) as u64);
+    for register in [&mut context.rflags, &mut context.cs, &mut context.ss] {
+        *register = u32_registers.next().unwrap();
+    }
+}
+
+fn insert_breakpoint(addr: u64) -> bool {
+    let mut breakpoints = BREAKPOINTS.lock();
+    if breakpoints
+        .iter()
+        .flatten()
+        .any(|breakpoint| breakpoint.addr == addr)
+    {
+        return true;
+    }
+    let Some(slot) = breakpoints.iter_mut().find(|slot| slot.is_none()) else {
+        return false;
+    };
+    let mut original_byte = 0;
+    if read_memory(addr, core::slice::from_mut(&mut original_byte)) == 0
+        || !write_memory(addr, [INT3].into_iter())
+    {
+        return false;
+    }
+    *slot = Some(Breakpoint {
+        addr,
+        original_byte,
+    });
+    true
+}
+
+fn remove_breakpoint(addr: u64) {
+    let mut breakpoints = BREAKPOINTS.lock();
+    if let Some(slot) = breakpoints
+        .iter_mut()
+        .find(|slot| slot.is_some_and(|breakpoint| breakpoint.addr == addr))
+    {
+        let breakpoint = slot.take().unwrap();
+        write_memory(breakpoint.addr, [breakpoint.original_byte].into_iter());
+    }
+}
+
+fn remove_all_breakpoints() {
+    let addrs = BREAKPOINTS
+        .lock()
+        .map(|slot| slot.map(|breakpoint| breakpoint.addr));
+    for addr in addrs.into_iter().flatten() {
+        remove_breakpoint(addr);
+    }
+}
+
+/// Handles a packet and writes the response. Returns `true` if the stub should exit and let `context` continue.
+fn handle_packet(
+    packet: &[u8],
+    response: &mut heapless::Vec<u8, MAX_PACKET_LEN>,
+    context: &mut FullContext,
+) -> bool {
+    response.clear();
+    let Some((&command, args)) = packet.split_first() else {
+        return false;
+    };
+    match command {
+        b'?' => {
+            let _ = response.extend_from_slice(STOP_REPLY);
+        }
+        b'g' => {
+            let (u64_registers, u32_registers) = registers(context);
+            for register in u64_registers {
+                push_hex(response, &register.to_le_bytes());
+            }
+            for register in u32_registers {
+                push_hex(response, &register.to_le_bytes());
+            }
+        }
+        b'G' => {
+            let mut bytes = [0; REGISTERS_LEN];
+            let parsed =
+                bytes
+                    .iter_mut()
+                    .zip(parse_hex_bytes(args))
+                    .all(|(slot, byte)| match byte {
+                        Some(byte) => {
+                            *slot = byte;
+                            true
+                        }
+                        None => false,
+                    });
+            if parsed && args.len() >= REGISTERS_LEN * 2 {
+                set_registers(context, &bytes);
+                let _ = response.extend_from_slice(b"OK");
+            } else {
+                let _ = response.extend_from_slice(b"E01");
+            }
+        }
+        b'm' => {
+            let mut args = args.split(|&byte| byte == b',');
+            match (
+                args.next().and_then(parse_hex),
+                args.next().and_then(parse_hex),
+            ) {
+                (Some(addr), Some(len)) if is_valid_range(addr, len) => {
+                    let mut buffer = [0; MAX_PACKET_LEN / 2];
+                    let len = (len as usize).min(buffer.len());
+                    match read_memory(addr, &mut buffer[..len]) {
+                        0 => {
+                            let _ = response.extend_from_slice(b"E14");
+                        }
+                        read => push_hex(response, &buffer[..read]),
+                    }
+                }
+                _ => {
+                    let _ = response.extend_from_slice(b"E01");
+                }
+            }
+        }
+        b'M' => {
+            let mut args = args.splitn(2, |&byte| byte == b':');
+            let mut addr_and_len = args.next().unwrap_or_default().split(|&byte| byte == b',');
+            match (
+                addr_and_len.next().and_then(parse_hex),
+                addr_and_len.next().and_then(parse_hex),
+                args.next(),
+            ) {
+                (Some(addr), Some(len), Some(data))
+                    if is_valid_range(addr, len)
+                        && len.checked_mul(2) == Some(data.len() as u64)
+                        && parse_hex_bytes(data).all(|byte| byte.is_some()) =>
+                {
+                    if write_memory(addr, parse_hex_bytes(data).flatten()) {
+                        let _ = response.extend_from_slice(b"OK");
+                    } else {
+                        let _ = response.extend_from_slice(b"E14");
+                    }
+                }
+                _ => {
+                    let _ = response.extend_from_slice(b"E01");
+                }
+            }
+        }
+        // Software breakpoints. Other kinds are not supported, which is an empty response.
+        b'Z' | b'z' if args.starts_with(b"0,") => {
+            match args[2..]
+                .split(|&byte| byte == b',')
+                .next()
+                .and_then(parse_hex)
+            {
+                Some(addr) => {
+                    if command == b'z' {
+                        remove_breakpoint(addr);
+                        let _ = response.extend_from_slice(b"OK");
+                    } else if insert_breakpoint(addr) {
+                        let _ = response.extend_from_slice(b"OK");
+                    } else {
+                        let _ = response.extend_from_slice(b"E01");
+                    }
+                }
+                None => {
+                    let _ = response.extend_from_slice(b"E01");
+                }
+            }
+        }
+        b'c' | b's' => {
+            if let Some(addr) = parse_hex(args) {
+                context.rip = addr;
+            }
+            if command == b's' {
+                context.rflags |= TRAP_FLAG;
+            } else {
+                context.rflags &= !TRAP_FLAG;
+            }
+            // The response is the stop reply when it stops again
+            return true;
+        }
+        b'D' | b'k' => {
+            remove_all_breakpoints();
+            context.rflags &= !TRAP_FLAG;
+            ATTACHED.store(false, Ordering::Relaxed);
+            if command == b'D' {
+                send_packet(b"OK");
+            }
+            return true;
+        }
+        b'q' if args.starts_with(b"Supported") => {
+            let _ = response.extend_from_slice(b"PacketSize=400");
+        }
+        // The kernel was already running
+        b'q' if args == b"Attached" => {
+            let _ = response.extend_from_slice(b"1");
+        }
+        _ => {}
+    }
+    false
+}
+
+/// Talks to GDB until it continues, steps, or detaches. Call this with interrupts disabled.
+/// `first_byte` is a byte that was already read from COM2. `stopped` sends a stop reply, which GDB expects after it interrupts, continues, or steps.
+fn run(context: &mut FullContext, mut first_byte: Option<u8>, stopped: bool) {
+    let Some(mut buffers) = BUFFERS.try_lock() else {
+        // A breakpoint was hit inside the stub
+        return;
+    };
+    let Buffers { packet, response } = &mut *buffers;
+    ATTACHED.store(true, Ordering::Relaxed);
+    if stopped {
+        send_packet(STOP_REPLY);
+    }
+    loop {
+        if !read_packet(packet, &mut first_byte) {
+            // GDB interrupted, but it's already stopped
+            send_packet(STOP_REPLY);
+            continue;
+        }
+        if handle_packet(packet, response, context) {
+            break;
+        }
+        send_packet(response);
+    }
+}
+
+pub struct GdbStubBuilder {
+    com2_interrupt_index: u8,
+}
+
+impl GdbStubBuilder {
+    /// Sets the breakpoint and debug exception entries, and an entry for COM2
+    pub fn set_interrupts(
+        idt_builder: &mut IdtBuilder,
+        local_apic: &'static OnceCell<Mutex<LocalApic>>,
+    ) -> Option<Self> {
+        LOCAL_APIC.try_init_once(|| local_apic).unwrap();
+        idt_builder
+            .set_breakpoint_entry(context_switching_idt_entry(
+                context_switching_breakpoint_handler,
+            ))
+            .ok()?;
+        idt_builder
+            .set_debug_entry(context_switching_idt_entry(context_switching_debug_handler))
+            .ok()?;
+        let com2_interrupt_index = idt_builder.set_flexible_entry(context_switching_idt_entry(
+            context_switching_com2_interrupt_handler,
+        ))?;
+        Some(Self {
+            com2_interrupt_index,
+        })
+    }
+
+    /// Starts listening for GDB on COM2. `mapper` is used to check addresses before GDB reads or writes them.
+    pub fn configure_io_apic(
+        &'static self,
+        io_apic: Arc<Mutex<IoApic>>,
+        mapper: Arc<Mutex<OffsetPageTable<'static>>>,
+    ) {
+        MAPPER.try_init_once(|| mapper).unwrap();
+        COM2.init();
+        let mut io_apic = io_apic.lock();
+        unsafe {
+            io_apic.set_table_entry(Pic8259Interrupts::Com2.into(), {
+                let mut entry = RedirectionTableEntry::default();
+                entry.set_vector(self.com2_interrupt_index);
+                entry
+            });
+            io_apic.enable_irq(Pic8259Interrupts::Com2.into());
+        }
+        COM2.enable_receive_interrupt();
+    }
+}
+
+pub fn test_memory_ranges() {
+    assert!(is_valid_range(0, 0));
+    assert!(is_valid_range(u64::MAX, 0));
+    assert!(is_valid_range(u64::MAX, 1));
+    assert!(is_valid_range(u64::MAX - 1, 2));
+    assert!(!is_valid_range(u64::MAX, 2));
+    assert!(!is_valid_range(1, u64::MAX));
+    // Reading at the end of the address space stops instead of overflowing
+    assert!(read_memory(u64::MAX, &mut [0; 2]) <= 1);
+}
@@ -0,0 +1,224 @@
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+use common::handle::{initial_handles, Handle, HandleError, Rights};
+use x86_64::structures::paging::PhysFrame;
+
+use crate::channels::ChannelEndpoint;
+
+/// Frames that can be mapped by multiple processes
+// FIXME: The frames are never deallocated. Closing the last handle doesn't unmap the memory from the processes
+// that mapped it, so the frames can only be freed once the kernel keeps track of the mappings and unmaps them.
+#[derive(Debug)]
+pub struct SharedMemory {
+    pub frames: Vec<PhysFrame>,
+}
+
+/// Something in the kernel that a process can have a handle to.
+/// Objects that can be dropped are reference counted, so they stay alive as long as there is a handle to them.
+#[derive(Debug, Clone)]
+pub enum KernelObject {
+    Process,
+    FrameBuffer,
+    Keyboard,
+    RealTimeClock,
+    Mouse,
+    Serial,
+    SharedMemory(Arc<SharedMemory>),
+    ChannelEndpoint(Arc<ChannelEndpoint>),
+}
+
+impl KernelObject {
+    /// Returns the channel endpoint if this was the last reference to it, which means that the endpoint should be closed
+    pub fn into_last_channel_endpoint(self) -> Option<ChannelEndpoint> {
+        match self {
+            Self::ChannelEndpoint(endpoint) => Arc::into_inner(endpoint),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HandleEntry {
+    pub object: KernelObject,
+    pub rights: Rights,
+}
+
+/// Each process has its own handle table, so a handle is only valid in the process that it was given to
+#[derive(Debug)]
+pub struct HandleTable {
+    entries: BTreeMap<Handle, HandleEntry>,
+    next_handle: u32,
+}
+
+impl HandleTable {
+    /// The handle table of the first process, which gets access to all of the devices.
+    /// The handles match [`initial_handles`].
+    pub fn new_with_initial_handles() -> Self {
+        let mut handle_table = Self {
+            entries: Default::default(),
+            next_handle: 0,
+        };
+        for (object, handle) in [
+            (KernelObject::Process, initial_handles::PROCESS),
+            (KernelObject::FrameBuffer, initial_handles::FRAME_BUFFER),
+            (KernelObject::Keyboard, initial_handles::KEYBOARD),
+            (
+                KernelObject::RealTimeClock,
+                initial_handles::REAL_TIME_CLOCK,
+            ),
+            (KernelObject::Mouse, initial_handles::MOUSE),
+            (KernelObject::Serial, initial_handles::SERIAL),
+        ] {
+            assert_eq!(
+                handle_table.insert(HandleEntry {
+                    object,
+                    rights: Rights::ALL,
+                }),
+                handle
+            );
+        }
+        handle_table
+    }
+
+    pub fn insert(&mut self, entry: HandleEntry) -> Handle {
+        let handle = Handle(self.next_handle);
+        self.next_handle += 1;
+        self.entries.insert(handle, entry);
+        handle
+    }
+
+    /// Makes sure that the handle has all of `rights`
+    pub fn get(&self, handle: Handle, rights: Rights) -> Result<&HandleEntry, HandleError> {
+        let entry = self
+            .entries
+            .get(&handle)
+            .ok_or(HandleError::InvalidHandle)?;
+        if entry.rights.contains(rights) {
+            Ok(entry)
+        } else {
+            Err(HandleError::MissingRights)
+        }
+    }
+
+    /// Makes sure that the handle has all of `rights`, and then removes it
+    pub fn remove(&mut self, handle: Handle, rights: Rights) -> Result<HandleEntry, HandleError> {
+        self.get(handle, rights)?;
+        Ok(self.entries.remove(&handle).unwrap())
+    }
+
+    pub fn duplicate(&mut self, handle: Handle, rights: Rights) -> Result<Handle, HandleError> {
+        let entry = self.get(handle, Rights::DUPLICATE)?;
+        if !entry.rights.contains(rights) {
+            return Err(HandleError::MissingRights);
+        }
+        let entry = HandleEntry {
+            object: entry.object.clone(),
+            rights,
+        };
+        Ok(self.insert(entry))
+    }
+
+    fn get_object<T>(
+        &self,
+        handle: Handle,
+        rights: Rights,
+        f: impl FnOnce(&KernelObject) -> Option<T>,
+    ) -> Result<T, HandleError> {
+        f(&self.get(handle, rights)?.object).ok_or(HandleError::WrongObjectType)
+    }
+
+    pub fn process(&self, handle: Handle, rights: Rights) -> Result<(), HandleError> {
+        self.get_object(handle, rights, |object| match object {
+            KernelObject::Process => Some(()),
+            _ => None,
+        })
+    }
+
+    pub fn frame_buffer(&self, handle: Handle, rights: Rights) -> Result<(), HandleError> {
+        self.get_object(handle, rights, |object| match object {
+            KernelObject::FrameBuffer => Some(()),
+            _ => None,
+        })
+    }
+
+    pub fn keyboard(&self, handle: Handle, rights: Rights) -> Result<(), HandleError> {
+        self.get_object(handle, rights, |object| match object {
+            KernelObject::Keyboard => Some(()),
+            _ => None,
+        })
+    }
+
+    pub fn real_time_clock(&self, handle: Handle, rights: Rights) -> Result<(), HandleError> {
+        self.get_object(handle, rights, |object| match object {
+            KernelObject::RealTimeClock => Some(()),
+            _ => None,
+        })
+    }
+
+    pub fn mouse(&self, handle: Handle, rights: Rights) -> Result<(), HandleError> {
+        self.get_object(handle, rights, |object| match object {
+            KernelObject::Mouse => Some(()),
+            _ => None,
+        })
+    }
+
+    pub fn serial(&self, handle: Handle, rights: Rights) -> Result<(), HandleError> {
+        self.get_object(handle, rights, |object| match object {
+            KernelObject::Serial => Some(()),
+            _ => None,
+        })
+    }
+
+    /// Also returns all of the handle's rights, because they decide how the memory gets mapped
+    pub fn shared_memory(
+        &self,
+        handle: Handle,
+        rights: Rights,
+    ) -> Result<(Arc<SharedMemory>, Rights), HandleError> {
+        let shared_memory = self.get_object(handle, rights, |object| match object {
+            KernelObject::SharedMemory(shared_memory) => Some(shared_memory.clone()),
+            _ => None,
+        })?;
+        Ok((shared_memory, self.entries[&handle].rights))
+    }
+
+    pub fn channel_endpoint(
+        &self,
+        handle: Handle,
+        rights: Rights,
+    ) -> Result<Arc<ChannelEndpoint>, HandleError> {
+        self.get_object(handle, rights, |object| match object {
+            KernelObject::ChannelEndpoint(endpoint) => Some(endpoint.clone()),
+            _ => None,
+        })
+    }
+}
+
+pub fn test_handle_rights() {
+    let mut handle_table = HandleTable::new_with_initial_handles();
+    assert_eq!(
+        handle_table.keyboard(initial_handles::KEYBOARD, Rights::READ),
+        Ok(())
+    );
+    assert_eq!(
+        handle_table.mouse(initial_handles::KEYBOARD, Rights::READ),
+        Err(HandleError::WrongObjectType)
+    );
+    let read_only = handle_table
+        .duplicate(initial_handles::KEYBOARD, Rights::READ)
+        .unwrap();
+    assert_eq!(handle_table.keyboard(read_only, Rights::READ), Ok(()));
+    assert_eq!(
+        handle_table.keyboard(read_only, Rights::WRITE),
+        Err(HandleError::MissingRights)
+    );
+    // Duplicating needs the duplicate right
+    assert_eq!(
+        handle_table.duplicate(read_only, Rights::READ),
+        Err(HandleError::MissingRights)
+    );
+    handle_table.remove(read_only, Rights::NONE).unwrap();
+    assert_eq!(
+        handle_table.keyboard(read_only, Rights::READ),
+        Err(HandleError::InvalidHandle)
+    );
+}
@@ -0,0 +1,613 @@
+//! Kernel threads run kernel code on their own stacks, so that long-running work, like flushing logs
+//! or disk I/O, doesn't have to happen inside interrupt handlers.
+//!
+//! Threads are scheduled round-robin with the main thread, which is everything that isn't a kernel
+//! thread: the boot code, the user space process and its syscalls. Context switching interrupt
+//! handlers go through [`on_interrupt`], which switches threads on timer interrupts and
+//! [`yield_now`], and switches to the main thread right away when an interrupt gives user space
+//! something to do.
+//!
+//! The main thread can't wait for a thread that was switched away from while holding a lock, because
+//! syscalls and interrupt handlers run with interrupts disabled. So locks that threads share with
+//! the main thread or with interrupt handlers are only held with interrupts disabled. The heap and
+//! the logger do this themselves, and [`spawn`] does it for the page tables.
+
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use conquer_once::noblock::OnceCell;
+use spin::Mutex;
+use x86_64::{
+    instructions::interrupts::without_interrupts,
+    registers::{
+        rflags::RFlags,
+        segmentation::{Segment, CS, SS},
+    },
+    structures::paging::{
+        mapper::MapToError, page::PageRange, FrameAllocator, Mapper, OffsetPageTable, Page,
+        PageSize, PageTableFlags, Size4KiB, Translate,
+    },
+    VirtAddr,
+};
+
+use crate::{
+    context::{AnyContext, FullContext},
+    context_switching_interrupt_handler::{
+        context_switching_idt_entry, context_switching_interrupt_handler,
+    },
+    memory::BootInfoFrameAllocator,
+    modules::idt::IdtBuilder,
+    test_framework,
+    user_space_state::JmpTo,
+    virt_mem_tracker::VirtMemTracker,
+};
+
+pub const MAX_KERNEL_THREADS: usize = 16;
+/// Not including the guard page
+const STACK_SIZE: u64 = 0x10000;
+/// The software interrupt that [`yield_now`] uses
+const YIELD_INTERRUPT_INDEX: u8 = 0xFE;
+
+type Entry = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug)]
+pub enum SpawnError {
+    TooManyThreads,
+    /// There is no free virtual memory for the stack
+    NoVirtMem,
+    Map(MapToError<Size4KiB>),
+}
+
+/// A stack with an unmapped guard page below it, so that overflowing it faults instead of
+/// overwriting other memory
+#[derive(Debug)]
+pub struct KernelStack {
+    guard_page: Page,
+}
+
+impl KernelStack {
+    pub fn allocate(
+        mapper: &mut OffsetPageTable,
+        frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+        virt_mem_tracker: &mut VirtMemTracker,
+    ) -> Result<Self, SpawnError> {
+        let page_count = STACK_SIZE / Size4KiB::SIZE;
+        // The guard page is allocated so that nothing else gets mapped there, but it's never mapped
+        let guard_page = virt_mem_tracker
+            .allocate_pages::<Size4KiB>(page_count + 1)
+            .ok_or(SpawnError::NoVirtMem)?;
+        let stack = Self { guard_page };
+        for page in stack.pages() {
+            let frame = frame_allocator
+                .allocate_frame()
+                .ok_or(SpawnError::Map(MapToError::FrameAllocationFailed))?;
+            unsafe {
+                mapper.map_to(
+                    page,
+                    frame,
+                    PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+                    frame_allocator,
+                )
+            }
+            .map_err(SpawnError::Map)?
+            .flush();
+        }
+        Ok(stack)
+    }
+
+    pub fn guard_page(&self) -> Page {
+        self.guard_page
+    }
+
+    pub fn pages(&self) -> PageRange {
+        Page::range(
+            self.guard_page + 1,
+            self.guard_page + 1 + STACK_SIZE / Size4KiB::SIZE,
+        )
+    }
+
+    pub fn end(&self) -> VirtAddr {
+        self.pages().end.start_address()
+    }
+}
+
+#[derive(Debug)]
+struct KernelThread {
+    /// Only up to date while the thread isn't running
+    context: FullContext,
+    stack: KernelStack,
+    /// The thread is done, but is still on its stack until the scheduler switches away from it
+    finished: bool,
+}
+
+#[derive(Debug)]
+enum Slot {
+    /// Keeps the stack of a finished thread, so that the next thread can use it
+    Free(Option<KernelStack>),
+    /// A thread is being spawned in this slot
+    Reserved,
+    Thread(KernelThread),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Running {
+    Main,
+    Thread {
+        index: usize,
+        /// Where the main thread continues
+        main_context: FullContext,
+    },
+}
+
+#[derive(Debug)]
+pub struct Scheduler {
+    slots: [Slot; MAX_KERNEL_THREADS],
+    running: Running,
+    /// The main thread continues round-robin from this slot
+    next_index: usize,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs the thread's closure. The initial context of every thread starts here, with the closure in `rdi`.
+extern "sysv64" fn thread_start(entry: *mut Entry) -> ! {
+    let entry = *unsafe { Box::from_raw(entry) };
+    entry();
+    exit()
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            slots: [const { Slot::Free(None) }; MAX_KERNEL_THREADS],
+            running: Running::Main,
+            next_index: 0,
+        }
+    }
+
+    /// Reserves a slot for a new thread, and returns its index and the stack of the thread that
+    /// used it before, if there was one
+    pub fn reserve(&mut self) -> Result<(usize, Option<KernelStack>), SpawnError> {
+        let free_slots = || {
+            self.slots
+                .iter()
+                .enumerate()
+                .filter(|(_, slot)| matches!(slot, Slot::Free(_)))
+        };
+        // Reusing a stack is better than allocating a new one
+        let index = free_slots()
+            .find(|(_, slot)| matches!(slot, Slot::Free(Some(_))))
+            .or_else(|| free_slots().next())
+            .map(|(index, _)| index)
+            .ok_or(SpawnError::TooManyThreads)?;
+        match core::mem::replace(&mut self.slots[index], Slot::Reserved) {
+            Slot::Free(stack) => Ok((index, stack)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Frees a slot that was reserved with [`Scheduler::reserve`] without starting a thread
+    pub fn release(&mut self, index: usize) {
+        assert!(matches!(self.slots[index], Slot::Reserved));
+        self.slots[index] = Slot::Free(None);
+    }
+
+    /// Starts a thread in a slot that was reserved with [`Scheduler::reserve`]. It runs when the
+    /// scheduler switches to it.
+    pub fn start(&mut self, index: usize, stack: KernelStack, entry: Box<Entry>) {
+        assert!(matches!(self.slots[index], Slot::Reserved));
+        // Set up the stack as if `thread_start` was called, with a null return address
+        let rsp = stack.end() - size_of::<u64>() as u64;
+        unsafe { rsp.as_mut_ptr::<u64>().write(0) };
+        let context = FullContext {
+            // Ends backtraces
+            rbp: 0,
+            rdi: Box::into_raw(entry) as u64,
+            rip: thread_start as usize as u64,
+            cs: CS::get_reg().0.into(),
+            // 0x0002 is reserved and always set
+            rflags: RFlags::INTERRUPT_FLAG.bits() | 0x0002,
+            rsp: rsp.as_u64(),
+            ss: SS::get_reg().0.into(),
+            ..Default::default()
+        };
+        self.slots[index] = Slot::Thread(KernelThread {
+            context,
+            stack,
+            finished: false,
+        });
+    }
+
+    /// Call this from a thread that is done. The scheduler switches away from it on the next interrupt.
+    pub fn finish_current(&mut self) {
+        match self.running {
+            Running::Thread { index, .. } => self.thread_mut(index).finished = true,
+            Running::Main => panic!("the main thread can't finish"),
+        }
+    }
+
+    fn thread_mut(&mut self, index: usize) -> &mut KernelThread {
+        match &mut self.slots[index] {
+            Slot::Thread(thread) => thread,
+            slot => panic!("slot {index} has no thread: {slot:?}"),
+        }
+    }
+
+    /// The first thread that can run, starting from `start`
+    fn next_thread(&self, start: usize) -> Option<usize> {
+        (start..MAX_KERNEL_THREADS).find(|&index| {
+            matches!(
+                self.slots[index],
+                Slot::Thread(KernelThread {
+                    finished: false,
+                    ..
+                })
+            )
+        })
+    }
+
+    fn switch_to_thread(&mut self, index: usize, main_context: FullContext) -> JmpTo {
+        self.running = Running::Thread {
+            index,
+            main_context,
+        };
+        JmpTo::RestoreContext(AnyContext::Full(self.thread_mut(index).context))
+    }
+
+    /// `main` gets the context of the main thread and returns where the main thread continues, like
+    /// an interrupt handler without kernel threads does. If `preempt` is `true`, the next thread
+    /// gets to run.
+    pub fn on_interrupt(
+        &mut self,
+        interrupted: FullContext,
+        preempt: bool,
+        main: impl FnOnce(FullContext) -> JmpTo,
+    ) -> JmpTo {
+        match self.running {
+            Running::Main => {
+                let jmp_to = main(interrupted);
+                match jmp_to {
+                    // Only the main thread's contexts that can be restored later can be preempted
+                    JmpTo::RestoreContext(AnyContext::Full(main_context)) if preempt => {
+                        match self
+                            .next_thread(self.next_index)
+                            .or_else(|| self.next_thread(0))
+                        {
+                            Some(index) => self.switch_to_thread(index, main_context),
+                            None => jmp_to,
+                        }
+                    }
+                    jmp_to => jmp_to,
+                }
+            }
+            Running::Thread {
+                index,
+                main_context,
+            } => {
+                let thread = self.thread_mut(index);
+                thread.context = interrupted;
+                let finished = thread.finished;
+                let jmp_to = main(main_context);
+                if finished {
+                    // Nothing runs on the stack after the switch, so the next thread can use it
+                    let Slot::Thread(thread) =
+                        core::mem::replace(&mut self.slots[index], Slot::Free(None))
+                    else {
+                        unreachable!()
+                    };
+                    self.slots[index] = Slot::Free(Some(thread.stack));
+                }
+                match jmp_to {
+                    JmpTo::RestoreContext(AnyContext::Full(context)) if context == main_context => {
+                        if finished || preempt {
+                            match self.next_thread(index + 1) {
+                                Some(next_index) => self.switch_to_thread(next_index, main_context),
+                                None => {
+                                    self.running = Running::Main;
+                                    self.next_index = 0;
+                                    jmp_to
+                                }
+                            }
+                        } else {
+                            JmpTo::RestoreContext(AnyContext::Full(interrupted))
+                        }
+                    }
+                    // The main thread has something to do, so it runs first and this thread continues after it
+                    jmp_to => {
+                        self.running = Running::Main;
+                        self.next_index = index;
+                        jmp_to
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct KernelThreads {
+    scheduler: Mutex<Scheduler>,
+    mapper: Arc<Mutex<OffsetPageTable<'static>>>,
+    frame_allocator: Arc<Mutex<BootInfoFrameAllocator>>,
+    virt_mem_tracker: Arc<Mutex<VirtMemTracker>>,
+}
+
+static KERNEL_THREADS: OnceCell<KernelThreads> = OnceCell::uninit();
+
+/// Threads can be spawned once this is called. They start running on the next timer interrupt or
+/// [`yield_now`].
+pub fn init(
+    mapper: Arc<Mutex<OffsetPageTable<'static>>>,
+    frame_allocator: Arc<Mutex<BootInfoFrameAllocator>>,
+    virt_mem_tracker: Arc<Mutex<VirtMemTracker>>,
+) {
+    KERNEL_THREADS
+        .try_init_once(|| KernelThreads {
+            scheduler: Default::default(),
+            mapper,
+            frame_allocator,
+            virt_mem_tracker,
+        })
+        .unwrap();
+}
+
+context_switching_interrupt_handler!(
+    context_switching_yield_interrupt_handler,
+    context_switching_yield_interrupt_handler_rust
+);
+
+unsafe extern "sysv64" fn context_switching_yield_interrupt_handler_rust(
+    context: *const FullContext,
+) {
+    let context = unsafe { *context };
+    let jmp_to = on_interrupt(context, true, |context| {
+        JmpTo::RestoreContext(AnyContext::Full(context))
+    });
+    unsafe { jmp_to.jmp() };
+}
+
+#[allow(clippy::result_unit_err)]
+pub fn set_yield_interrupt(idt_builder: &mut IdtBuilder) -> Result<(), ()> {
+    idt_builder.set_fixed_entry(
+        YIELD_INTERRUPT_INDEX,
+        context_switching_idt_entry(context_switching_yield_interrupt_handler),
+    )
+}
+
+/// Lets the next thread run, like a timer interrupt does. Works with interrupts disabled.
+/// The interrupt has to be set with [`set_yield_interrupt`].
+pub fn yield_now() {
+    unsafe { asm!("int {}", const YIELD_INTERRUPT_INDEX) };
+}
+
+/// Can be dropped to detach the thread
+#[derive(Debug)]
+pub struct JoinHandle {
+    finished: Arc<AtomicBool>,
+}
+
+impl JoinHandle {
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+
+    /// Yields until the thread is done
+    pub fn join(self) {
+        while !self.is_finished() {
+            yield_now();
+        }
+    }
+}
+
+/// Runs `f` in a new kernel thread. Don't spawn in interrupt handlers, because the interrupted code
+/// could be holding the page table locks.
+pub fn spawn(f: impl FnOnce() + Send + 'static) -> Result<JoinHandle, SpawnError> {
+    let kernel_threads = KERNEL_THREADS
+        .try_get()
+        .expect("kernel threads are not initialized");
+    let finished = Arc::new(AtomicBool::new(false));
+    let entry: Box<Entry> = Box::new(Box::new({
+        let finished = finished.clone();
+        move || {
+            f();
+            finished.store(true, Ordering::Release);
+        }
+    }));
+    let (index, stack) = without_interrupts(|| kernel_threads.scheduler.lock().reserve())?;
+    let stack = match stack {
+        Some(stack) => stack,
+        None => without_interrupts(|| {
+            KernelStack::allocate(
+                &mut kernel_threads.mapper.lock(),
+                &mut *kernel_threads.frame_allocator.lock(),
+                &mut kernel_threads.virt_mem_tracker.lock(),
+            )
+        })
+        .inspect_err(|_| without_interrupts(|| kernel_threads.scheduler.lock().release(index)))?,
+    };
+    without_interrupts(|| kernel_threads.scheduler.lock().start(index, stack, entry));
+    Ok(JoinHandle { finished })
+}
+
+/// Ends the current kernel thread. Returning from the thread's closure does the same thing.
+pub fn exit() -> ! {
+    let kernel_threads = KERNEL_THREADS.try_get().unwrap();
+    without_interrupts(|| kernel_threads.scheduler.lock().finish_current());
+    loop {
+        // The scheduler never switches back to a finished thread
+        yield_now();
+    }
+}
+
+/// Call this from context switching interrupt handlers. See [`Scheduler::on_interrupt`].
+/// Before [`init`] is called, this just calls `main` with `interrupted`.
+pub fn on_interrupt(
+    interrupted: FullContext,
+    preempt: bool,
+    main: impl FnOnce(FullContext) -> JmpTo,
+) -> JmpTo {
+    match KERNEL_THREADS.try_get() {
+        Ok(kernel_threads) => {
+            kernel_threads
+                .scheduler
+                .lock()
+                .on_interrupt(interrupted, preempt, main)
+        }
+        Err(_) => main(interrupted),
+    }
+}
+
+pub fn test_kernel_stack() {
+    let resources = test_framework::resources();
+    let stack = KernelStack::allocate(
+        &mut resources.mapper.lock(),
+        &mut *resources.frame_allocator.lock(),
+        &mut resources.virt_mem_tracker.lock(),
+    )
+    .unwrap();
+    let mapper = resources.mapper.lock();
+    assert_eq!(
+        mapper.translate_addr(stack.guard_page().start_address()),
+        None
+    );
+    assert_eq!(stack.pages().start, stack.guard_page() + 1);
+    for page in stack.pages() {
+        assert!(mapper.translate_addr(page.start_address()).is_some());
+    }
+    assert!(stack.end().is_aligned(16u64));
+    // Both ends of the stack are writable
+    for addr in [stack.pages().start.start_address(), stack.end() - 8u64] {
+        unsafe { addr.as_mut_ptr::<u64>().write_volatile(0xC0FFEE) };
+    }
+}
+
+pub fn test_scheduler() {
+    fn restored(jmp_to: JmpTo) -> FullContext {
+        match jmp_to {
+            JmpTo::RestoreContext(AnyContext::Full(context)) => context,
+            jmp_to => panic!("expected a full context to be restored, got {jmp_to:?}"),
+        }
+    }
+    let restore = |context| JmpTo::RestoreContext(AnyContext::Full(context));
+
+    let resources = test_framework::resources();
+    let mut scheduler = Scheduler::new();
+    let mut stack_ends = [VirtAddr::zero(); 2];
+    for stack_end in &mut stack_ends {
+        let (index, stack) = scheduler.reserve().unwrap();
+        assert!(stack.is_none());
+        let stack = KernelStack::allocate(
+            &mut resources.mapper.lock(),
+            &mut *resources.frame_allocator.lock(),
+            &mut resources.virt_mem_tracker.lock(),
+        )
+        .unwrap();
+        *stack_end = stack.end();
+        // The threads never actually run
+        scheduler.start(index, stack, Box::new(Box::new(|| {})));
+    }
+    let main = FullContext {
+        rip: 1,
+        ..Default::default()
+    };
+
+    // Other interrupts don't switch threads
+    assert_eq!(restored(scheduler.on_interrupt(main, false, restore)), main);
+    // The threads start at `thread_start`
+    let thread_0 = restored(scheduler.on_interrupt(main, true, restore));
+    assert_eq!(thread_0.rip, thread_start as usize as u64);
+    assert_eq!(thread_0.rsp, stack_ends[0].as_u64() - 8);
+    let thread_0 = FullContext { rip: 2, ..thread_0 };
+    let thread_1 = restored(scheduler.on_interrupt(thread_0, true, |context| {
+        assert_eq!(context, main);
+        restore(context)
+    }));
+    assert_eq!(thread_1.rsp, stack_ends[1].as_u64() - 8);
+    let thread_1 = FullContext { rip: 3, ..thread_1 };
+    assert_eq!(
+        restored(scheduler.on_interrupt(thread_1, true, restore)),
+        main
+    );
+    assert_eq!(
+        restored(scheduler.on_interrupt(main, true, restore)),
+        thread_0
+    );
+
+    // The main thread runs right away when it has something to do, and then the thread continues
+    let event = FullContext {
+        rip: 4,
+        ..Default::default()
+    };
+    assert_eq!(
+        restored(scheduler.on_interrupt(thread_0, false, |_| restore(event))),
+        event
+    );
+    assert_eq!(
+        restored(scheduler.on_interrupt(event, true, restore)),
+        thread_0
+    );
+
+    // A finished thread's stack is used by the next thread
+    scheduler.finish_current();
+    assert_eq!(
+        restored(scheduler.on_interrupt(thread_0, false, |context| {
+            assert_eq!(context, event);
+            restore(context)
+        })),
+        thread_1
+    );
+    let (_, stack) = scheduler.reserve().unwrap();
+    assert_eq!(stack.unwrap().end(), stack_ends[0]);
+}
+
+pub fn test_spawn_and_join() {
+    let counter = Arc::new(AtomicU32::new(0));
+    let handles = (0..2)
+        .map(|_| {
+            let counter = counter.clone();
+            spawn(move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+                yield_now();
+                counter.fetch_add(1, Ordering::Relaxed);
+            })
+            .unwrap()
+        })
+        .collect::<Vec<_>>();
+    // Nothing runs until the main thread yields
+    assert_eq!(counter.load(Ordering::Relaxed), 0);
+    for handle in handles {
+        handle.join();
+    }
+    assert_eq!(counter.load(Ordering::Relaxed), 4);
+
+    // The stacks of the finished threads are reused, so no stack is allocated for a new thread
+    let free_stacks = || {
+        without_interrupts(|| {
+            KERNEL_THREADS
+                .try_get()
+                .unwrap()
+                .scheduler
+                .lock()
+                .slots
+                .iter()
+                .filter(|slot| matches!(slot, Slot::Free(Some(_))))
+                .count()
+        })
+    };
+    let stacks_before = free_stacks();
+    assert!(stacks_before >= 2);
+    let counter_clone = counter.clone();
+    spawn(move || {
+        counter_clone.fetch_add(1, Ordering::Relaxed);
+    })
+    .unwrap()
+    .join();
+    assert_eq!(free_stacks(), stacks_before);
+    assert_eq!(counter.load(Ordering::Relaxed), 5);
+}
@@ -0,0 +1,181 @@
+use core::{
+    arch::x86_64::_rdtsc,
+    hint::spin_loop,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+use alloc::sync::Arc;
+use common::syscall_timer::NANOS_PER_MILLI;
+use conquer_once::noblock::OnceCell;
+use spin::Mutex;
+use x2apic::lapic::{LocalApic, TimerDivide, TimerMode};
+use x86_64::instructions::{interrupts, port::Port};
+
+use crate::{
+    context::{AnyContext, FullContext},
+    context_switching_interrupt_handler::{
+        context_switching_idt_entry, context_switching_interrupt_handler,
+    },
+    kernel_thread,
+    modules::idt::IdtBuilder,
+    time_page,
+    user_space_state::{JmpTo, State},
+};
+
+/// The timer interrupts once every tick
+const NANOS_PER_TICK: u64 = NANOS_PER_MILLI;
+/// How long to count LAPIC timer ticks for while calibrating
+const CALIBRATION_MILLIS: u32 = 10;
+/// https://wiki.osdev.org/Programmable_Interval_Timer
+const PIT_FREQUENCY: u32 = 1_193_182;
+
+static LOCAL_APIC: OnceCell<&'static OnceCell<Mutex<LocalApic>>> = OnceCell::uninit();
+static STATE: OnceCell<Arc<Mutex<State>>> = OnceCell::uninit();
+/// Ticks since the timer was started
+static TICKS: AtomicU64 = AtomicU64::new(0);
+/// The LAPIC timer's initial count, which is the number of LAPIC timer counts per tick
+static COUNTS_PER_TICK: AtomicU32 = AtomicU32::new(0);
+/// Measured while calibrating. Assumes that the TSC is invariant.
+static TSC_PER_MILLI: AtomicU64 = AtomicU64::new(0);
+/// Used to make sure that the time never goes backwards
+static LAST_TIME: AtomicU64 = AtomicU64::new(0);
+
+context_switching_interrupt_handler!(
+    context_switching_timer_interrupt_handler,
+    context_switching_timer_interrupt_handler_rust
+);
+
+unsafe extern "sysv64" fn context_switching_timer_interrupt_handler_rust(
+    context: *const FullContext,
+) {
+    let context = unsafe { *context };
+    // Make sure to drop all locks before exiting
+    let jmp_to = {
+        TICKS.fetch_add(1, Ordering::Relaxed);
+        let mut local_apic = LOCAL_APIC.try_get().unwrap().try_get().unwrap().lock();
+        unsafe { local_apic.end_of_interrupt() };
+        drop(local_apic);
+
+        let now = monotonic_time();
+        time_page::update(now);
+        // Every tick is a time slice
+        kernel_thread::on_interrupt(context, true, |context| {
+            match STATE.try_get().unwrap().lock().as_mut() {
+                Some(user_space_state) => user_space_state.on_timer_interrupt(now, context),
+                None => JmpTo::RestoreContext(AnyContext::Full(context)),
+            }
+        })
+    };
+    unsafe { jmp_to.jmp() };
+}
+
+/// Nanoseconds since the timer was started. It has the precision of the LAPIC timer, even though it only interrupts once every tick.
+/// It can be called with interrupts enabled, like from kernel threads.
+pub fn monotonic_time() -> u64 {
+    let counts_per_tick = COUNTS_PER_TICK.load(Ordering::Relaxed);
+    if counts_per_tick == 0 {
+        // The timer isn't started
+        return 0;
+    }
+    // Interrupt handlers also lock the LAPIC, so an interrupt while it is locked here would deadlock.
+    // This also keeps the timer from interrupting between reading `TICKS` and the current count.
+    let (ticks, current_count) = interrupts::without_interrupts(|| {
+        let ticks = TICKS.load(Ordering::Relaxed);
+        let current_count = unsafe {
+            LOCAL_APIC
+                .try_get()
+                .unwrap()
+                .try_get()
+                .unwrap()
+                .lock()
+                .timer_current()
+        };
+        (ticks, current_count)
+    });
+    let counts_in_tick = counts_per_tick.saturating_sub(current_count) as u64;
+    let time = ticks * NANOS_PER_TICK + counts_in_tick * NANOS_PER_TICK / counts_per_tick as u64;
+    // The timer can reload before the interrupt that increments `TICKS` is handled, which would make the time go backwards
+    LAST_TIME.fetch_max(time, Ordering::Relaxed).max(time)
+}
+
+pub fn tsc_per_milli() -> u64 {
+    TSC_PER_MILLI.load(Ordering::Relaxed)
+}
+
+/// Counts how many LAPIC timer counts and TSC ticks happen in [`CALIBRATION_MILLIS`], using the PIT's channel 2, which doesn't need an interrupt
+///
+/// # Safety
+/// Uses the PIT and the LAPIC timer. Nothing else can be using them.
+unsafe fn calibrate(local_apic: &mut LocalApic) -> (u32, u64) {
+    // Bit 0 is channel 2's gate, bit 1 connects channel 2 to the PC speaker, and bit 5 is channel 2's output
+    let mut control_port = Port::<u8>::new(0x61);
+    let mut command_port = Port::<u8>::new(0x43);
+    let mut channel_2_port = Port::<u8>::new(0x42);
+    let pit_count = PIT_FREQUENCY * CALIBRATION_MILLIS / 1000;
+    unsafe {
+        // Stop channel 2 and disconnect the speaker
+        let control = control_port.read() & !0b11;
+        control_port.write(control);
+        // Channel 2, lobyte/hibyte, mode 0 (interrupt on terminal count), binary
+        command_port.write(0b1011_0000);
+        channel_2_port.write(pit_count as u8);
+        channel_2_port.write((pit_count >> 8) as u8);
+
+        local_apic.set_timer_divide(TimerDivide::Div16);
+        local_apic.set_timer_mode(TimerMode::OneShot);
+        // Start both at the same time
+        local_apic.set_timer_initial(u32::MAX);
+        let start_tsc = _rdtsc();
+        control_port.write(control | 0b1);
+        while control_port.read() & 0b10_0000 == 0 {
+            spin_loop();
+        }
+        let counts = u32::MAX - local_apic.timer_current();
+        let tsc = _rdtsc() - start_tsc;
+        local_apic.set_timer_initial(0);
+        (counts, tsc)
+    }
+}
+
+pub struct LapicTimerBuilder {
+    interrupt_index: u8,
+}
+
+impl LapicTimerBuilder {
+    pub fn set_interrupt(
+        idt_builder: &mut IdtBuilder,
+        local_apic: &'static OnceCell<Mutex<LocalApic>>,
+    ) -> Option<Self> {
+        LOCAL_APIC.try_init_once(|| local_apic).unwrap();
+        let interrupt_index = idt_builder.set_flexible_entry(context_switching_idt_entry(
+            context_switching_timer_interrupt_handler,
+        ))?;
+        Some(Self { interrupt_index })
+    }
+
+    /// The local APIC's timer vector must be set to this
+    pub fn interrupt_index(&self) -> u8 {
+        self.interrupt_index
+    }
+
+    /// Calibrates the timer and starts interrupting once every tick. Call this after the local APIC is stored.
+    pub fn start(&'static self, state: Arc<Mutex<State>>) {
+        STATE.try_init_once(|| state).unwrap();
+        let mut local_apic = LOCAL_APIC.try_get().unwrap().try_get().unwrap().lock();
+        let (counts, tsc) = unsafe { calibrate(&mut local_apic) };
+        let counts_per_tick = counts / CALIBRATION_MILLIS;
+        let tsc_per_milli = tsc / CALIBRATION_MILLIS as u64;
+        log::info!(
+            "LAPIC timer counts per tick: {}. TSC per ms: {}",
+            counts_per_tick,
+            tsc_per_milli
+        );
+        COUNTS_PER_TICK.store(counts_per_tick, Ordering::Relaxed);
+        TSC_PER_MILLI.store(tsc_per_milli, Ordering::Relaxed);
+        unsafe {
+            local_apic.set_timer_mode(TimerMode::Periodic);
+            local_apic.set_timer_initial(counts_per_tick);
+            local_apic.enable_timer();
+        }
+    }
+}
@@ -0,0 +1,79 @@
+#![no_std]
+#![feature(abi_x86_interrupt)]
+#![feature(allocator_api)]
+#![feature(int_roundings)]
+#![feature(naked_functions)]
+#![feature(pointer_is_aligned_to)]
+#![feature(unsigned_is_multiple_of)]
+#![feature(vec_push_within_capacity)]
+#![deny(unsafe_op_in_unsafe_fn)]
+
+extern crate alloc;
+
+pub mod acpi;
+pub mod allocator;
+pub mod apic;
+pub mod backtrace;
+pub mod channels;
+pub mod colorful_logger;
+pub mod combined_logger;
+pub mod context;
+pub mod context_switching_interrupt_handler;
+pub mod cool_keyboard_interrupt_handler;
+pub mod cool_mouse_interrupt_handler;
+pub mod cool_rtc_interrupt_handler;
+pub mod cool_serial_interrupt_handler;
+pub mod debug_monitor;
+pub mod demo_async;
+pub mod demo_async_keyboard_drop;
+pub mod demo_async_rtc_drop;
+pub mod demo_maze_roller_game;
+pub mod draw_rust;
+pub mod embedded_graphics_writer;
+pub mod enter_user_mode;
+pub mod executor;
+pub mod find_used_virt_addrs;
+pub mod frame_buffer;
+pub mod gdb_stub;
+pub mod get_rgb_color;
+pub mod handle_table;
+pub mod hlt_loop;
+pub mod insert;
+pub mod kernel_thread;
+pub mod lapic_timer;
+pub mod logger;
+pub mod logger_without_interrupts;
+pub mod memory;
+pub mod modules;
+pub mod panic_screen;
+pub mod phys_mapper;
+pub mod pic8259_interrupts;
+pub mod ps2_controller;
+pub mod ps2_keyboard;
+pub mod ps2_mouse;
+pub mod qemu_exit;
+pub mod real_time;
+pub mod serial_logger;
+pub mod set_color;
+pub mod split_draw_target;
+pub mod symbols;
+pub mod syscall_handler;
+pub mod test_framework;
+pub mod time_page;
+pub mod uart;
+pub mod user_pointer;
+pub mod user_space_state;
+pub mod virt_addr_from_indexes;
+pub mod virt_mem_tracker;
+
+use bootloader_api::{config::Mapping, BootloaderConfig};
+use common::mem::KERNEL_VIRT_MEM_START;
+
+/// Shared by the kernel and the kernel tests
+pub const BOOTLOADER_CONFIG: BootloaderConfig = {
+    let mut config = BootloaderConfig::new_default();
+    config.mappings.physical_memory = Some(Mapping::Dynamic);
+    // Use higher half for kernel to have space in the lower parts for ELFs
+    config.mappings.dynamic_range_start = Some(KERNEL_VIRT_MEM_START);
+    config
+};
@@ -1,98 +1,72 @@
 #![no_std]
 #![no_main]
-#![feature(abi_x86_interrupt)]
-#![feature(allocator_api)]
-#![feature(int_roundings)]
-#![feature(naked_functions)]
-#![feature(pointer_is_aligned_to)]
-#![feature(unsigned_is_multiple_of)]
-#![feature(vec_push_within_capacity)]
-#![deny(unsafe_op_in_unsafe_fn)]
 
 extern crate alloc;
 
-pub mod acpi;
-pub mod allocator;
-pub mod apic;
-pub mod colorful_logger;
-pub mod combined_logger;
-pub mod context;
-pub mod cool_keyboard_interrupt_handler;
-pub mod demo_async;
-pub mod demo_async_keyboard_drop;
-pub mod demo_async_rtc_drop;
-pub mod demo_maze_roller_game;
-pub mod draw_rust;
-pub mod embedded_graphics_writer;
-pub mod enter_user_mode;
-pub mod execute_future;
-pub mod find_used_virt_addrs;
-pub mod frame_buffer;
-pub mod get_rgb_color;
-pub mod hlt_loop;
-pub mod insert;
-pub mod logger;
-pub mod logger_without_interrupts;
-pub mod memory;
-pub mod modules;
-pub mod phys_mapper;
-pub mod pic8259_interrupts;
-pub mod serial_logger;
-pub mod set_color;
-pub mod split_draw_target;
-pub mod syscall_handler;
-pub mod user_space_state;
-pub mod virt_addr_from_indexes;
-pub mod virt_mem_tracker;
-
 use alloc::sync::Arc;
-use bootloader_api::{config::Mapping, entry_point, BootInfo, BootloaderConfig};
-use common::mem::KERNEL_VIRT_MEM_START;
+use bootloader_api::{entry_point, BootInfo};
+use common::{mouse::MouseId, ramdisk::Ramdisk};
 use conquer_once::noblock::OnceCell;
-use cool_keyboard_interrupt_handler::CoolKeyboardBuilder;
 use core::{ops::DerefMut, panic::PanicInfo, slice};
 #[allow(unused)]
-use demo_async::demo_async;
+use kernel::demo_async::demo_async;
 #[allow(unused)]
-use demo_async_keyboard_drop::demo_async_keyboard_drop;
+use kernel::demo_async_keyboard_drop::demo_async_keyboard_drop;
 #[allow(unused)]
-use demo_async_rtc_drop::demo_asyc_rtc_drop;
+use kernel::demo_async_rtc_drop::demo_asyc_rtc_drop;
 #[allow(unused)]
-use demo_maze_roller_game::demo_maze_roller_game;
+use kernel::demo_maze_roller_game::demo_maze_roller_game;
 #[allow(unused)]
-use draw_rust::draw_rust;
-use hlt_loop::hlt_loop;
+use kernel::draw_rust::draw_rust;
 #[allow(unused)]
-use logger::init_logger_with_framebuffer;
-use modules::{
-    double_fault_handler_entry::get_double_fault_entry,
-    gdt::Gdt,
-    get_apic::get_apic,
-    get_io_apic::get_io_apic,
-    get_local_apic::get_local_apic,
-    idt::IdtBuilder,
-    logging_breakpoint_handler::logging_breakpoint_handler,
-    logging_timer_interrupt_handler::get_logging_timer_interrupt_handler,
-    panicking_double_fault_handler::panicking_double_fault_handler,
-    panicking_general_protection_fault_handler::panicking_general_protection_fault_handler,
-    panicking_invalid_opcode_handler::panicking_invalid_opcode_handler,
-    panicking_invalid_tss_fault_handler::panicking_invalid_tss_fault_handler,
-    panicking_local_apic_error_interrupt_handler::panicking_local_apic_error_interrupt_handler,
-    panicking_page_fault_handler::panicking_page_fault_handler,
-    panicking_segment_not_present_handler::panicking_segment_not_present_handler,
-    panicking_spurious_interrupt_handler::panicking_spurious_interrupt_handler,
-    panicking_stack_segment_fault_handler::panicking_stack_segment_fault_handler,
-    spurious_interrupt_handler::set_spurious_interrupt_handler,
-    static_local_apic::{self, LOCAL_APIC},
-    syscall::{init_syscalls::init_syscalls, jmp_to_elf::jmp_to_elf},
-    tss::TssBuilder,
+use kernel::logger::init_logger_with_framebuffer;
+use kernel::{
+    acpi, allocator,
+    backtrace::Backtrace,
+    context_switching_interrupt_handler::context_switching_page_fault_idt_entry,
+    cool_keyboard_interrupt_handler::CoolKeyboardBuilder,
+    cool_mouse_interrupt_handler::CoolMouseBuilder,
+    cool_rtc_interrupt_handler::CoolRtcBuilder,
+    cool_serial_interrupt_handler::CoolSerialBuilder,
+    debug_monitor,
+    gdb_stub::GdbStubBuilder,
+    hlt_loop::hlt_loop,
+    kernel_thread,
+    lapic_timer::LapicTimerBuilder,
+    memory,
+    modules::{
+        double_fault_handler_entry::get_double_fault_entry,
+        gdt::Gdt,
+        get_apic::get_apic,
+        get_io_apic::get_io_apic,
+        get_local_apic::get_local_apic,
+        idt::IdtBuilder,
+        panicking_double_fault_handler::panicking_double_fault_handler,
+        panicking_general_protection_fault_handler::panicking_general_protection_fault_handler,
+        panicking_invalid_opcode_handler::panicking_invalid_opcode_handler,
+        panicking_invalid_tss_fault_handler::panicking_invalid_tss_fault_handler,
+        panicking_local_apic_error_interrupt_handler::panicking_local_apic_error_interrupt_handler,
+        panicking_segment_not_present_handler::panicking_segment_not_present_handler,
+        panicking_spurious_interrupt_handler::panicking_spurious_interrupt_handler,
+        panicking_stack_segment_fault_handler::panicking_stack_segment_fault_handler,
+        signaling_page_fault_handler::{self, signaling_page_fault_handler},
+        spurious_interrupt_handler::set_spurious_interrupt_handler,
+        static_local_apic::{self, LOCAL_APIC},
+        syscall::{init_syscalls::init_syscalls, jmp_to_elf::jmp_to_elf},
+        tss::TssBuilder,
+    },
+    panic_screen,
+    phys_mapper::PhysMapper,
+    ps2_mouse, real_time,
+    symbols::{self, Symbolized},
+    syscall_handler::{get_syscall_handler, Devices},
+    time_page, BOOTLOADER_CONFIG,
 };
-use phys_mapper::PhysMapper;
 use spin::Mutex;
-use syscall_handler::get_syscall_handler;
 use x86_64::{
+    instructions::interrupts,
     structures::{
-        idt::{self, HandlerFunc, HandlerFuncWithErrCode, PageFaultHandlerFunc},
+        idt::{self, HandlerFunc, HandlerFuncWithErrCode},
         tss::TaskStateSegment,
     },
     VirtAddr,
@@ -101,28 +75,30 @@ use x86_64::{
 /// This function is called on panic.
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    // TODO: Blue screen with a frowny face and a QR Code
     log::error!("{}", info);
+    log::error!("Backtrace:");
+    for return_address in Backtrace::here() {
+        log::error!("  {}", Symbolized::return_address(return_address));
+    }
+    interrupts::disable();
+    panic_screen::draw(info);
+    debug_monitor::run(None);
     hlt_loop()
 }
 
-pub static BOOTLOADER_CONFIG: BootloaderConfig = {
-    let mut config = BootloaderConfig::new_default();
-    config.mappings.physical_memory = Some(Mapping::Dynamic);
-    // Use higher half for kernel to have space in the lower parts for ELFs
-    config.mappings.dynamic_range_start = Some(KERNEL_VIRT_MEM_START);
-    config
-};
-
 entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
 
 struct StaticStuff {
     tss: TaskStateSegment,
     idt_builder: IdtBuilder,
     spurious_interrupt_handler_index: u8,
-    timer_interrupt_index: u8,
+    lapic_timer: LapicTimerBuilder,
     local_apic_error_interrupt_index: u8,
     keyboard: CoolKeyboardBuilder,
+    rtc: CoolRtcBuilder,
+    mouse: CoolMouseBuilder,
+    serial: CoolSerialBuilder,
+    gdb_stub: GdbStubBuilder,
 }
 
 static STATIC_STUFF: OnceCell<StaticStuff> = OnceCell::uninit();
@@ -131,6 +107,9 @@ static GDT: OnceCell<Gdt> = OnceCell::uninit();
 fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     let frame_buffer = boot_info.framebuffer.as_mut();
     init_logger_with_framebuffer(None);
+    if let Some(frame_buffer) = &frame_buffer {
+        panic_screen::init(frame_buffer);
+    }
     log::info!(
         "Ramdisk len: {:?}. Ramdisk addr: {:?}",
         boot_info.ramdisk_len,
@@ -146,13 +125,6 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
                     panicking_double_fault_handler,
                 ))
                 .unwrap();
-            idt_builder
-                .set_breakpoint_entry({
-                    let mut entry = idt::Entry::<HandlerFunc>::missing();
-                    entry.set_handler_fn(logging_breakpoint_handler);
-                    entry
-                })
-                .unwrap();
             idt_builder
                 .set_general_protection_fault_entry({
                     let mut entry = idt::Entry::<HandlerFuncWithErrCode>::missing();
@@ -161,11 +133,9 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
                 })
                 .unwrap();
             idt_builder
-                .set_page_fault_entry({
-                    let mut entry = idt::Entry::<PageFaultHandlerFunc>::missing();
-                    entry.set_handler_fn(panicking_page_fault_handler);
-                    entry
-                })
+                .set_page_fault_entry(context_switching_page_fault_idt_entry(
+                    signaling_page_fault_handler,
+                ))
                 .unwrap();
             idt_builder
                 .set_invalid_tss_fault_entry({
@@ -207,13 +177,8 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
                 panicking_spurious_interrupt_handler,
             )
             .unwrap();
-            let timer_interrupt_index = idt_builder
-                .set_flexible_entry({
-                    let mut entry = idt::Entry::missing();
-                    entry.set_handler_fn(get_logging_timer_interrupt_handler(&LOCAL_APIC));
-                    entry
-                })
-                .unwrap();
+            let lapic_timer =
+                LapicTimerBuilder::set_interrupt(&mut idt_builder, &LOCAL_APIC).unwrap();
             let local_apic_error_interrupt_index = idt_builder
                 .set_flexible_entry({
                     let mut entry = idt::Entry::<HandlerFunc>::missing();
@@ -235,14 +200,23 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
             .unwrap();
             let keyboard =
                 CoolKeyboardBuilder::set_interrupt(&mut idt_builder, &LOCAL_APIC).unwrap();
+            let rtc = CoolRtcBuilder::set_interrupt(&mut idt_builder, &LOCAL_APIC).unwrap();
+            let mouse = CoolMouseBuilder::set_interrupt(&mut idt_builder, &LOCAL_APIC).unwrap();
+            let serial = CoolSerialBuilder::set_interrupt(&mut idt_builder, &LOCAL_APIC).unwrap();
+            let gdb_stub = GdbStubBuilder::set_interrupts(&mut idt_builder, &LOCAL_APIC).unwrap();
+            kernel_thread::set_yield_interrupt(&mut idt_builder).unwrap();
 
             StaticStuff {
                 tss: tss.get_tss(),
                 idt_builder,
                 spurious_interrupt_handler_index,
-                timer_interrupt_index,
+                lapic_timer,
                 local_apic_error_interrupt_index,
                 keyboard,
+                rtc,
+                mouse,
+                serial,
+                gdb_stub,
             }
         })
         .unwrap();
@@ -257,6 +231,15 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
             .expect("No physical memory mapped"),
     );
     let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    symbols::init_kernel(
+        unsafe {
+            slice::from_raw_parts(
+                (phys_mem_offset + boot_info.kernel_addr).as_ptr(),
+                boot_info.kernel_len as usize,
+            )
+        },
+        boot_info.kernel_image_offset,
+    );
 
     let mut frame_allocator =
         unsafe { memory::BootInfoFrameAllocator::init(boot_info.memory_regions.deref_mut()) };
@@ -267,7 +250,11 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     let mapper = Arc::new(spin::Mutex::new(mapper));
     let virt_mem_tracker = Arc::new(spin::Mutex::new(used_virt_mem_ranges));
     let frame_allocator = Arc::new(spin::Mutex::new(frame_allocator));
-    let phys_mapper = PhysMapper::new(mapper.clone(), virt_mem_tracker, frame_allocator.clone());
+    let phys_mapper = PhysMapper::new(
+        mapper.clone(),
+        virt_mem_tracker.clone(),
+        frame_allocator.clone(),
+    );
     let acpi_tables = unsafe {
         acpi::init(
             boot_info.rsdp_addr.take().expect("No rsdp address!") as usize,
@@ -283,7 +270,7 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
         &apic,
         &mut phys_mapper.clone(),
         static_stuff.spurious_interrupt_handler_index,
-        static_stuff.timer_interrupt_index,
+        static_stuff.lapic_timer.interrupt_index(),
         static_stuff.local_apic_error_interrupt_index,
     )
     .unwrap();
@@ -292,27 +279,80 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     #[allow(unused)]
     let mut io_apic = unsafe { get_io_apic(&apic, &mut phys_mapper.clone()) };
     let state = Arc::new(Mutex::new(None));
+    signaling_page_fault_handler::init(state.clone());
+    kernel_thread::init(
+        mapper.clone(),
+        frame_allocator.clone(),
+        virt_mem_tracker.clone(),
+    );
+    debug_monitor::init(
+        state.clone(),
+        mapper.clone(),
+        virt_mem_tracker,
+        &static_stuff.idt_builder,
+    );
+    static_stuff.lapic_timer.start(state.clone());
+    real_time::init();
+    time_page::init(&mut mapper.lock(), &mut frame_allocator.lock());
+    let io_apic = Arc::new(Mutex::new(io_apic));
     let keyboard = static_stuff
         .keyboard
-        .configure_io_apic(Arc::new(Mutex::new(io_apic)), state.clone());
+        .configure_io_apic(io_apic.clone(), state.clone());
+    let rtc = static_stuff
+        .rtc
+        .configure_io_apic(io_apic.clone(), state.clone());
+    let mouse_id = ps2_mouse::init().unwrap_or_else(|e| {
+        // Without a mouse, the mouse interrupt never happens
+        log::warn!("Failed to initialize PS/2 mouse: {:?}", e);
+        MouseId::Standard
+    });
+    log::info!("PS/2 mouse id: {:?}", mouse_id);
+    let mouse = static_stuff
+        .mouse
+        .configure_io_apic(io_apic.clone(), state.clone(), mouse_id);
+    let serial = static_stuff
+        .serial
+        .configure_io_apic(io_apic.clone(), state.clone());
+    static_stuff
+        .gdb_stub
+        .configure_io_apic(io_apic, mapper.clone());
 
-    if let Some(ramdisk_addr) = boot_info.ramdisk_addr.as_ref() {
-        let elf_bytes = unsafe {
+    let ramdisk = boot_info.ramdisk_addr.as_ref().and_then(|ramdisk_addr| {
+        let bytes = unsafe {
             slice::from_raw_parts(*ramdisk_addr as *const u8, boot_info.ramdisk_len as usize)
         };
-        log::info!("Entering ELF as user space");
+        Ramdisk::parse(bytes)
+            .inspect_err(|e| log::error!("Invalid ramdisk: {:?}", e))
+            .ok()
+    });
+    if let Some(ramdisk) = ramdisk {
+        for file in ramdisk.files() {
+            log::info!(
+                "Ramdisk file: {} ({:?}, {} bytes)",
+                file.name,
+                file.kind,
+                file.contents.len()
+            );
+        }
+        let init = ramdisk.init();
+        log::info!("Entering {} as user space", init.name);
         let user_space_mem_info = Arc::new(spin::Mutex::new(None));
         init_syscalls(get_syscall_handler(
             frame_buffer,
             mapper.clone(),
             frame_allocator.clone(),
-            keyboard,
+            Devices {
+                keyboard,
+                rtc,
+                mouse,
+                serial,
+            },
             user_space_mem_info.clone(),
             state.clone(),
         ));
         unsafe {
             jmp_to_elf(
-                elf_bytes,
+                init.contents,
                 mapper.clone(),
                 frame_allocator.clone(),
                 user_space_mem_info,
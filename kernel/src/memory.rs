@@ -1,9 +1,14 @@
+use alloc::vec::Vec;
 use bootloader_api::info::{MemoryRegion, MemoryRegionKind};
 use x86_64::{
-    structures::paging::{FrameAllocator, OffsetPageTable, PageTable, PhysFrame, Size4KiB},
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, OffsetPageTable, PageTable, PhysFrame, Size4KiB,
+    },
     PhysAddr, VirtAddr,
 };
 
+use crate::test_framework;
+
 /// Initialize a new OffsetPageTable.
 ///
 /// # Safety
@@ -40,6 +45,9 @@ pub unsafe fn get_active_level_4_table(physical_memory_offset: VirtAddr) -> &'st
 pub struct BootInfoFrameAllocator {
     memory_map: &'static mut [MemoryRegion],
     next: usize,
+    /// Frames that were deallocated, which are allocated again before new frames.
+    /// Frames can only be deallocated after the heap is initialized.
+    free_frames: Vec<PhysFrame>,
 }
 
 impl BootInfoFrameAllocator {
@@ -53,6 +61,7 @@ impl BootInfoFrameAllocator {
         BootInfoFrameAllocator {
             memory_map,
             next: 0,
+            free_frames: Vec::new(),
         }
     }
 
@@ -72,8 +81,73 @@ impl BootInfoFrameAllocator {
 
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        if let Some(frame) = self.free_frames.pop() {
+            return Some(frame);
+        }
         let frame = self.usable_frames().nth(self.next);
         self.next += 1;
         frame
     }
 }
+
+impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.free_frames.push(frame);
+    }
+}
+
+pub fn test_deallocate_frame() {
+    let resources = test_framework::resources();
+    let mut frame_allocator = resources.frame_allocator.lock();
+    let frame = frame_allocator.allocate_frame().unwrap();
+    unsafe { frame_allocator.deallocate_frame(frame) };
+    assert_eq!(frame_allocator.allocate_frame(), Some(frame));
+}
+
+pub fn test_map_allocated_frame() {
+    use x86_64::structures::paging::{Mapper, PageTableFlags, Translate};
+
+    let resources = test_framework::resources();
+    let mut frame_allocator = resources.frame_allocator.lock();
+    let frame = frame_allocator.allocate_frame().unwrap();
+    let other_frame = frame_allocator.allocate_frame().unwrap();
+    assert_ne!(frame, other_frame);
+
+    let mut mapper = resources.mapper.lock();
+    let page = resources
+        .virt_mem_tracker
+        .lock()
+        .allocate_pages::<Size4KiB>(1)
+        .unwrap();
+    unsafe {
+        mapper.map_to(
+            page,
+            frame,
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+            &mut *frame_allocator,
+        )
+    }
+    .unwrap()
+    .flush();
+    assert_eq!(
+        mapper.translate_addr(page.start_address()),
+        Some(frame.start_address())
+    );
+    // Writing through the new page should be visible through the physical memory mapping
+    unsafe {
+        page.start_address()
+            .as_mut_ptr::<u64>()
+            .write_volatile(0xC0FFEE)
+    };
+    let value = unsafe {
+        (mapper.phys_offset() + frame.start_address().as_u64())
+            .as_ptr::<u64>()
+            .read_volatile()
+    };
+    assert_eq!(value, 0xC0FFEE);
+    mapper.unmap(page).unwrap().1.flush();
+    resources
+        .virt_mem_tracker
+        .lock()
+        .deallocate_pages_unchecked(page..page + 1);
+}
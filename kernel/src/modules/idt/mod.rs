@@ -12,6 +12,7 @@ const MAX_FLEXIBLE_ENTRIES: u8 = FLEXIBLE_ENTRIES_START.wrapping_neg();
 pub struct IdtBuilder {
     idt: InterruptDescriptorTable,
     set_double_fault_entry: bool,
+    set_debug_entry: bool,
     set_breakpoint_entry: bool,
     set_general_protection_fault: bool,
     set_page_fault_entry: bool,
@@ -28,6 +29,7 @@ impl Default for IdtBuilder {
         Self {
             idt: InterruptDescriptorTable::new(),
             set_double_fault_entry: false,
+            set_debug_entry: false,
             set_breakpoint_entry: false,
             set_general_protection_fault: false,
             set_page_fault_entry: false,
@@ -56,6 +58,17 @@ impl IdtBuilder {
         }
     }
 
+    #[allow(clippy::result_unit_err)]
+    pub fn set_debug_entry(&mut self, entry: idt::Entry<HandlerFunc>) -> Result<(), ()> {
+        if !self.set_debug_entry {
+            self.idt.debug = entry;
+            self.set_debug_entry = true;
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
     #[allow(clippy::result_unit_err)]
     pub fn set_breakpoint_entry(&mut self, entry: idt::Entry<HandlerFunc>) -> Result<(), ()> {
         if !self.set_breakpoint_entry {
@@ -190,6 +203,32 @@ impl IdtBuilder {
         }
     }
 
+    /// The vectors that have an entry, including exceptions
+    pub fn used_vectors(&self) -> impl Iterator<Item = u8> + '_ {
+        [
+            (1, self.set_debug_entry),
+            (3, self.set_breakpoint_entry),
+            (6, self.set_invalid_opcode_entry),
+            (8, self.set_double_fault_entry),
+            (10, self.set_invalid_tss_fault_entry),
+            (11, self.set_segment_not_present_entry),
+            (12, self.set_stack_segment_fault_entry),
+            (13, self.set_general_protection_fault),
+            (14, self.set_page_fault_entry),
+            (30, self.set_security_exception_fault_entry),
+        ]
+        .into_iter()
+        .filter(|(_, used)| *used)
+        .map(|(vector, _)| vector)
+        .chain(
+            self.used_flexible_entries
+                .iter()
+                .enumerate()
+                .filter(|(_, used)| **used)
+                .map(|(index, _)| index as u8 + FLEXIBLE_ENTRIES_START),
+        )
+    }
+
     pub fn init(&'static self) {
         self.idt.load();
         disable_pic8259();
@@ -6,7 +6,6 @@ pub mod get_apic;
 pub mod get_io_apic;
 pub mod get_local_apic;
 pub mod idt;
-pub mod logging_breakpoint_handler;
 pub mod logging_timer_interrupt_handler;
 pub mod panicking_double_fault_handler;
 pub mod panicking_general_protection_fault_handler;
@@ -18,6 +17,7 @@ pub mod panicking_security_exception_fault_handler;
 pub mod panicking_segment_not_present_handler;
 pub mod panicking_spurious_interrupt_handler;
 pub mod panicking_stack_segment_fault_handler;
+pub mod signaling_page_fault_handler;
 pub mod spurious_interrupt_handler;
 pub mod static_local_apic;
 pub mod syscall;
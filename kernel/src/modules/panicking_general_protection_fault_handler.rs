@@ -1,12 +1,17 @@
 use x86_64::structures::{gdt::SegmentSelector, idt::InterruptStackFrame};
 
+use crate::symbols::Symbolized;
+
 pub extern "x86-interrupt" fn panicking_general_protection_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
     let s = SegmentSelector(error_code.try_into().unwrap());
     panic!(
-        "EXCEPTION: General Protection\n{:#?}\nError code: {:?}. Error code as segment selector: {:?}",
-        stack_frame, error_code, s
+        "EXCEPTION: General Protection at {}\n{:#?}\nError code: {:?}. Error code as segment selector: {:?}",
+        Symbolized::instruction(stack_frame.instruction_pointer.as_u64()),
+        stack_frame,
+        error_code,
+        s
     );
 }
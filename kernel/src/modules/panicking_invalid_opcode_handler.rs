@@ -1,5 +1,11 @@
 use x86_64::structures::idt::InterruptStackFrame;
 
+use crate::symbols::Symbolized;
+
 pub extern "x86-interrupt" fn panicking_invalid_opcode_handler(stack_frame: InterruptStackFrame) {
-    panic!("Invalid opcode! {:#?}", stack_frame);
+    panic!(
+        "Invalid opcode at {}! {:#?}",
+        Symbolized::instruction(stack_frame.instruction_pointer.as_u64()),
+        stack_frame
+    );
 }
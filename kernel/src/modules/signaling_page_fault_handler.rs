@@ -0,0 +1,68 @@
+use alloc::sync::Arc;
+use common::signal::Signal;
+use conquer_once::noblock::OnceCell;
+use spin::Mutex;
+use x86_64::{registers::control::Cr2, structures::idt::PageFaultErrorCode, PrivilegeLevel};
+
+use crate::{
+    backtrace::Backtrace,
+    context::{AnyContext, Context, FullContext},
+    context_switching_interrupt_handler::context_switching_interrupt_handler_with_error_code,
+    symbols::Symbolized,
+    user_space_state::State,
+};
+
+static STATE: OnceCell<Arc<Mutex<State>>> = OnceCell::uninit();
+
+/// Page faults in user space send a [`Signal::Segfault`] to the process once this is called. Page faults in the kernel always panic.
+pub fn init(state: Arc<Mutex<State>>) {
+    STATE.try_init_once(|| state).unwrap();
+}
+
+context_switching_interrupt_handler_with_error_code!(
+    pub signaling_page_fault_handler,
+    signaling_page_fault_handler_rust
+);
+
+unsafe extern "sysv64" fn signaling_page_fault_handler_rust(
+    context: *const FullContext,
+    error_code: u64,
+) {
+    let context = unsafe { *context };
+    let error_code = PageFaultErrorCode::from_bits_truncate(error_code);
+    let accessed_address = Cr2::read();
+    let state = STATE
+        .try_get()
+        .ok()
+        .filter(|_| context.privilege_level() == PrivilegeLevel::Ring3);
+    match state {
+        Some(state) => {
+            log::info!(
+                "User space page fault accessing {:?} at {}: {:?}",
+                accessed_address,
+                Symbolized::instruction(context.rip),
+                error_code
+            );
+            log::info!("Backtrace:");
+            for return_address in Backtrace::user_space(context.rbp) {
+                log::info!("  {}", Symbolized::return_address(return_address));
+            }
+            // Make sure to drop all locks before exiting
+            let jmp_to = state
+                .lock()
+                .as_mut()
+                .unwrap()
+                .on_signal(Signal::Segfault, AnyContext::Full(context));
+            unsafe { jmp_to.jmp() };
+        }
+        None => {
+            panic!(
+                "Page fault accessing {:?} at {}: {:?}. Context: {:#x?}",
+                accessed_address,
+                Symbolized::instruction(context.rip),
+                error_code,
+                context
+            );
+        }
+    }
+}
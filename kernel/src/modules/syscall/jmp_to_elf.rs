@@ -2,8 +2,8 @@ use core::{ops::DerefMut, slice};
 
 use alloc::{sync::Arc, vec::Vec};
 use anyhow::{anyhow, Context};
-use common::mem::KERNEL_VIRT_MEM_START;
-use elf::{endian::NativeEndian, ElfBytes};
+use common::{mem::KERNEL_VIRT_MEM_START, ramdisk::FileKind};
+use elf::{endian::NativeEndian, symbol::Symbol, ElfBytes};
 use spin::Mutex;
 use x86_64::{
     structures::paging::{
@@ -14,8 +14,11 @@ use x86_64::{
 
 use crate::{
     enter_user_mode::enter_user_mode,
+    handle_table::HandleTable,
     memory::BootInfoFrameAllocator,
+    symbols::{self, SymbolTable},
     syscall_handler::UserSpaceMemInfo,
+    test_framework,
     user_space_state::{State, UserSpaceState},
     virt_mem_tracker::VirtMemTracker,
 };
@@ -32,10 +35,30 @@ pub fn elf_flags_to_page_table_flags(elf_flags: u32) -> PageTableFlags {
     page_table_flags
 }
 
+fn find_start_symbol(elf: &ElfBytes<NativeEndian>) -> anyhow::Result<Symbol> {
+    let (symbols_parsing_table, symbols_strings) = elf
+        .symbol_table()?
+        .ok_or(anyhow!("No symbols / symbol strings"))?;
+    symbols_parsing_table
+        .into_iter()
+        .filter(|symbol| !symbol.is_undefined())
+        .find_map(
+            |symbol| match symbols_strings.get(symbol.st_name as usize) {
+                Ok(symbol_string) => match symbol_string {
+                    "_start" => Some(Ok(symbol)),
+                    _ => None,
+                },
+                Err(e) => Some(Err(e)),
+            },
+        )
+        .ok_or(anyhow!("_start not found"))?
+        .context("Error finding _start symbol")
+}
+
 /// # Safety
 /// Literally jumps to arbitrary code. You are responsible for handling any exceptions from code / invalid code.
 pub unsafe fn jmp_to_elf(
-    elf_bytes: &[u8],
+    elf_bytes: &'static [u8],
     mapper: Arc<spin::Mutex<OffsetPageTable<'static>>>,
     frame_allocator: Arc<spin::Mutex<BootInfoFrameAllocator>>,
     user_space_mem_info: Arc<spin::Mutex<Option<UserSpaceMemInfo>>>,
@@ -50,25 +73,9 @@ pub unsafe fn jmp_to_elf(
             .filter(|segment| segment.p_type == 1)
             .collect::<Vec<_>>();
 
-        let start_symbol = {
-            let (symbols_parsing_table, symbols_strings) = elf
-                .symbol_table()?
-                .ok_or(anyhow!("No symbols / symbol strings"))?;
-            symbols_parsing_table
-                .into_iter()
-                .filter(|symbol| !symbol.is_undefined())
-                .find_map(
-                    |symbol| match symbols_strings.get(symbol.st_name as usize) {
-                        Ok(symbol_string) => match symbol_string {
-                            "_start" => Some(Ok(symbol)),
-                            _ => None,
-                        },
-                        Err(e) => Some(Err(e)),
-                    },
-                )
-                .ok_or(anyhow!("_start not found"))?
-                .context("Error finding _start symbol")?
-        };
+        let start_symbol = find_start_symbol(&elf)?;
+        // The ELF is loaded at the addresses it's linked at
+        symbols::set_user_space(Some(SymbolTable::new(elf_bytes, 0)));
         // log::info!("ELF: {:#?}", loadable_segments);
         // log::info!("Symbols: {:#?}", start_symbol);
 
@@ -294,15 +301,49 @@ pub unsafe fn jmp_to_elf(
     };
     // FIXME: Make sure that the stack doesn't end up in between the ELF area for some reason.
     *user_space_mem_info.lock() = Some(UserSpaceMemInfo::new(stack_end.start_address()));
-    *state.lock() = Some(UserSpaceState {
-        // The capacity needed is equal to the number of user space interrupt handlers
-        // Interrupt handlers can stack on top of each other, but the same interrupt handler can't stack on itself
-        // For example, a timer interrupt handler can interrupt a keyboard interrupt handler, but a keyboard interrupt handler can't interrupt a keyboard interrupt handler
-        stack_of_saved_contexts: Vec::with_capacity(1),
-        // stack_pointer: None,
-        keyboard_interrupt_queued: false,
-        in_keyboard_interrupt_handler: false,
-        interrupts_enabled: true,
-    });
+    *state.lock() = Some(UserSpaceState::new(HandleTable::new_with_initial_handles()));
     unsafe { enter_user_mode(start_addr, stack_end.start_address()) };
 }
+
+pub fn test_elf_flags_to_page_table_flags() {
+    // Read only
+    assert_eq!(
+        elf_flags_to_page_table_flags(0b100),
+        PageTableFlags::NO_EXECUTE
+    );
+    // Read and write
+    assert_eq!(
+        elf_flags_to_page_table_flags(0b110),
+        PageTableFlags::NO_EXECUTE | PageTableFlags::WRITABLE
+    );
+    // Read and execute
+    assert_eq!(
+        elf_flags_to_page_table_flags(0b101),
+        PageTableFlags::empty()
+    );
+}
+
+/// Checks that the user space programs in the ramdisk can be loaded, without running them
+pub fn test_user_space_elfs() {
+    let Some(ramdisk) = test_framework::resources().ramdisk else {
+        return;
+    };
+    for file in ramdisk
+        .files()
+        .filter(|file| file.kind == FileKind::Program)
+    {
+        let elf = ElfBytes::<NativeEndian>::minimal_parse(file.contents).unwrap();
+        let start_symbol = find_start_symbol(&elf).unwrap();
+        assert_eq!(start_symbol.st_value, elf.ehdr.e_entry);
+        let loadable_segments = elf
+            .segments()
+            .unwrap()
+            .into_iter()
+            .filter(|segment| segment.p_type == 1)
+            .collect::<Vec<_>>();
+        assert!(!loadable_segments.is_empty());
+        for segment in loadable_segments {
+            assert!(segment.p_vaddr + segment.p_memsz <= KERNEL_VIRT_MEM_START);
+        }
+    }
+}
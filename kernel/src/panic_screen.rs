@@ -0,0 +1,313 @@
+//! Draws a report of a panic over the whole frame buffer, together with a QR code of the same
+//! report. That way a crash on real hardware can be captured with a phone, without a serial cable.
+
+use core::{
+    arch::asm,
+    convert::Infallible,
+    fmt::{self, Write},
+    iter,
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use bootloader_api::info::{FrameBuffer, FrameBufferInfo};
+use conquer_once::noblock::OnceCell;
+use embedded_graphics::{
+    geometry::AngleUnit,
+    mono_font::{
+        iso_8859_16::{FONT_10X20, FONT_9X15},
+        MonoTextStyle,
+    },
+    pixelcolor::{Rgb888, RgbColor},
+    prelude::{DrawTarget, Drawable, OriginDimensions, Point, Primitive, Size},
+    primitives::{Arc, Circle, PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+use qrcodegen_no_heap::{QrCode, QrCodeEcc, Version};
+use spin::Mutex;
+use x86_64::registers::{
+    control::{Cr0, Cr2, Cr3, Cr4},
+    rflags,
+};
+
+use crate::{backtrace::Backtrace, frame_buffer::Display, symbols::Symbolized};
+
+const BACKGROUND_COLOR: Rgb888 = Rgb888::new(0x00, 0x00, 0xAA);
+const FOREGROUND_COLOR: Rgb888 = Rgb888::WHITE;
+const MARGIN: u32 = 32;
+const FACE_DIAMETER: u32 = 96;
+/// The QR code spec requires 4 light modules around the code
+const QUIET_ZONE: u32 = 4;
+/// Keeps the QR code small enough that the modules are big enough to scan
+const REPORT_CAPACITY: usize = 1536;
+const QR_BUFFER_LEN: usize = Version::MAX.buffer_len();
+
+struct Buffers {
+    report: heapless::String<REPORT_CAPACITY>,
+    temp: [u8; QR_BUFFER_LEN],
+    out: [u8; QR_BUFFER_LEN],
+}
+
+/// Too big to put on the stack, which may be almost full when panicking
+static BUFFERS: Mutex<Buffers> = Mutex::new(Buffers {
+    report: heapless::String::new(),
+    temp: [0; QR_BUFFER_LEN],
+    out: [0; QR_BUFFER_LEN],
+});
+static FRAME_BUFFER: OnceCell<(u64, FrameBufferInfo)> = OnceCell::uninit();
+/// So that a panic while drawing doesn't draw again
+static DRAWING: AtomicBool = AtomicBool::new(false);
+
+/// Remembers where the frame buffer is. The frame buffer can still be given to user space, since
+/// after a panic nothing else will draw to it.
+pub fn init(frame_buffer: &FrameBuffer) {
+    FRAME_BUFFER
+        .try_init_once(|| (frame_buffer.buffer().as_ptr() as u64, frame_buffer.info()))
+        .unwrap();
+}
+
+struct Registers {
+    rsp: u64,
+    rbp: u64,
+    rflags: u64,
+    cr0: u64,
+    cr2: u64,
+    cr3: u64,
+    cr4: u64,
+}
+
+impl Registers {
+    #[inline(always)]
+    fn read() -> Self {
+        let rsp: u64;
+        let rbp: u64;
+        unsafe {
+            asm!(
+                "mov {}, rsp",
+                "mov {}, rbp",
+                out(reg) rsp,
+                out(reg) rbp,
+                options(nomem, nostack, preserves_flags)
+            )
+        };
+        Self {
+            rsp,
+            rbp,
+            rflags: rflags::read_raw(),
+            cr0: Cr0::read_raw(),
+            cr2: Cr2::read_raw(),
+            cr3: Cr3::read_raw().0.start_address().as_u64(),
+            cr4: Cr4::read_raw(),
+        }
+    }
+}
+
+/// Writes as much as fits and drops the rest, so a long message doesn't hide the rest of the report
+struct TruncatingWriter<'a, const N: usize>(&'a mut heapless::String<N>);
+
+impl<const N: usize> Write for TruncatingWriter<'_, N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.0.push(c).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_report(w: &mut impl Write, info: &PanicInfo, registers: &Registers) -> fmt::Result {
+    writeln!(w, "Kernel {}", info)?;
+    writeln!(
+        w,
+        "RSP    {:#018x}  RBP {:#018x}",
+        registers.rsp, registers.rbp
+    )?;
+    writeln!(
+        w,
+        "RFLAGS {:#018x}  CR0 {:#018x}",
+        registers.rflags, registers.cr0
+    )?;
+    writeln!(
+        w,
+        "CR2    {:#018x}  CR3 {:#018x}",
+        registers.cr2, registers.cr3
+    )?;
+    writeln!(w, "CR4    {:#018x}", registers.cr4)?;
+    writeln!(w, "Backtrace:")?;
+    for (i, return_address) in Backtrace::from_rbp(registers.rbp).enumerate() {
+        writeln!(
+            w,
+            "{:>2}: {}",
+            i,
+            Symbolized::return_address(return_address)
+        )?;
+    }
+    Ok(())
+}
+
+/// Splits lines that don't fit in `columns` characters
+fn wrap(text: &str, columns: usize) -> impl Iterator<Item = &str> {
+    text.lines().flat_map(move |line| {
+        let mut rest = line;
+        iter::from_fn(move || {
+            if rest.is_empty() {
+                return None;
+            }
+            let end = rest
+                .char_indices()
+                .nth(columns)
+                .map_or(rest.len(), |(i, _)| i);
+            let (line, remaining) = rest.split_at(end);
+            rest = remaining;
+            Some(line)
+        })
+    })
+}
+
+fn draw_face(display: &mut Display, top_left: Point) -> Result<(), Infallible> {
+    let style = PrimitiveStyle::with_stroke(FOREGROUND_COLOR, 6);
+    let eye_style = PrimitiveStyle::with_fill(FOREGROUND_COLOR);
+    let d = FACE_DIAMETER as i32;
+    Circle::new(top_left, FACE_DIAMETER)
+        .into_styled(style)
+        .draw(display)?;
+    for eye_x in [d * 3 / 10, d * 6 / 10] {
+        Circle::new(top_left + Point::new(eye_x, d * 3 / 10), FACE_DIAMETER / 8)
+            .into_styled(eye_style)
+            .draw(display)?;
+    }
+    // The top part of a circle below the eyes
+    Arc::new(
+        top_left + Point::new(d / 4, d * 6 / 10),
+        FACE_DIAMETER / 2,
+        200.0.deg(),
+        140.0.deg(),
+    )
+    .into_styled(style)
+    .draw(display)?;
+    Ok(())
+}
+
+/// Draws the QR code in the top right corner and returns its left edge
+fn draw_qr_code(display: &mut Display, qr_code: &QrCode) -> Result<u32, Infallible> {
+    let Size { width, height } = display.size();
+    let modules = qr_code.size() as u32 + 2 * QUIET_ZONE;
+    let max_side = (height.saturating_sub(2 * MARGIN)).min(width.saturating_sub(2 * MARGIN) / 2);
+    let scale = (max_side / modules).max(1);
+    let left = width.saturating_sub(MARGIN + modules * scale);
+    Rectangle::new(
+        Point::new(left as i32, MARGIN as i32),
+        Size::new_equal(modules * scale),
+    )
+    .into_styled(PrimitiveStyle::with_fill(Rgb888::WHITE))
+    .draw(display)?;
+    for y in 0..qr_code.size() {
+        for x in 0..qr_code.size() {
+            if qr_code.get_module(x, y) {
+                display.fill_solid(
+                    &Rectangle::new(
+                        Point::new(
+                            (left + (x as u32 + QUIET_ZONE) * scale) as i32,
+                            (MARGIN + (y as u32 + QUIET_ZONE) * scale) as i32,
+                        ),
+                        Size::new_equal(scale),
+                    ),
+                    Rgb888::BLACK,
+                )?;
+            }
+        }
+    }
+    Ok(left)
+}
+
+fn draw_text(display: &mut Display, text: &str, right: u32) -> Result<(), Infallible> {
+    let font = FONT_9X15;
+    let style = MonoTextStyle::new(&font, FOREGROUND_COLOR);
+    let top = MARGIN + FACE_DIAMETER + MARGIN;
+    let columns = (right.saturating_sub(2 * MARGIN) / font.character_size.width) as usize;
+    let rows =
+        (display.size().height.saturating_sub(top + MARGIN) / font.character_size.height) as usize;
+    for (row, line) in wrap(text, columns.max(1)).take(rows).enumerate() {
+        Text::with_baseline(
+            line,
+            Point::new(
+                MARGIN as i32,
+                (top + row as u32 * font.character_size.height) as i32,
+            ),
+            style,
+            Baseline::Top,
+        )
+        .draw(display)?;
+    }
+    Ok(())
+}
+
+fn draw_screen(
+    display: &mut Display,
+    report: &str,
+    qr_code: Option<&QrCode>,
+) -> Result<(), Infallible> {
+    display.clear(BACKGROUND_COLOR)?;
+    draw_face(display, Point::new(MARGIN as i32, MARGIN as i32))?;
+    let text_right = match qr_code {
+        Some(qr_code) => {
+            let left = draw_qr_code(display, qr_code)?;
+            Text::with_baseline(
+                "Scan to copy this report",
+                Point::new(
+                    left as i32,
+                    (MARGIN - FONT_9X15.character_size.height) as i32 / 2,
+                ),
+                MonoTextStyle::new(&FONT_9X15, FOREGROUND_COLOR),
+                Baseline::Top,
+            )
+            .draw(display)?;
+            left
+        }
+        None => display.size().width,
+    };
+    Text::with_baseline(
+        "Your computer ran into a problem",
+        Point::new((2 * MARGIN + FACE_DIAMETER) as i32, MARGIN as i32),
+        MonoTextStyle::new(&FONT_10X20, FOREGROUND_COLOR),
+        Baseline::Top,
+    )
+    .draw(display)?;
+    draw_text(display, report, text_right)
+}
+
+/// Draws the panic report. Does nothing if there is no frame buffer.
+pub fn draw(info: &PanicInfo) {
+    let registers = Registers::read();
+    let Ok((buffer_start, frame_buffer_info)) = FRAME_BUFFER.try_get() else {
+        return;
+    };
+    if DRAWING.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let Some(mut buffers) = BUFFERS.try_lock() else {
+        return;
+    };
+    let Buffers { report, temp, out } = &mut *buffers;
+    report.clear();
+    let _ = write_report(&mut TruncatingWriter(report), info, &registers);
+    let qr_code = QrCode::encode_text(
+        report,
+        temp,
+        out,
+        QrCodeEcc::Low,
+        Version::MIN,
+        Version::MAX,
+        None,
+        true,
+    )
+    .ok();
+    // Safety: Nothing else is running anymore, so nothing else is using the frame buffer
+    let mut frame_buffer = unsafe { FrameBuffer::new(*buffer_start, *frame_buffer_info) };
+    let _ = draw_screen(
+        &mut Display::new(&mut frame_buffer),
+        report,
+        qr_code.as_ref(),
+    );
+}
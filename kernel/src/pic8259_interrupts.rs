@@ -5,5 +5,10 @@ use num_enum::IntoPrimitive;
 pub enum Pic8259Interrupts {
     Timer,
     Keyboard,
+    /// Also used by COM4
+    Com2 = 3,
+    /// Also used by COM3
+    Com1 = 4,
     Rtc = 8,
+    Mouse = 12,
 }
@@ -0,0 +1,68 @@
+//! The PS/2 controller, which the keyboard and mouse are connected to.
+//! See https://wiki.osdev.org/I8042_PS/2_Controller
+
+use x86_64::instructions::port::Port;
+
+const DATA_PORT: u16 = 0x60;
+/// Reading is the status register and writing is the command register
+const STATUS_AND_COMMAND_PORT: u16 = 0x64;
+/// Status bit that is set when there is a byte to read
+const OUTPUT_FULL: u8 = 1 << 0;
+/// Status bit that is set when the controller isn't ready to be written to
+const INPUT_FULL: u8 = 1 << 1;
+/// How many times to check the status before giving up, so that this doesn't hang without a device
+const TIMEOUT: usize = 100_000;
+const ACK: u8 = 0xFA;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2Error {
+    Timeout,
+    /// The device replied with something other than an acknowledgement
+    NotAcknowledged(u8),
+}
+
+fn status() -> u8 {
+    unsafe { Port::new(STATUS_AND_COMMAND_PORT).read() }
+}
+
+fn wait_for(condition: impl Fn(u8) -> bool) -> Result<(), Ps2Error> {
+    (0..TIMEOUT)
+        .find(|_| condition(status()))
+        .map(|_| ())
+        .ok_or(Ps2Error::Timeout)
+}
+
+/// Sends a command to the controller itself
+pub fn write_command(command: u8) -> Result<(), Ps2Error> {
+    wait_for(|status| status & INPUT_FULL == 0)?;
+    unsafe { Port::new(STATUS_AND_COMMAND_PORT).write(command) };
+    Ok(())
+}
+
+/// Sends a byte to the keyboard, unless it was preceded by a command that redirects it
+pub fn write_data(data: u8) -> Result<(), Ps2Error> {
+    wait_for(|status| status & INPUT_FULL == 0)?;
+    unsafe { Port::new(DATA_PORT).write(data) };
+    Ok(())
+}
+
+pub fn read_data() -> Result<u8, Ps2Error> {
+    wait_for(|status| status & OUTPUT_FULL != 0)?;
+    Ok(unsafe { Port::new(DATA_PORT).read() })
+}
+
+/// Reads a byte without waiting. Returns [`None`] if it was already read, for example by a syscall that waited for an acknowledgement.
+pub fn try_read_data() -> Option<u8> {
+    if status() & OUTPUT_FULL != 0 {
+        Some(unsafe { Port::new(DATA_PORT).read() })
+    } else {
+        None
+    }
+}
+
+pub fn read_ack() -> Result<(), Ps2Error> {
+    match read_data()? {
+        ACK => Ok(()),
+        reply => Err(Ps2Error::NotAcknowledged(reply)),
+    }
+}
@@ -0,0 +1,117 @@
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use common::syscall_configure_keyboard::{
+    ConfigureKeyboardError, KeyboardCommand, KeyboardLocks, ScanCodeSet,
+};
+
+use crate::ps2_controller::{read_ack, write_data, Ps2Error};
+
+/// Starts with Num Lock on, like `pc_keyboard`
+static LOCKS: AtomicU8 = AtomicU8::new(KeyboardLocks::NUM_LOCK.0);
+/// Lock keys that are held down, so that repeated make codes don't toggle them again
+static HELD: AtomicU8 = AtomicU8::new(0);
+/// The number of upcoming scan codes that are part of an extended key, so they aren't lock keys
+static SKIP: AtomicU8 = AtomicU8::new(0);
+/// If the skipped scan code comes after E0. Right Ctrl is E0 1D.
+static EXTENDED: AtomicBool = AtomicBool::new(false);
+static CTRL_HELD: AtomicBool = AtomicBool::new(false);
+
+impl From<Ps2Error> for ConfigureKeyboardError {
+    fn from(value: Ps2Error) -> Self {
+        match value {
+            Ps2Error::Timeout => Self::Timeout,
+            Ps2Error::NotAcknowledged(reply) => Self::NotAcknowledged(reply),
+        }
+    }
+}
+
+/// Call this for every Set 1 scan code to keep track of the lock keys and Ctrl. Returns `true` if Ctrl+C was pressed.
+pub fn track_keys(scan_code: u8) -> bool {
+    if SKIP.load(Ordering::Relaxed) > 0 {
+        SKIP.fetch_sub(1, Ordering::Relaxed);
+        if EXTENDED.swap(false, Ordering::Relaxed) {
+            track_ctrl(scan_code);
+        }
+        return false;
+    }
+    let (lock, pressed) = match scan_code {
+        // Extended keys, like Ctrl+Break (E0 46), share the rest of their scan code with normal keys
+        0xE0 => {
+            SKIP.store(1, Ordering::Relaxed);
+            EXTENDED.store(true, Ordering::Relaxed);
+            return false;
+        }
+        // Pause is E1 1D 45 E1 9D C5, and the bytes after each E1 aren't Num Lock or Ctrl
+        0xE1 => {
+            SKIP.store(2, Ordering::Relaxed);
+            return false;
+        }
+        // C
+        0x2E => return CTRL_HELD.load(Ordering::Relaxed),
+        0x3A => (KeyboardLocks::CAPS_LOCK, true),
+        0x45 => (KeyboardLocks::NUM_LOCK, true),
+        0x46 => (KeyboardLocks::SCROLL_LOCK, true),
+        0xBA => (KeyboardLocks::CAPS_LOCK, false),
+        0xC5 => (KeyboardLocks::NUM_LOCK, false),
+        0xC6 => (KeyboardLocks::SCROLL_LOCK, false),
+        scan_code => {
+            track_ctrl(scan_code);
+            return false;
+        }
+    };
+    if pressed {
+        let held = HELD.fetch_or(lock.0, Ordering::Relaxed);
+        if held & lock.0 == 0 {
+            LOCKS.fetch_xor(lock.0, Ordering::Relaxed);
+        }
+    } else {
+        HELD.fetch_and(!lock.0, Ordering::Relaxed);
+    }
+    false
+}
+
+/// Left Ctrl is 1D, and Right Ctrl is the same after E0
+fn track_ctrl(scan_code: u8) {
+    match scan_code {
+        0x1D => CTRL_HELD.store(true, Ordering::Relaxed),
+        0x9D => CTRL_HELD.store(false, Ordering::Relaxed),
+        _ => {}
+    }
+}
+
+pub fn locks() -> KeyboardLocks {
+    KeyboardLocks(LOCKS.load(Ordering::Relaxed))
+}
+
+/// Sends a byte to the keyboard and waits for the keyboard to acknowledge it
+fn write_to_keyboard(data: u8) -> Result<(), Ps2Error> {
+    write_data(data)?;
+    read_ack()
+}
+
+/// Call this with interrupts disabled, so that the keyboard interrupt handler doesn't read the acknowledgements.
+/// See https://wiki.osdev.org/PS/2_Keyboard#Commands
+pub fn configure(command: KeyboardCommand) -> Result<(), ConfigureKeyboardError> {
+    match command {
+        KeyboardCommand::SetLeds(leds) => {
+            write_to_keyboard(0xED)?;
+            write_to_keyboard(leds.0)?;
+        }
+        KeyboardCommand::SetTypematic(typematic) => {
+            let byte = typematic
+                .to_byte()
+                .ok_or(ConfigureKeyboardError::InvalidRepeatRate)?;
+            write_to_keyboard(0xF3)?;
+            write_to_keyboard(byte)?;
+        }
+        KeyboardCommand::SetScanCodeSet(scan_code_set) => {
+            write_to_keyboard(0xF0)?;
+            write_to_keyboard(match scan_code_set {
+                ScanCodeSet::Set1 => 1,
+                ScanCodeSet::Set2 => 2,
+                ScanCodeSet::Set3 => 3,
+            })?;
+        }
+    }
+    Ok(())
+}
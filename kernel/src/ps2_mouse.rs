@@ -0,0 +1,61 @@
+use common::mouse::MouseId;
+
+use crate::ps2_controller::{read_ack, read_data, write_command, write_data, Ps2Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2MouseError {
+    Controller(Ps2Error),
+    UnknownId(u8),
+}
+
+impl From<Ps2Error> for Ps2MouseError {
+    fn from(value: Ps2Error) -> Self {
+        Self::Controller(value)
+    }
+}
+
+/// Sends a byte to the mouse instead of the keyboard and waits for the mouse to acknowledge it
+fn write_to_mouse(data: u8) -> Result<(), Ps2Error> {
+    write_command(0xD4)?;
+    write_data(data)?;
+    read_ack()
+}
+
+fn set_sample_rate(rate: u8) -> Result<(), Ps2Error> {
+    write_to_mouse(0xF3)?;
+    write_to_mouse(rate)
+}
+
+fn get_id() -> Result<MouseId, Ps2MouseError> {
+    write_to_mouse(0xF2)?;
+    let id = read_data()?;
+    MouseId::from_id(id).ok_or(Ps2MouseError::UnknownId(id))
+}
+
+/// Enables the PS/2 controller's auxiliary port and IRQ12, turns on the scroll wheel and extra buttons if the mouse has them, and makes the mouse start sending packets.
+/// Call this with interrupts disabled, before the keyboard interrupt is enabled, so that no one else reads the data port.
+/// See https://wiki.osdev.org/PS/2_Mouse
+pub fn init() -> Result<MouseId, Ps2MouseError> {
+    // Enable the auxiliary device
+    write_command(0xA8)?;
+    // Enable IRQ12 and the mouse clock in the controller configuration byte
+    write_command(0x20)?;
+    let configuration = (read_data()? | 0b10) & !0b10_0000;
+    write_command(0x60)?;
+    write_data(configuration)?;
+    // Use default settings
+    write_to_mouse(0xF6)?;
+    // Magic sample rate sequences that unlock the scroll wheel and then the extra buttons
+    for rate in [200, 100, 80] {
+        set_sample_rate(rate)?;
+    }
+    if get_id()? == MouseId::Wheel {
+        for rate in [200, 200, 80] {
+            set_sample_rate(rate)?;
+        }
+    }
+    let id = get_id()?;
+    // Enable data reporting
+    write_to_mouse(0xF4)?;
+    Ok(id)
+}
@@ -0,0 +1,24 @@
+//! Exits QEMU through its `isa-debug-exit` device, which must be added with
+//! `-device isa-debug-exit,iobase=0xf4,iosize=0x04`.
+//! QEMU exits with `(code << 1) | 1`, so it can't be confused with QEMU's own exit codes.
+
+use x86_64::instructions::port::Port;
+
+use crate::hlt_loop::hlt_loop;
+
+const ISA_DEBUG_EXIT_PORT: u16 = 0xF4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    /// QEMU exits with 33
+    Success = 0x10,
+    /// QEMU exits with 35
+    Failed = 0x11,
+}
+
+/// Halts forever if not running in QEMU with the `isa-debug-exit` device
+pub fn exit_qemu(exit_code: QemuExitCode) -> ! {
+    unsafe { Port::new(ISA_DEBUG_EXIT_PORT).write(exit_code as u32) };
+    hlt_loop()
+}
@@ -0,0 +1,68 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use common::syscall_timer::NANOS_PER_SECOND;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_rtc::Rtc;
+
+use crate::{
+    executor::{self, yield_now, Priority},
+    lapic_timer::monotonic_time,
+};
+
+/// Unix time in nanoseconds minus monotonic time. The CMOS RTC only has a precision of 1 second, so the sub-second part comes from the monotonic clock.
+static OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// Reads the CMOS RTC. Call this after the LAPIC timer is started and kernel threads are initialized.
+/// The time is only known to the second at first. A kernel task waits for the RTC's seconds to
+/// change (up to 1 second), and then the time is known to about the length of a time slice.
+pub fn init() {
+    let start = Rtc::new().get_unix_timestamp();
+    set(start * NANOS_PER_SECOND);
+    let coarse_offset = offset();
+    log::info!("Unix time: {}", start);
+    let result = executor::spawn_thread(move |spawner| {
+        spawner.spawn_with_priority(Priority::Low, align_to_rtc(start, coarse_offset));
+    });
+    if let Err(e) = result {
+        log::warn!("Couldn't spawn a thread to align the real time: {:?}", e);
+    }
+}
+
+/// Sets the sub-second part of the time once the RTC's seconds change after `start`
+async fn align_to_rtc(start: u64, coarse_offset: u64) {
+    let rtc = Rtc::new();
+    let unix_time = loop {
+        // Reading the RTC selects a CMOS register and then reads it, so nothing else can use the CMOS in between
+        let unix_time = without_interrupts(|| rtc.get_unix_timestamp());
+        if unix_time != start {
+            break unix_time;
+        }
+        yield_now().await;
+    };
+    let offset = (unix_time * NANOS_PER_SECOND).saturating_sub(monotonic_time());
+    // Don't overwrite a time that was set in the meantime
+    let _ = OFFSET.compare_exchange(coarse_offset, offset, Ordering::Relaxed, Ordering::Relaxed);
+}
+
+/// Nanoseconds since the Unix epoch
+pub fn get() -> u64 {
+    offset() + monotonic_time()
+}
+
+/// Unix time in nanoseconds minus monotonic time
+pub fn offset() -> u64 {
+    OFFSET.load(Ordering::Relaxed)
+}
+
+fn set(unix_nanos: u64) {
+    OFFSET.store(
+        unix_nanos.saturating_sub(monotonic_time()),
+        Ordering::Relaxed,
+    );
+}
+
+/// Also writes the time to the CMOS RTC, so that it stays after rebooting
+pub fn set_and_write_to_cmos(unix_nanos: u64) {
+    set(unix_nanos);
+    Rtc::new().set_unix_timestamp(unix_nanos / NANOS_PER_SECOND);
+}
@@ -0,0 +1,129 @@
+//! Resolves addresses to the names of the functions they are in, using the symbol tables of the
+//! kernel's ELF (which the bootloader leaves in memory) and the user space ELF.
+
+use core::fmt::{self, Display};
+
+use alloc::format;
+
+use common::mem::KERNEL_VIRT_MEM_START;
+use conquer_once::noblock::OnceCell;
+use elf::{abi::STT_FUNC, endian::NativeEndian, ElfBytes};
+use spin::Mutex;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SymbolTable {
+    elf_bytes: &'static [u8],
+    /// How far from its linked address the ELF was loaded
+    load_offset: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Symbol {
+    pub name: &'static str,
+    /// The address relative to the start of the function
+    pub offset: u64,
+}
+
+impl SymbolTable {
+    pub fn new(elf_bytes: &'static [u8], load_offset: u64) -> Self {
+        Self {
+            elf_bytes,
+            load_offset,
+        }
+    }
+
+    /// Finds the function that contains `address`
+    pub fn lookup(&self, address: u64) -> Option<Symbol> {
+        let address = address.checked_sub(self.load_offset)?;
+        let elf = ElfBytes::<NativeEndian>::minimal_parse(self.elf_bytes).ok()?;
+        let (symbols, strings) = elf.symbol_table().ok()??;
+        let symbol = symbols.into_iter().find(|symbol| {
+            symbol.st_symtype() == STT_FUNC
+                && !symbol.is_undefined()
+                && (symbol.st_value..symbol.st_value + symbol.st_size).contains(&address)
+        })?;
+        Some(Symbol {
+            name: strings.get(symbol.st_name as usize).ok()?,
+            offset: address - symbol.st_value,
+        })
+    }
+}
+
+static KERNEL_SYMBOLS: OnceCell<SymbolTable> = OnceCell::uninit();
+static USER_SPACE_SYMBOLS: Mutex<Option<SymbolTable>> = Mutex::new(None);
+
+/// `kernel_elf` is the kernel's ELF file, which was loaded `kernel_image_offset` bytes from its linked address
+pub fn init_kernel(kernel_elf: &'static [u8], kernel_image_offset: u64) {
+    KERNEL_SYMBOLS
+        .try_init_once(|| SymbolTable::new(kernel_elf, kernel_image_offset))
+        .unwrap();
+}
+
+/// Call this when a new user space ELF is loaded
+pub fn set_user_space(symbol_table: Option<SymbolTable>) {
+    *USER_SPACE_SYMBOLS.lock() = symbol_table;
+}
+
+fn lookup(address: u64) -> Option<Symbol> {
+    let symbol_table = if address >= KERNEL_VIRT_MEM_START {
+        KERNEL_SYMBOLS.try_get().ok().copied()
+    } else {
+        // Don't deadlock when panicking while this is locked
+        USER_SPACE_SYMBOLS
+            .try_lock()
+            .and_then(|symbol_table| *symbol_table)
+    }?;
+    symbol_table.lookup(address)
+}
+
+/// Formats an address followed by the function it is in, if that is known
+#[derive(Debug, Clone, Copy)]
+pub struct Symbolized {
+    address: u64,
+    is_return_address: bool,
+}
+
+impl Symbolized {
+    /// For addresses of instructions, such as the `rip` of an interrupted context
+    pub fn instruction(address: u64) -> Self {
+        Self {
+            address,
+            is_return_address: false,
+        }
+    }
+
+    /// For return addresses, such as the ones in a [`Backtrace`](crate::backtrace::Backtrace).
+    /// These point to the instruction after the call, which can be in the next function if the
+    /// call was the last instruction of a function that doesn't return.
+    pub fn return_address(address: u64) -> Self {
+        Self {
+            address,
+            is_return_address: true,
+        }
+    }
+}
+
+impl Display for Symbolized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#018x}", self.address)?;
+        let call_address = self.address.saturating_sub(self.is_return_address as u64);
+        if let Some(symbol) = lookup(call_address) {
+            write!(
+                f,
+                " {:#}+{:#x}",
+                rustc_demangle::demangle(symbol.name),
+                symbol.offset + self.is_return_address as u64
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn test_kernel_symbols() {
+    let address = test_kernel_symbols as usize as u64;
+    let symbol = lookup(address).unwrap();
+    assert_eq!(symbol.offset, 0);
+    assert!(format!("{:#}", rustc_demangle::demangle(symbol.name))
+        .ends_with("symbols::test_kernel_symbols"));
+    assert!(lookup(0x1234).is_none());
+}
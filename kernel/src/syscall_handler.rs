@@ -1,34 +1,82 @@
-use core::{arch::naked_asm, cmp::Ordering, mem::MaybeUninit, ops::DerefMut, str};
+use core::{
+    arch::naked_asm,
+    cmp::Ordering,
+    mem::MaybeUninit,
+    ops::{DerefMut, Range},
+    str,
+};
 
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
 use bootloader_api::info::FrameBuffer;
 use common::{
+    event::EventSource,
+    handle::Rights,
+    key_event::KeyEvent,
     mem::{KERNEL_VIRT_MEM_START, USER_SPACE_MMIO_START},
+    mouse::MouseEvent,
     syscall::Syscall,
+    syscall_allocate_pages::{
+        AllocatePagesError, SyscallAllocatePagesInput, SyscallAllocatePagesOutput, MAX_HEAP_PAGES,
+    },
+    syscall_channel::{
+        ChannelError, CreateChannelOutputData, ReceivedMessage, SyscallChannelCallOutput,
+        SyscallChannelOutput, SyscallChannelReceiveHandleOutput, SyscallChannelReceiveInput,
+        SyscallChannelReplyInput, SyscallChannelSendInput,
+    },
+    syscall_configure_keyboard::{
+        ConfigureKeyboardError, SyscallConfigureKeyboardInput, SyscallConfigureKeyboardOutput,
+        SyscallKeyboardLocksOutput,
+    },
+    syscall_handle::{
+        SyscallDuplicateHandleInput, SyscallHandleOutput, SyscallNewHandleOutput,
+        SyscallTransferHandleInput,
+    },
     syscall_output::SyscallOutput,
     syscall_print::{SyscallPrintError, SyscallPrintOutput},
+    syscall_real_time::{
+        RtcTicksError, SyscallPollRtcTicksOutput, SyscallRtcTicksOutput, SyscallSetRealTimeInput,
+        SyscallSetRealTimeOutput, SyscallStartRtcTicksInput,
+    },
+    syscall_set_event_handler::SyscallSetEventHandlerInput,
+    syscall_shared_memory::{
+        SharedMemoryError, SyscallCreateSharedMemoryOutput, SyscallMapSharedMemoryInput,
+        SyscallMapSharedMemoryOutput, MAX_SHARED_MEMORY_PAGES,
+    },
+    syscall_signal::{
+        SyscallSendSignalInput, SyscallSendSignalOutput, SyscallSetSignalHandlerInput,
+    },
     syscall_take_frame_buffer::{
-        TakeFrameBufferError, TakeFrameBufferOutput, TakeFrameBufferOutputData,
+        SyscallTakeFrameBufferInput, TakeFrameBufferError, TakeFrameBufferOutput,
+        TakeFrameBufferOutputData,
     },
+    syscall_timer::{SyscallCreateTimerOutput, SyscallPollTimerOutput, SyscallTimerOutput},
 };
 use conquer_once::noblock::OnceCell;
 use spin::Mutex;
 use x86_64::{
     instructions::interrupts,
     structures::paging::{
-        FrameAllocator, Mapper, OffsetPageTable, Page, PageSize, PageTableFlags, Size4KiB,
+        mapper::MapToError, page::PageRange, FrameAllocator, FrameDeallocator, Mapper,
+        OffsetPageTable, Page, PageSize, PageTableFlags, PhysFrame, Size4KiB,
     },
     PrivilegeLevel, VirtAddr,
 };
+use x86_rtc::interrupts::DividerValue;
 
 use crate::{
+    channels::Channels,
     context::{AnyContext, Context, SyscallContext},
-    cool_keyboard_interrupt_handler::{CoolKeyboard, USER_SPACE_INTERRUPT_HANDLER},
-    enter_user_mode::enter_user_mode,
-    hlt_loop::hlt_loop,
+    cool_keyboard_interrupt_handler::CoolKeyboard,
+    cool_mouse_interrupt_handler::CoolMouse,
+    cool_rtc_interrupt_handler::CoolRtc,
+    cool_serial_interrupt_handler::CoolSerial,
+    handle_table::{HandleEntry, HandleTable, KernelObject, SharedMemory},
+    lapic_timer::monotonic_time,
     memory::BootInfoFrameAllocator,
     modules::syscall::syscall_handler::SyscallHandler,
-    user_space_state::State,
+    ps2_keyboard, real_time,
+    user_pointer::{check_user_pointer, user_slice, user_slice_mut},
+    user_space_state::{JmpTo, State},
 };
 
 pub struct UserSpaceMemInfo {
@@ -43,19 +91,93 @@ impl UserSpaceMemInfo {
             allocated_pages: 0,
         }
     }
+
+    /// The address range that the heap can grow into
+    fn heap_range(&self) -> Range<VirtAddr> {
+        self.user_space_heap_start..self.user_space_heap_start + MAX_HEAP_PAGES * Size4KiB::SIZE
+    }
+}
+
+/// Maps the `i`th page to the frame that `frame_for(i, ..)` returns. If a page can't be mapped, the
+/// pages that were already mapped are unmapped again, so that a failed syscall doesn't leave part
+/// of the range mapped. Every frame from `frame_for` that doesn't end up mapped is passed to `release`.
+fn map_pages(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut BootInfoFrameAllocator,
+    pages: PageRange<Size4KiB>,
+    flags: PageTableFlags,
+    mut frame_for: impl FnMut(u64, &mut BootInfoFrameAllocator) -> Option<PhysFrame>,
+    mut release: impl FnMut(PhysFrame, &mut BootInfoFrameAllocator),
+) -> Result<(), MapToError<Size4KiB>> {
+    for (i, page) in pages.into_iter().enumerate() {
+        let result = match frame_for(i as u64, frame_allocator) {
+            Some(frame) => unsafe { mapper.map_to(page, frame, flags, frame_allocator) }
+                .map(|flush| flush.flush())
+                .inspect_err(|_| release(frame, frame_allocator)),
+            None => Err(MapToError::FrameAllocationFailed),
+        };
+        if let Err(e) = result {
+            for page in Page::range(pages.start, page) {
+                let (frame, flush) = mapper.unmap(page).unwrap();
+                flush.flush();
+                release(frame, frame_allocator);
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
 }
 
+/// When more than one of these locks is held at the same time, they are always taken in this order,
+/// so that syscalls can't deadlock against each other: `state` (including [`with_handles`]),
+/// `channels`, `user_space_mem_info`, `mapper`, `frame_allocator`.
 struct StaticStuff {
     frame_buffer: Option<&'static mut FrameBuffer>,
     mapper: Arc<spin::Mutex<OffsetPageTable<'static>>>,
     frame_allocator: Arc<spin::Mutex<BootInfoFrameAllocator>>,
     cool_keyboard: CoolKeyboard,
+    cool_rtc: CoolRtc,
+    cool_mouse: CoolMouse,
+    cool_serial: CoolSerial,
     user_space_mem_info: Arc<Mutex<Option<UserSpaceMemInfo>>>,
     state: Arc<Mutex<State>>,
+    channels: Mutex<Channels>,
 }
 
 static STATIC_STUFF: OnceCell<StaticStuff> = OnceCell::uninit();
 
+/// The handle table of the process that made the syscall
+fn with_handles<T>(f: impl FnOnce(&mut HandleTable) -> T) -> T {
+    let mut state = STATIC_STUFF.try_get().unwrap().state.lock();
+    f(&mut state.as_mut().unwrap().handles)
+}
+
+/// Queue an event for the process that made the syscall
+fn queue_event(source: EventSource) {
+    STATIC_STUFF
+        .try_get()
+        .unwrap()
+        .state
+        .lock()
+        .as_mut()
+        .unwrap()
+        .queue_event(source);
+}
+
+/// Call this after removing a handle from a handle table
+fn drop_kernel_object(object: KernelObject) {
+    if let Some(endpoint) = object.into_last_channel_endpoint() {
+        // The endpoint stays open as long as there is a handle to it
+        STATIC_STUFF
+            .try_get()
+            .unwrap()
+            .channels
+            .lock()
+            .close(endpoint)
+            .unwrap();
+    }
+}
+
 // save the registers, handle the syscall and return to usermode
 #[naked]
 unsafe extern "sysv64" fn raw_syscall_handler() {
@@ -163,7 +285,7 @@ extern "sysv64" fn get_temp_rsp() -> u64 {
     let temp_stack_rsp = contexts
         .iter()
         .rev()
-        .filter_map(|context| match context {
+        .filter_map(|saved_context| match &saved_context.context {
             AnyContext::Full(context) => Some(context),
             AnyContext::Syscall(_) => None,
         })
@@ -206,7 +328,6 @@ extern "sysv64" fn handle_syscall(
         pub r11: u64,
         pub rcx: u64,
     }
-    let rsp_to_restore = user_space_stack_pointer + size_of::<PushedRegisters>() as u64;
     let get_syscall_context = |return_value: u64| {
         let pushed_registers = unsafe { *(user_space_stack_pointer as *const PushedRegisters) };
         let rsp_to_restore = user_space_stack_pointer + size_of::<PushedRegisters>() as u64;
@@ -227,41 +348,31 @@ extern "sysv64" fn handle_syscall(
     let return_value = match Syscall::deserialize_from_input(inputs) {
         Ok(syscall) => match syscall {
             Syscall::Print(message) => {
-                let output = SyscallPrintOutput({
-                    let pointer: *const u8 = message.into();
-                    if pointer.is_null() {
-                        Err(SyscallPrintError::PointerIsNull)
-                    } else if !pointer.is_aligned() {
-                        Err(SyscallPrintError::PointerNotAligned)
-                    } else if VirtAddr::from_ptr(pointer.wrapping_add(message.len() as usize))
-                        > VirtAddr::new_truncate(KERNEL_VIRT_MEM_START)
-                    {
-                        Err(SyscallPrintError::PointerNotAllowed)
-                    } else {
-                        match str::from_utf8(unsafe { message.to_slice() }) {
+                let output = SyscallPrintOutput(
+                    unsafe { user_slice::<u8>(message) }
+                        .map_err(SyscallPrintError::from)
+                        .and_then(|message| match str::from_utf8(message) {
                             Ok(message) => {
                                 log::info!("[U] {:?}", message);
                                 Ok(())
                             }
                             Err(_e) => Err(SyscallPrintError::InvalidString),
-                        }
-                    }
-                });
+                        }),
+                );
                 output.to_syscall_output().unwrap()
             }
-            Syscall::TakeFrameBuffer(output) => {
+            Syscall::TakeFrameBuffer(SyscallTakeFrameBufferInput {
+                frame_buffer: frame_buffer_handle,
+                output,
+            }) => {
                 let return_value = TakeFrameBufferOutput({
                     let output: *mut TakeFrameBufferOutputData = output.into();
-                    if output.is_null() {
-                        Err(TakeFrameBufferError::PointerIsNull)
-                    } else if !output.is_aligned() {
-                        Err(TakeFrameBufferError::PointerNotAligned)
-                    }
-                    //Check if owned by user space
-                    else if VirtAddr::from_ptr(output.wrapping_add(1))
-                        > VirtAddr::new_truncate(KERNEL_VIRT_MEM_START)
-                    {
-                        Err(TakeFrameBufferError::PointerNotAllowed)
+                    if let Err(e) = with_handles(|handles| {
+                        handles.frame_buffer(frame_buffer_handle, Rights::MAP)
+                    }) {
+                        Err(e.into())
+                    } else if let Err(e) = check_user_pointer(output, 1) {
+                        Err(e.into())
                     } else {
                         let static_stuff = STATIC_STUFF.try_get().unwrap();
                         match &static_stuff.frame_buffer {
@@ -298,29 +409,32 @@ extern "sysv64" fn handle_syscall(
                                         )
                                         .unwrap();
                                     log::info!("Mapping pages...");
-                                    for i in 0..page_count {
-                                        unsafe {
-                                            mapper
-                                                .map_to(
-                                                    start_page_in_user_space + i,
-                                                    phys_start + i,
-                                                    PageTableFlags::PRESENT
-                                                        | PageTableFlags::USER_ACCESSIBLE
-                                                        | PageTableFlags::WRITABLE
-                                                        | PageTableFlags::NO_EXECUTE,
-                                                    frame_allocator.deref_mut(),
-                                                )
-                                                .unwrap()
-                                                .flush();
-                                        };
-                                    }
-                                    unsafe {
+                                    map_pages(
+                                        mapper.deref_mut(),
+                                        frame_allocator.deref_mut(),
+                                        Page::range(
+                                            start_page_in_user_space,
+                                            start_page_in_user_space + page_count,
+                                        ),
+                                        PageTableFlags::PRESENT
+                                            | PageTableFlags::USER_ACCESSIBLE
+                                            | PageTableFlags::WRITABLE
+                                            | PageTableFlags::NO_EXECUTE,
+                                        |i, _| Some(phys_start + i),
+                                        |_, _| {},
+                                    )
+                                    .map_err(|e| match e {
+                                        MapToError::FrameAllocationFailed => {
+                                            TakeFrameBufferError::OutOfMemory
+                                        }
+                                        _ => TakeFrameBufferError::AlreadyMapped,
+                                    })
+                                    .map(|()| unsafe {
                                         output.write(TakeFrameBufferOutputData::new(
                                             frame_buffer_start_address_in_user_space.as_u64(),
                                             frame_buffer.info(),
                                         ))
-                                    };
-                                    Ok(())
+                                    })
                                 } else {
                                     log::warn!("Can't give frame buffer to user space because it doesn't have a phys frames to itself.");
                                     Err(TakeFrameBufferError::CannotSecurelyGiveAccess)
@@ -337,21 +451,34 @@ extern "sysv64" fn handle_syscall(
                 return_value.to_syscall_output().unwrap()
             }
             Syscall::Exit => {
-                // Nothing to do
-                hlt_loop();
+                STATIC_STUFF
+                    .try_get()
+                    .unwrap()
+                    .state
+                    .lock()
+                    .as_mut()
+                    .unwrap()
+                    .exit();
+                log::info!("Process exited");
+                // Syscalls run with interrupts disabled, so a plain `hlt` loop would stop everything else
+                unsafe { JmpTo::HltLoop.jmp() }
             }
             Syscall::StartRecordingKeyboard(input) => {
-                STATIC_STUFF.try_get().unwrap().cool_keyboard.enable(input);
-                Default::default()
+                let return_value = SyscallHandleOutput(
+                    with_handles(|handles| handles.keyboard(input.keyboard, Rights::READ)).map(
+                        |()| {
+                            STATIC_STUFF.try_get().unwrap().cool_keyboard.enable(input);
+                        },
+                    ),
+                );
+                return_value.to_syscall_output().unwrap()
             }
-            Syscall::PollKeyboard(dest) => {
-                let dest_ptr: *mut u8 = dest.into();
-                if !dest_ptr.is_null()
-                    && dest_ptr.is_aligned()
-                    && VirtAddr::from_ptr(dest_ptr.wrapping_add(1))
-                        <= VirtAddr::new_truncate(KERNEL_VIRT_MEM_START)
+            Syscall::PollKeyboard(keyboard, dest) => {
+                match with_handles(|handles| handles.keyboard(keyboard, Rights::READ))
+                    .ok()
+                    .and_then(|()| unsafe { user_slice_mut::<u8>(dest) }.ok())
                 {
-                    match STATIC_STUFF
+                    Some(slice) => match STATIC_STUFF
                         .try_get()
                         .unwrap()
                         .cool_keyboard
@@ -359,7 +486,6 @@ extern "sysv64" fn handle_syscall(
                         .queue()
                     {
                         Some(queue) => {
-                            let slice = unsafe { dest.to_slice_mut::<u8>() };
                             let count = {
                                 let mut count = 0;
                                 while let Some(slot) = slice.get_mut(count) {
@@ -378,54 +504,77 @@ extern "sysv64" fn handle_syscall(
                             count as u64
                         }
                         None => 0,
-                    }
-                } else {
-                    0
+                    },
+                    None => 0,
                 }
             }
-            Syscall::AllocatePages(pages) => {
-                let stuff = STATIC_STUFF.try_get().unwrap();
-                let mut user_space_mem_info = stuff.user_space_mem_info.lock();
-                let UserSpaceMemInfo {
-                    user_space_heap_start,
-                    allocated_pages,
-                } = user_space_mem_info.as_mut().unwrap();
-
-                // FIXME: Check for situations where a ton of pages are requested
-                match (*allocated_pages).cmp(&pages) {
-                    Ordering::Less => {
-                        let mut frame_allocator = stuff.frame_allocator.lock();
-                        let mut mapper = stuff.mapper.lock();
-                        for i in *allocated_pages..pages {
-                            unsafe {
-                                mapper.map_to(
-                                    Page::from_start_address(*user_space_heap_start).unwrap() + i,
-                                    // FIXME: Handle errors
-                                    frame_allocator.allocate_frame().unwrap(),
-                                    PageTableFlags::PRESENT
-                                        | PageTableFlags::USER_ACCESSIBLE
-                                        | PageTableFlags::WRITABLE,
-                                    frame_allocator.deref_mut(),
-                                )
-                            }
-                            .unwrap()
-                            .flush();
-                        }
+            Syscall::AllocatePages(SyscallAllocatePagesInput {
+                total_pages,
+                heap_start,
+            }) => {
+                let return_value = SyscallAllocatePagesOutput((|| {
+                    let heap_start: *mut u64 = heap_start.into();
+                    check_user_pointer(heap_start, 1)?;
+                    if total_pages > MAX_HEAP_PAGES {
+                        return Err(AllocatePagesError::TooBig);
                     }
-                    Ordering::Equal => {}
-                    Ordering::Greater => {
-                        // FIXME: Deallocate pages
+                    let stuff = STATIC_STUFF.try_get().unwrap();
+                    let mut user_space_mem_info = stuff.user_space_mem_info.lock();
+                    let UserSpaceMemInfo {
+                        user_space_heap_start,
+                        allocated_pages,
+                    } = user_space_mem_info.as_mut().unwrap();
+                    match (*allocated_pages).cmp(&total_pages) {
+                        Ordering::Less => {
+                            let start_page =
+                                Page::<Size4KiB>::from_start_address(*user_space_heap_start)
+                                    .unwrap();
+                            let mut mapper = stuff.mapper.lock();
+                            let mut frame_allocator = stuff.frame_allocator.lock();
+                            map_pages(
+                                mapper.deref_mut(),
+                                frame_allocator.deref_mut(),
+                                Page::range(
+                                    start_page + *allocated_pages,
+                                    start_page + total_pages,
+                                ),
+                                PageTableFlags::PRESENT
+                                    | PageTableFlags::USER_ACCESSIBLE
+                                    | PageTableFlags::WRITABLE,
+                                |_, frame_allocator| frame_allocator.allocate_frame(),
+                                |frame, frame_allocator| unsafe {
+                                    frame_allocator.deallocate_frame(frame)
+                                },
+                            )
+                            .map_err(|e| match e {
+                                MapToError::FrameAllocationFailed => {
+                                    AllocatePagesError::OutOfMemory
+                                }
+                                _ => AllocatePagesError::AlreadyMapped,
+                            })?;
+                            *allocated_pages = total_pages;
+                        }
+                        Ordering::Equal => {}
+                        Ordering::Greater => {
+                            // FIXME: Deallocate pages
+                        }
                     }
-                }
-                user_space_heap_start.as_u64()
+                    unsafe { heap_start.write(user_space_heap_start.as_u64()) };
+                    Ok(())
+                })());
+                return_value.to_syscall_output().unwrap()
             }
-            Syscall::SetKeyboardInterruptHandler(user_space_interrupt) => {
+            Syscall::SetEventHandler(SyscallSetEventHandlerInput { source, handler }) => {
                 STATIC_STUFF
                     .try_get()
                     .unwrap()
-                    .cool_keyboard
-                    .set_user_space_interrupt(
-                        user_space_interrupt.map(|syscall_pointer| {
+                    .state
+                    .lock()
+                    .as_mut()
+                    .unwrap()
+                    .set_event_handler(
+                        source,
+                        handler.map(|syscall_pointer| {
                             VirtAddr::from_ptr::<()>(syscall_pointer.into())
                         }),
                     );
@@ -433,58 +582,18 @@ extern "sysv64" fn handle_syscall(
             }
             Syscall::DoneWithInterruptHandler => {
                 // Make sure lock is dropped
-                enum Action {
-                    JmpToUserMode(VirtAddr, VirtAddr),
-                    RestoreContext(AnyContext),
-                }
-                let action = {
-                    {
-                        // log::info!(
-                        //     "[{syscall:?}] State: {:#x?}",
-                        //     STATIC_STUFF.try_get().unwrap().state.lock().deref()
-                        // );
-                    };
-                    let mut user_space_state = STATIC_STUFF.try_get().unwrap().state.lock();
-                    let user_space_state = user_space_state.as_mut().unwrap();
+                let jmp_to = STATIC_STUFF
+                    .try_get()
+                    .unwrap()
+                    .state
+                    .lock()
+                    .as_mut()
+                    .unwrap()
+                    .done_with_event_handler();
+                match jmp_to {
+                    Some(jmp_to) => unsafe { jmp_to.jmp() },
                     // TODO: Return with `Err`
-                    if !user_space_state.in_keyboard_interrupt_handler {
-                        unreachable!("{:?} called outside of interrupt handler", syscall);
-                    }
-                    user_space_state.in_keyboard_interrupt_handler = false;
-                    if !user_space_state.keyboard_interrupt_queued {
-                        Action::RestoreContext(
-                            user_space_state.stack_of_saved_contexts.pop().unwrap(),
-                        )
-                    } else {
-                        // log::info!("Entering queued keyboard interrupt handler");
-                        user_space_state.keyboard_interrupt_queued = false;
-                        match USER_SPACE_INTERRUPT_HANDLER.lock().as_ref() {
-                            Some(user_space_interrupt_handler) => {
-                                let interrupt_handler_stack_end = VirtAddr::new(
-                                    match user_space_state.stack_of_saved_contexts.last().unwrap() {
-                                        AnyContext::Full(full_context) => full_context.rsp,
-                                        AnyContext::Syscall(syscall_context) => syscall_context.rsp,
-                                    },
-                                );
-                                user_space_state.in_keyboard_interrupt_handler = true;
-                                Action::JmpToUserMode(
-                                    *user_space_interrupt_handler,
-                                    interrupt_handler_stack_end,
-                                )
-                            }
-                            None => Action::RestoreContext(
-                                user_space_state.stack_of_saved_contexts.pop().unwrap(),
-                            ),
-                        }
-                    }
-                };
-                match action {
-                    Action::RestoreContext(context) => {
-                        unsafe { context.context().restore() };
-                    }
-                    Action::JmpToUserMode(code, stack_end) => {
-                        unsafe { enter_user_mode(code, stack_end) };
-                    }
+                    None => unreachable!("{:?} called outside of interrupt handler", syscall),
                 }
             }
             Syscall::DisableAndDeferMyInterrupts => {
@@ -499,87 +608,559 @@ extern "sysv64" fn handle_syscall(
                 Default::default()
             }
             Syscall::EnableAndCatchUpOnMyInterrupts => {
-                let interrupt_to_jmp_to = {
+                let jmp_to = {
                     let mut user_space_state = STATIC_STUFF.try_get().unwrap().state.lock();
                     let user_space_state = user_space_state.as_mut().unwrap();
                     user_space_state.interrupts_enabled = true;
-                    if user_space_state.keyboard_interrupt_queued {
-                        user_space_state.keyboard_interrupt_queued = false;
-                        if let Some(user_space_interrupt_handler) =
-                            USER_SPACE_INTERRUPT_HANDLER.lock().as_ref()
-                        {
-                            let interrupt_handler_stack_end = VirtAddr::new(rsp_to_restore);
-                            user_space_state.in_keyboard_interrupt_handler = true;
-                            user_space_state
-                                .stack_of_saved_contexts
-                                .push_within_capacity(AnyContext::Syscall(get_syscall_context(
-                                    Default::default(),
-                                )))
-                                .unwrap();
-                            Some((*user_space_interrupt_handler, interrupt_handler_stack_end))
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
+                    user_space_state.handle_pending(get_syscall_context(Default::default()))
                 };
-                if let Some((code, stack_end)) = interrupt_to_jmp_to {
-                    unsafe { enter_user_mode(code, stack_end) }
+                if let Some(jmp_to) = jmp_to {
+                    unsafe { jmp_to.jmp() }
                 }
                 Default::default()
             }
             Syscall::EnableMyInterruptsAndWaitUntilOneHappens => {
-                enum Action {
-                    Return(u64),
-                    JmpToUserMode(VirtAddr, VirtAddr),
-                    WaitForInterruptToHappen,
-                }
-                let action = {
+                let jmp_to = {
                     let mut user_space_state = STATIC_STUFF.try_get().unwrap().state.lock();
                     let user_space_state = user_space_state.as_mut().unwrap();
                     user_space_state.interrupts_enabled = true;
-                    if user_space_state.keyboard_interrupt_queued {
-                        user_space_state.keyboard_interrupt_queued = false;
-                        if let Some(user_space_interrupt_handler) =
-                            USER_SPACE_INTERRUPT_HANDLER.lock().as_ref()
-                        {
-                            let interrupt_handler_stack_end = VirtAddr::new(rsp_to_restore);
-                            user_space_state.in_keyboard_interrupt_handler = true;
-                            user_space_state
-                                .stack_of_saved_contexts
-                                .push_within_capacity(AnyContext::Syscall(get_syscall_context(
-                                    Default::default(),
-                                )))
-                                .unwrap();
-                            Action::JmpToUserMode(
-                                *user_space_interrupt_handler,
-                                interrupt_handler_stack_end,
-                            )
-                        } else {
-                            // Consider the interrupt to have happened (cuz there is no handler)
-                            Action::Return(Default::default())
-                        }
-                    } else {
-                        // Wait until one happens
-                        user_space_state
-                            .stack_of_saved_contexts
-                            .push_within_capacity(AnyContext::Syscall(get_syscall_context(
-                                Default::default(),
-                            )))
-                            .unwrap();
-                        Action::WaitForInterruptToHappen
+                    let syscall_context = get_syscall_context(Default::default());
+                    let jmp_to = user_space_state.handle_pending(syscall_context);
+                    if jmp_to.is_none() {
+                        user_space_state.wait_for_event(syscall_context);
                     }
+                    jmp_to
                 };
-                match action {
-                    Action::JmpToUserMode(code, stack_end) => unsafe {
-                        enter_user_mode(code, stack_end)
+                match jmp_to {
+                    Some(jmp_to) => unsafe { jmp_to.jmp() },
+                    None => loop {
+                        // Events that can't be handled yet restore the context of this loop
+                        interrupts::enable_and_hlt();
                     },
-                    Action::WaitForInterruptToHappen => {
+                }
+            }
+            Syscall::CreateChannel(output) => {
+                let output: *mut CreateChannelOutputData = output.into();
+                let return_value = SyscallChannelOutput(
+                    check_user_pointer(output, 1)
+                        .map_err(ChannelError::from)
+                        .map(|()| {
+                            let endpoints =
+                                STATIC_STUFF.try_get().unwrap().channels.lock().create();
+                            let endpoints = with_handles(|handles| {
+                                endpoints.map(|endpoint| {
+                                    handles.insert(HandleEntry {
+                                        object: KernelObject::ChannelEndpoint(Arc::new(endpoint)),
+                                        rights: Rights::DUPLICATE
+                                            | Rights::TRANSFER
+                                            | Rights::READ
+                                            | Rights::WRITE,
+                                    })
+                                })
+                            });
+                            unsafe { output.write(CreateChannelOutputData { endpoints }) };
+                        }),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::ChannelSend(SyscallChannelSendInput { endpoint, message }) => {
+                let return_value = SyscallChannelOutput(
+                    unsafe { user_slice(message) }
+                        .map_err(ChannelError::from)
+                        .and_then(|message| {
+                            let endpoint = with_handles(|handles| {
+                                handles.channel_endpoint(endpoint, Rights::WRITE)
+                            })?;
+                            STATIC_STUFF
+                                .try_get()
+                                .unwrap()
+                                .channels
+                                .lock()
+                                .send(&endpoint, message)
+                        })
+                        .inspect(|()| queue_event(EventSource::Channel)),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::ChannelCall(SyscallChannelSendInput { endpoint, message }) => {
+                let return_value = SyscallChannelCallOutput(
+                    unsafe { user_slice(message) }
+                        .map_err(ChannelError::from)
+                        .and_then(|message| {
+                            let endpoint = with_handles(|handles| {
+                                handles.channel_endpoint(endpoint, Rights::WRITE)
+                            })?;
+                            STATIC_STUFF
+                                .try_get()
+                                .unwrap()
+                                .channels
+                                .lock()
+                                .call(&endpoint, message)
+                        })
+                        .inspect(|_| queue_event(EventSource::Channel)),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::ChannelReply(SyscallChannelReplyInput {
+                endpoint,
+                transaction,
+                message,
+            }) => {
+                let return_value = SyscallChannelOutput(
+                    unsafe { user_slice(message) }
+                        .map_err(ChannelError::from)
+                        .and_then(|message| {
+                            let endpoint = with_handles(|handles| {
+                                handles.channel_endpoint(endpoint, Rights::WRITE)
+                            })?;
+                            STATIC_STUFF.try_get().unwrap().channels.lock().reply(
+                                &endpoint,
+                                transaction,
+                                message,
+                            )
+                        })
+                        .inspect(|()| queue_event(EventSource::Channel)),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::ChannelReceive(SyscallChannelReceiveInput {
+                endpoint,
+                buffer,
+                output,
+            }) => {
+                let output: *mut ReceivedMessage = output.into();
+                let return_value = SyscallChannelOutput(
+                    check_user_pointer(output, 1)
+                        .and_then(|()| unsafe { user_slice_mut(buffer) })
+                        .map_err(ChannelError::from)
+                        .and_then(|buffer| {
+                            let endpoint = with_handles(|handles| {
+                                handles.channel_endpoint(endpoint, Rights::READ)
+                            })?;
+                            STATIC_STUFF
+                                .try_get()
+                                .unwrap()
+                                .channels
+                                .lock()
+                                .receive(&endpoint, buffer)
+                        })
+                        .map(|received_message| unsafe { output.write(received_message) }),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::DuplicateHandle(SyscallDuplicateHandleInput { handle, rights }) => {
+                let return_value = SyscallNewHandleOutput(with_handles(|handles| {
+                    handles.duplicate(handle, rights)
+                }));
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::CloseHandle(handle) => {
+                let return_value = SyscallHandleOutput(
+                    with_handles(|handles| handles.remove(handle, Rights::NONE))
+                        .map(|entry| drop_kernel_object(entry.object)),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::TransferHandle(SyscallTransferHandleInput { channel, handle }) => {
+                let return_value = SyscallChannelOutput(
+                    // The handle is only removed if it can be sent, so both locks are held
+                    with_handles(|handles| {
+                        let endpoint = handles.channel_endpoint(channel, Rights::WRITE)?;
+                        STATIC_STUFF
+                            .try_get()
+                            .unwrap()
+                            .channels
+                            .lock()
+                            .send_handle(&endpoint, || handles.remove(handle, Rights::TRANSFER))
+                    })
+                    .inspect(|()| queue_event(EventSource::Channel)),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::ReceiveHandle(channel) => {
+                let return_value = SyscallChannelReceiveHandleOutput(with_handles(|handles| {
+                    let endpoint = handles.channel_endpoint(channel, Rights::READ)?;
+                    let entry = STATIC_STUFF
+                        .try_get()
+                        .unwrap()
+                        .channels
+                        .lock()
+                        .receive_handle(&endpoint)?;
+                    Ok(handles.insert(entry))
+                }));
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::CreateSharedMemory(pages) => {
+                let return_value =
+                    SyscallCreateSharedMemoryOutput(if pages > MAX_SHARED_MEMORY_PAGES {
+                        Err(SharedMemoryError::TooBig)
+                    } else {
+                        let stuff = STATIC_STUFF.try_get().unwrap();
+                        let frames = {
+                            let mapper = stuff.mapper.lock();
+                            let mut frame_allocator = stuff.frame_allocator.lock();
+                            let mut frames = Vec::with_capacity(pages as usize);
+                            while frames.len() < pages as usize {
+                                match frame_allocator.allocate_frame() {
+                                    Some(frame) => {
+                                        // Zero the frame to avoid exposing data
+                                        unsafe {
+                                            (mapper.phys_offset() + frame.start_address().as_u64())
+                                                .as_mut_ptr::<u8>()
+                                                .write_bytes(0, Size4KiB::SIZE as usize)
+                                        };
+                                        frames.push(frame);
+                                    }
+                                    None => break,
+                                }
+                            }
+                            if frames.len() < pages as usize {
+                                // Give back the frames that were already allocated
+                                for frame in frames {
+                                    unsafe { frame_allocator.deallocate_frame(frame) };
+                                }
+                                Err(SharedMemoryError::OutOfMemory)
+                            } else {
+                                Ok(frames)
+                            }
+                        };
+                        frames.map(|frames| {
+                            with_handles(|handles| {
+                                handles.insert(HandleEntry {
+                                    object: KernelObject::SharedMemory(Arc::new(SharedMemory {
+                                        frames,
+                                    })),
+                                    rights: Rights::ALL,
+                                })
+                            })
+                        })
+                    });
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::MapSharedMemory(SyscallMapSharedMemoryInput {
+                shared_memory,
+                address,
+            }) => {
+                let return_value = SyscallMapSharedMemoryOutput((|| {
+                    let (shared_memory, rights) =
+                        with_handles(|handles| handles.shared_memory(shared_memory, Rights::MAP))?;
+                    let pointer: *const u8 = address.into();
+                    let len = shared_memory.frames.len() * Size4KiB::SIZE as usize;
+                    // Checks that the address is canonical and that the whole range is in user space
+                    check_user_pointer(pointer, len)?;
+                    let start = VirtAddr::try_new(pointer as u64)
+                        .map_err(|_| SharedMemoryError::PointerNotAllowed)?;
+                    if !start.is_aligned(Size4KiB::SIZE) {
+                        return Err(SharedMemoryError::PointerNotAligned);
+                    }
+                    let stuff = STATIC_STUFF.try_get().unwrap();
+                    // The kernel maps pages in these later without checking for shared memory.
+                    // The MMIO region goes up to the end of user space, so checking this first also
+                    // makes sure that the end of the range is canonical.
+                    let heap_range = stuff
+                        .user_space_mem_info
+                        .lock()
+                        .as_ref()
+                        .unwrap()
+                        .heap_range();
+                    let mmio_range = VirtAddr::new_truncate(USER_SPACE_MMIO_START)
+                        ..VirtAddr::new_truncate(KERNEL_VIRT_MEM_START);
+                    let end = start.as_u64() + len as u64;
+                    if [heap_range, mmio_range]
+                        .iter()
+                        .any(|range| start < range.end && range.start.as_u64() < end)
+                    {
+                        return Err(SharedMemoryError::Reserved);
+                    }
+                    let start_page = Page::<Size4KiB>::from_start_address(start).unwrap();
+                    let pages =
+                        Page::range(start_page, start_page + shared_memory.frames.len() as u64);
+                    let mut mapper = stuff.mapper.lock();
+                    let mut frame_allocator = stuff.frame_allocator.lock();
+                    if pages
+                        .into_iter()
+                        .any(|page| mapper.translate_page(page).is_ok())
+                    {
+                        return Err(SharedMemoryError::AlreadyMapped);
+                    }
+                    let mut flags = PageTableFlags::PRESENT
+                        | PageTableFlags::USER_ACCESSIBLE
+                        | PageTableFlags::NO_EXECUTE;
+                    if rights.contains(Rights::WRITE) {
+                        flags |= PageTableFlags::WRITABLE;
+                    }
+                    map_pages(
+                        mapper.deref_mut(),
+                        frame_allocator.deref_mut(),
+                        pages,
+                        flags,
+                        |i, _| Some(shared_memory.frames[i as usize]),
+                        |_, _| {},
+                    )
+                    .map_err(|e| match e {
+                        MapToError::FrameAllocationFailed => SharedMemoryError::OutOfMemory,
+                        _ => SharedMemoryError::AlreadyMapped,
+                    })
+                })());
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::SetSignalHandler(SyscallSetSignalHandlerInput { signal, handler }) => {
+                STATIC_STUFF
+                    .try_get()
+                    .unwrap()
+                    .state
+                    .lock()
+                    .as_mut()
+                    .unwrap()
+                    .set_signal_handler(
+                        signal,
+                        handler.map(|syscall_pointer| {
+                            VirtAddr::from_ptr::<()>(syscall_pointer.into())
+                        }),
+                    );
+                Default::default()
+            }
+            Syscall::SendSignal(SyscallSendSignalInput { process, signal }) => {
+                let output = SyscallSendSignalOutput(with_handles(|handles| {
+                    handles.process(process, Rights::SIGNAL)
+                }));
+                let return_value = output.to_syscall_output().unwrap();
+                if output.0.is_ok() {
+                    // The only process is the one that made this syscall, so the signal is handled before the syscall returns
+                    let jmp_to = STATIC_STUFF
+                        .try_get()
+                        .unwrap()
+                        .state
+                        .lock()
+                        .as_mut()
+                        .unwrap()
+                        .on_signal(
+                            signal,
+                            AnyContext::Syscall(get_syscall_context(return_value)),
+                        );
+                    unsafe { jmp_to.jmp() }
+                }
+                return_value
+            }
+            Syscall::SignalReturn => {
+                // Make sure lock is dropped
+                let jmp_to = STATIC_STUFF
+                    .try_get()
+                    .unwrap()
+                    .state
+                    .lock()
+                    .as_mut()
+                    .unwrap()
+                    .signal_return();
+                match jmp_to {
+                    Some(jmp_to) => unsafe { jmp_to.jmp() },
+                    // TODO: Return with `Err`
+                    None => unreachable!("{:?} called outside of signal handler", syscall),
+                }
+            }
+            Syscall::Sleep(nanos) => {
+                if nanos > 0 {
+                    let deadline = monotonic_time().saturating_add(nanos);
+                    STATIC_STUFF
+                        .try_get()
+                        .unwrap()
+                        .state
+                        .lock()
+                        .as_mut()
+                        .unwrap()
+                        .sleep(deadline, get_syscall_context(Default::default()));
+                    loop {
+                        // The timer interrupt returns from this syscall when the sleep is over
                         interrupts::enable_and_hlt();
-                        unreachable!()
                     }
-                    Action::Return(return_value) => return_value,
+                }
+                Default::default()
+            }
+            Syscall::GetMonotonicTime => monotonic_time(),
+            Syscall::SetAlarm(nanos) => {
+                let deadline = (nanos > 0).then(|| monotonic_time().saturating_add(nanos));
+                STATIC_STUFF
+                    .try_get()
+                    .unwrap()
+                    .state
+                    .lock()
+                    .as_mut()
+                    .unwrap()
+                    .set_alarm(deadline);
+                Default::default()
+            }
+            Syscall::CreateTimer(input) => {
+                let now = monotonic_time();
+                let return_value = SyscallCreateTimerOutput(
+                    STATIC_STUFF
+                        .try_get()
+                        .unwrap()
+                        .state
+                        .lock()
+                        .as_mut()
+                        .unwrap()
+                        .create_timer(now, input),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::PollTimer(timer) => {
+                let return_value = SyscallPollTimerOutput(
+                    STATIC_STUFF
+                        .try_get()
+                        .unwrap()
+                        .state
+                        .lock()
+                        .as_mut()
+                        .unwrap()
+                        .poll_timer(timer),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::CancelTimer(timer) => {
+                let return_value = SyscallTimerOutput(
+                    STATIC_STUFF
+                        .try_get()
+                        .unwrap()
+                        .state
+                        .lock()
+                        .as_mut()
+                        .unwrap()
+                        .cancel_timer(timer),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::GetRealTime => real_time::get(),
+            Syscall::SetRealTime(SyscallSetRealTimeInput { clock, unix_nanos }) => {
+                let return_value = SyscallSetRealTimeOutput(
+                    with_handles(|handles| handles.real_time_clock(clock, Rights::WRITE))
+                        .map(|()| real_time::set_and_write_to_cmos(unix_nanos)),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::StartRtcTicks(SyscallStartRtcTicksInput {
+                clock,
+                divider_value,
+            }) => {
+                let return_value = SyscallRtcTicksOutput((|| {
+                    with_handles(|handles| handles.real_time_clock(clock, Rights::READ))?;
+                    let divider_value = DividerValue::new(divider_value)
+                        .ok_or(RtcTicksError::InvalidDividerValue)?;
+                    STATIC_STUFF
+                        .try_get()
+                        .unwrap()
+                        .cool_rtc
+                        .enable(divider_value);
+                    Ok(())
+                })());
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::StopRtcTicks(clock) => {
+                let return_value = SyscallRtcTicksOutput(
+                    with_handles(|handles| handles.real_time_clock(clock, Rights::READ))
+                        .map(|()| STATIC_STUFF.try_get().unwrap().cool_rtc.disable())
+                        .map_err(RtcTicksError::from),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::PollRtcTicks(clock) => {
+                let return_value = SyscallPollRtcTicksOutput(
+                    with_handles(|handles| handles.real_time_clock(clock, Rights::READ))
+                        .map(|()| STATIC_STUFF.try_get().unwrap().cool_rtc.poll()),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::StartRecordingMouse(input) => {
+                let return_value = SyscallHandleOutput(
+                    with_handles(|handles| handles.mouse(input.mouse, Rights::READ)).map(|()| {
+                        STATIC_STUFF.try_get().unwrap().cool_mouse.enable(input);
+                    }),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::StopRecordingMouse(mouse) => {
+                let return_value = SyscallHandleOutput(
+                    with_handles(|handles| handles.mouse(mouse, Rights::READ))
+                        .map(|()| STATIC_STUFF.try_get().unwrap().cool_mouse.disable()),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::StopRecordingKeyboard(keyboard) => {
+                let static_stuff = STATIC_STUFF.try_get().unwrap();
+                let return_value = SyscallHandleOutput(
+                    with_handles(|handles| handles.keyboard(keyboard, Rights::READ)).map(|()| {
+                        static_stuff.cool_keyboard.disable();
+                        static_stuff
+                            .state
+                            .lock()
+                            .as_mut()
+                            .unwrap()
+                            .set_event_handler(EventSource::Keyboard, None);
+                    }),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::ConfigureKeyboard(SyscallConfigureKeyboardInput { keyboard, command }) => {
+                let return_value = SyscallConfigureKeyboardOutput(
+                    with_handles(|handles| handles.keyboard(keyboard, Rights::WRITE))
+                        .map_err(ConfigureKeyboardError::from)
+                        .and_then(|()| ps2_keyboard::configure(command)),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::GetKeyboardLocks(keyboard) => {
+                let return_value = SyscallKeyboardLocksOutput(
+                    with_handles(|handles| handles.keyboard(keyboard, Rights::READ))
+                        .map(|()| ps2_keyboard::locks()),
+                );
+                return_value.to_syscall_output().unwrap()
+            }
+            Syscall::ReadSerial(serial, dest) => {
+                match with_handles(|handles| handles.serial(serial, Rights::READ))
+                    .ok()
+                    .and_then(|()| unsafe { user_slice_mut::<u8>(dest) }.ok())
+                {
+                    Some(slice) => STATIC_STUFF.try_get().unwrap().cool_serial.read(slice) as u64,
+                    None => 0,
+                }
+            }
+            Syscall::PollKeyEvents(keyboard, dest) => {
+                match with_handles(|handles| handles.keyboard(keyboard, Rights::READ))
+                    .ok()
+                    .and_then(|()| unsafe { user_slice_mut::<u8>(dest) }.ok())
+                {
+                    Some(slice) => {
+                        match STATIC_STUFF
+                            .try_get()
+                            .unwrap()
+                            .cool_keyboard
+                            .queue()
+                            .key_events()
+                        {
+                            Some(queue) => KeyEvent::encode_all(slice, || queue.pop()) as u64,
+                            None => 0,
+                        }
+                    }
+                    None => 0,
+                }
+            }
+            Syscall::PollMouse(mouse, dest) => {
+                match with_handles(|handles| handles.mouse(mouse, Rights::READ))
+                    .ok()
+                    .and_then(|()| unsafe { user_slice_mut::<MouseEvent>(dest) }.ok())
+                {
+                    Some(slice) => {
+                        match STATIC_STUFF.try_get().unwrap().cool_mouse.queue().queue() {
+                            Some(queue) => slice
+                                .iter_mut()
+                                .map_while(|slot| {
+                                    *slot = queue.pop()?;
+                                    Some(())
+                                })
+                                .count() as u64,
+                            None => 0,
+                        }
+                    }
+                    None => 0,
                 }
             }
         },
@@ -597,11 +1178,24 @@ extern "sysv64" fn handle_syscall(
     unsafe { syscall_context.restore() };
 }
 
+/// Devices that user space can use through syscalls
+pub struct Devices {
+    pub keyboard: CoolKeyboard,
+    pub rtc: CoolRtc,
+    pub mouse: CoolMouse,
+    pub serial: CoolSerial,
+}
+
 pub fn get_syscall_handler(
     frame_buffer: Option<&'static mut FrameBuffer>,
     mapper: Arc<spin::Mutex<OffsetPageTable<'static>>>,
     frame_allocator: Arc<spin::Mutex<BootInfoFrameAllocator>>,
-    cool_keyboard: CoolKeyboard,
+    Devices {
+        keyboard: cool_keyboard,
+        rtc: cool_rtc,
+        mouse: cool_mouse,
+        serial: cool_serial,
+    }: Devices,
     user_space_mem_info: Arc<spin::Mutex<Option<UserSpaceMemInfo>>>,
     state: Arc<Mutex<State>>,
 ) -> SyscallHandler {
@@ -611,8 +1205,12 @@ pub fn get_syscall_handler(
             mapper,
             frame_allocator,
             cool_keyboard,
+            cool_rtc,
+            cool_mouse,
+            cool_serial,
             user_space_mem_info,
             state,
+            channels: Default::default(),
         })
         .unwrap();
     SYSCALL_HANDLER
@@ -0,0 +1,92 @@
+//! Runs the kernel tests in the `kernel_tests` binary. The results are written to COM1 in the same
+//! format as `cargo test`, and then QEMU is exited with a [`QemuExitCode`].
+//! Tests are functions named `test_*` next to the code they test, and are listed in `kernel_tests.rs`.
+//! A test fails by panicking.
+
+use core::{fmt::Write, panic::PanicInfo};
+
+use alloc::sync::Arc;
+use common::ramdisk::Ramdisk;
+use conquer_once::noblock::OnceCell;
+use spin::Mutex;
+use x86_64::structures::paging::OffsetPageTable;
+
+use crate::{
+    backtrace::Backtrace,
+    memory::BootInfoFrameAllocator,
+    qemu_exit::{exit_qemu, QemuExitCode},
+    symbols::Symbolized,
+    uart::{UartWriter, COM1},
+    virt_mem_tracker::VirtMemTracker,
+};
+
+pub struct Test {
+    pub name: &'static str,
+    pub run: fn(),
+}
+
+/// Makes a [`Test`] named after the path of the test function
+#[macro_export]
+macro_rules! kernel_test {
+    ($test:path) => {
+        $crate::test_framework::Test {
+            name: stringify!($test),
+            run: $test,
+        }
+    };
+}
+
+/// Things that only exist after the kernel is initialized, for the tests that need them
+pub struct TestResources {
+    pub mapper: Arc<Mutex<OffsetPageTable<'static>>>,
+    pub frame_allocator: Arc<Mutex<BootInfoFrameAllocator>>,
+    pub virt_mem_tracker: Arc<Mutex<VirtMemTracker>>,
+    /// The ramdisk of the tests image, if there is one
+    pub ramdisk: Option<Ramdisk<'static>>,
+}
+
+static RESOURCES: OnceCell<TestResources> = OnceCell::uninit();
+static CURRENT_TEST: Mutex<Option<&'static str>> = Mutex::new(None);
+
+pub fn resources() -> &'static TestResources {
+    RESOURCES
+        .try_get()
+        .expect("Tests can only use resources while running")
+}
+
+pub fn run_tests(tests: &[Test], resources: TestResources) -> ! {
+    RESOURCES.try_init_once(|| resources).unwrap();
+    let mut w = UartWriter(COM1);
+    let _ = writeln!(w, "\nrunning {} tests", tests.len());
+    for test in tests {
+        let _ = write!(w, "test {} ... ", test.name);
+        *CURRENT_TEST.lock() = Some(test.name);
+        (test.run)();
+        let _ = writeln!(w, "ok");
+    }
+    let _ = writeln!(w, "\ntest result: ok. {} passed; 0 failed", tests.len());
+    exit_qemu(QemuExitCode::Success)
+}
+
+/// Call this from the panic handler. It fails the test that was running.
+pub fn fail(info: &PanicInfo) -> ! {
+    let mut w = UartWriter(COM1);
+    let _ = writeln!(w, "FAILED");
+    let _ = writeln!(w, "\nfailures:\n{}", info);
+    let _ = writeln!(w, "Backtrace:");
+    for return_address in Backtrace::here() {
+        let _ = writeln!(w, "  {}", Symbolized::return_address(return_address));
+    }
+    match CURRENT_TEST
+        .try_lock()
+        .and_then(|current_test| *current_test)
+    {
+        Some(name) => {
+            let _ = writeln!(w, "\ntest result: FAILED. {} failed", name);
+        }
+        None => {
+            let _ = writeln!(w, "\ntest result: FAILED. Panicked before running tests");
+        }
+    }
+    exit_qemu(QemuExitCode::Failed)
+}
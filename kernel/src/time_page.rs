@@ -0,0 +1,51 @@
+use core::arch::x86_64::_rdtsc;
+
+use common::{
+    mem::USER_SPACE_TIME_PAGE,
+    time_page::{TimeBase, TimePage},
+};
+use conquer_once::noblock::OnceCell;
+use x86_64::{
+    structures::paging::{FrameAllocator, Mapper, OffsetPageTable, Page, PageTableFlags},
+    VirtAddr,
+};
+
+use crate::{lapic_timer::tsc_per_milli, memory::BootInfoFrameAllocator, real_time};
+
+/// Accessed through the kernel's mapping of physical memory
+static TIME_PAGE: OnceCell<&'static TimePage> = OnceCell::uninit();
+
+/// Allocates the time page and maps it as read only at [`USER_SPACE_TIME_PAGE`].
+/// Call this after the LAPIC timer is calibrated and the real time is known.
+pub fn init(mapper: &mut OffsetPageTable<'static>, frame_allocator: &mut BootInfoFrameAllocator) {
+    let frame = frame_allocator.allocate_frame().unwrap();
+    let time_page = unsafe {
+        let time_page =
+            (mapper.phys_offset() + frame.start_address().as_u64()).as_mut_ptr::<TimePage>();
+        time_page.write(TimePage::new());
+        &*time_page
+    };
+    unsafe {
+        mapper.map_to(
+            Page::from_start_address(VirtAddr::new(USER_SPACE_TIME_PAGE)).unwrap(),
+            frame,
+            PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE | PageTableFlags::NO_EXECUTE,
+            frame_allocator,
+        )
+    }
+    .unwrap()
+    .flush();
+    TIME_PAGE.try_init_once(|| time_page).unwrap();
+}
+
+/// Call this from the timer interrupt handler
+pub fn update(monotonic_nanos: u64) {
+    if let Ok(time_page) = TIME_PAGE.try_get() {
+        time_page.write(TimeBase {
+            tsc: unsafe { _rdtsc() },
+            monotonic_nanos,
+            real_time_offset: real_time::offset(),
+            nanos_per_tsc: TimeBase::nanos_per_tsc(tsc_per_milli()),
+        });
+    }
+}
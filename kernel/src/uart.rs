@@ -0,0 +1,102 @@
+//! Direct access to 16550 UARTs, for things that can't go through the serial logger's lock.
+//! See https://wiki.osdev.org/Serial_Ports
+
+use core::fmt;
+
+use x86_64::instructions::port::Port;
+
+/// Also used by the serial logger
+pub const COM1: Uart = Uart::new(0x3F8);
+/// Used by the GDB stub
+pub const COM2: Uart = Uart::new(0x2F8);
+
+const INTERRUPT_ENABLE: u16 = 1;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+/// Interrupt enable bit for when a byte is received
+const DATA_AVAILABLE_INTERRUPT: u8 = 1 << 0;
+/// Line status bit that is set when there is a byte to read
+const DATA_READY: u8 = 1 << 0;
+/// Line status bit that is set when a byte can be written
+const TRANSMITTER_EMPTY: u8 = 1 << 5;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Uart {
+    base: u16,
+}
+
+impl Uart {
+    pub const fn new(base: u16) -> Self {
+        Self { base }
+    }
+
+    fn read_register(&self, offset: u16) -> u8 {
+        unsafe { Port::new(self.base + offset).read() }
+    }
+
+    fn write_register(&self, offset: u16, value: u8) {
+        unsafe { Port::new(self.base + offset).write(value) }
+    }
+
+    /// Sets it to 115200 baud, 8 data bits, no parity, and 1 stop bit, with interrupts disabled.
+    /// COM1 doesn't need this because the serial logger initializes it.
+    pub fn init(&self) {
+        self.write_register(INTERRUPT_ENABLE, 0);
+        // Set the divisor to 1 while the divisor latch is enabled
+        self.write_register(LINE_CONTROL, 0x80);
+        self.write_register(0, 1);
+        self.write_register(1, 0);
+        self.write_register(LINE_CONTROL, 0x03);
+        // Enable and clear the FIFOs
+        self.write_register(FIFO_CONTROL, 0xC7);
+        // Data terminal ready, request to send, and the output that connects the interrupt line
+        self.write_register(MODEM_CONTROL, 0x0B);
+    }
+
+    /// Only interrupt for received bytes. Writing doesn't use interrupts.
+    pub fn enable_receive_interrupt(&self) {
+        self.write_register(INTERRUPT_ENABLE, DATA_AVAILABLE_INTERRUPT);
+    }
+
+    pub fn try_read(&self) -> Option<u8> {
+        if self.read_register(LINE_STATUS) & DATA_READY != 0 {
+            Some(self.read_register(0))
+        } else {
+            None
+        }
+    }
+
+    /// Spins until a byte is received, so only use this with interrupts disabled
+    pub fn read(&self) -> u8 {
+        loop {
+            if let Some(byte) = self.try_read() {
+                break byte;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    pub fn write(&self, byte: u8) {
+        while self.read_register(LINE_STATUS) & TRANSMITTER_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        self.write_register(0, byte);
+    }
+}
+
+/// Writes without locking. Turns `\n` into `\r\n`, since terminals connected to the serial port are usually in raw mode.
+pub struct UartWriter(pub Uart);
+
+impl fmt::Write for UartWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.0.write(b'\r');
+            }
+            self.0.write(byte);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use common::{
+    mem::KERNEL_VIRT_MEM_START, syscall_allocate_pages::AllocatePagesError,
+    syscall_print::SyscallPrintError, syscall_shared_memory::SharedMemoryError,
+    syscall_slice::SyscallSlice, syscall_take_frame_buffer::TakeFrameBufferError,
+};
+use x86_64::VirtAddr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserPointerError {
+    Null,
+    NotAligned,
+    /// The user space program is not allowed to access the pointer it provided
+    NotAllowed,
+}
+
+impl From<UserPointerError> for SyscallPrintError {
+    fn from(value: UserPointerError) -> Self {
+        match value {
+            UserPointerError::Null => Self::PointerIsNull,
+            UserPointerError::NotAligned => Self::PointerNotAligned,
+            UserPointerError::NotAllowed => Self::PointerNotAllowed,
+        }
+    }
+}
+
+impl From<UserPointerError> for TakeFrameBufferError {
+    fn from(value: UserPointerError) -> Self {
+        match value {
+            UserPointerError::Null => Self::PointerIsNull,
+            UserPointerError::NotAligned => Self::PointerNotAligned,
+            UserPointerError::NotAllowed => Self::PointerNotAllowed,
+        }
+    }
+}
+
+impl From<UserPointerError> for SharedMemoryError {
+    fn from(value: UserPointerError) -> Self {
+        match value {
+            UserPointerError::Null => Self::PointerIsNull,
+            UserPointerError::NotAligned => Self::PointerNotAligned,
+            UserPointerError::NotAllowed => Self::PointerNotAllowed,
+        }
+    }
+}
+
+impl From<UserPointerError> for AllocatePagesError {
+    fn from(value: UserPointerError) -> Self {
+        match value {
+            UserPointerError::Null => Self::PointerIsNull,
+            UserPointerError::NotAligned => Self::PointerNotAligned,
+            UserPointerError::NotAllowed => Self::PointerNotAllowed,
+        }
+    }
+}
+
+/// Checks that `len` `T`s starting at `pointer` are in user space memory.
+/// Both the first and the last byte must be canonical and below the kernel, so a range can't wrap
+/// around or cross the non-canonical hole into kernel memory.
+pub fn check_user_pointer<T>(pointer: *const T, len: usize) -> Result<(), UserPointerError> {
+    if pointer.is_null() {
+        return Err(UserPointerError::Null);
+    }
+    if !pointer.is_aligned() {
+        return Err(UserPointerError::NotAligned);
+    }
+    let start = pointer as u64;
+    let bytes = (len as u64)
+        .checked_mul(size_of::<T>() as u64)
+        .ok_or(UserPointerError::NotAllowed)?;
+    // The end is exclusive, so the last byte is checked instead. An empty range only has its start.
+    let last = start
+        .checked_add(bytes.saturating_sub(1))
+        .ok_or(UserPointerError::NotAllowed)?;
+    let is_user_space = |addr: u64| {
+        VirtAddr::try_new(addr)
+            .is_ok_and(|addr| addr < VirtAddr::new_truncate(KERNEL_VIRT_MEM_START))
+    };
+    if is_user_space(start) && is_user_space(last) {
+        Ok(())
+    } else {
+        Err(UserPointerError::NotAllowed)
+    }
+}
+
+/// # Safety
+/// The memory must be mapped. The slice must not be used after the user space memory is unmapped.
+pub unsafe fn user_slice<'a, T>(slice: SyscallSlice) -> Result<&'a [T], UserPointerError> {
+    check_user_pointer::<T>(slice.into(), slice.len() as usize)?;
+    Ok(unsafe { slice.to_slice() })
+}
+
+/// # Safety
+/// The memory must be mapped. The slice must not be used after the user space memory is unmapped.
+pub unsafe fn user_slice_mut<'a, T>(slice: SyscallSlice) -> Result<&'a mut [T], UserPointerError> {
+    check_user_pointer::<T>(slice.into(), slice.len() as usize)?;
+    Ok(unsafe { slice.to_slice_mut() })
+}
+
+pub fn test_check_user_pointer() {
+    assert_eq!(
+        check_user_pointer::<u64>(core::ptr::null(), 1),
+        Err(UserPointerError::Null)
+    );
+    assert_eq!(
+        check_user_pointer(0x1001 as *const u64, 1),
+        Err(UserPointerError::NotAligned)
+    );
+    assert_eq!(
+        check_user_pointer(KERNEL_VIRT_MEM_START as *const u64, 1),
+        Err(UserPointerError::NotAllowed)
+    );
+    // Ends exactly where the lower half ends
+    let user_space_end = 0x0000_8000_0000_0000u64;
+    assert_eq!(
+        check_user_pointer((user_space_end - 8) as *const u64, 1),
+        Ok(())
+    );
+    assert_eq!(
+        check_user_pointer((user_space_end - 8) as *const u64, 2),
+        Err(UserPointerError::NotAllowed)
+    );
+    // Non-canonical
+    assert_eq!(
+        check_user_pointer(user_space_end as *const u64, 1),
+        Err(UserPointerError::NotAllowed)
+    );
+    assert_eq!(
+        check_user_pointer((KERNEL_VIRT_MEM_START - 8) as *const u64, 1),
+        Err(UserPointerError::NotAllowed)
+    );
+    // Starts in the kernel and wraps around to user space
+    assert_eq!(
+        check_user_pointer((u64::MAX - 7) as *const u64, 2),
+        Err(UserPointerError::NotAllowed)
+    );
+    assert_eq!(
+        check_user_pointer(0x1000 as *const u64, usize::MAX),
+        Err(UserPointerError::NotAllowed)
+    );
+    assert_eq!(check_user_pointer(0x1000 as *const u64, 0), Ok(()));
+}
@@ -1,18 +1,535 @@
 use alloc::vec::Vec;
-// use x86_64::VirtAddr;
+use common::{
+    event::EventSource,
+    signal::Signal,
+    syscall_timer::{SyscallCreateTimerInput, TimerError, TimerId, MAX_TIMERS},
+};
+use x86_64::{instructions::interrupts, PrivilegeLevel, VirtAddr};
 
-use crate::context::AnyContext;
+use crate::{
+    context::{AnyContext, Context, FullContext, SyscallContext},
+    enter_user_mode::enter_user_mode_with_input,
+    handle_table::HandleTable,
+};
 
-// For now since we don't have any kernel tasks and only have 1 user space task this can just be an `Option` instead of a list of tasks
+/// A user space function that the kernel calls. Sorted from highest to lowest priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Handler {
+    Signal(Signal),
+    Event(EventSource),
+}
+
+impl Handler {
+    /// Sorted from highest to lowest priority
+    fn all() -> impl Iterator<Item = Self> {
+        Signal::ALL
+            .into_iter()
+            .map(Self::Signal)
+            .chain(EventSource::ALL.into_iter().map(Self::Event))
+    }
+
+    /// The input that the handler gets called with
+    fn input(self) -> u64 {
+        match self {
+            Self::Signal(signal) => signal.number().into(),
+            Self::Event(_) => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SavedContext {
+    pub context: AnyContext,
+    /// The handler that is running on top of this context.
+    /// `None` means that the kernel is waiting for an event in a syscall, and this context returns from the syscall.
+    pub handler: Option<Handler>,
+}
+
+/// Where to go after handling an event or a syscall that changes which handler is running
+#[derive(Debug)]
+pub enum JmpTo {
+    /// Enter a handler with the given code, stack end, and input
+    UserMode(VirtAddr, VirtAddr, u64),
+    RestoreContext(AnyContext),
+    /// The process was terminated, so there is nothing to go back to. Interrupts are enabled while
+    /// halting, so that kernel threads keep running.
+    HltLoop,
+}
+
+impl JmpTo {
+    /// # Safety
+    /// Completely changes context. Make sure that all locks are dropped.
+    pub unsafe fn jmp(self) -> ! {
+        match self {
+            JmpTo::UserMode(code, stack_end, input) => unsafe {
+                enter_user_mode_with_input(code, stack_end, input)
+            },
+            JmpTo::RestoreContext(context) => unsafe { context.context().restore() },
+            JmpTo::HltLoop => loop {
+                interrupts::enable_and_hlt();
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Timer {
+    /// The monotonic time of the next expiration. `None` if it is a one-shot timer that already expired.
+    deadline: Option<u64>,
+    period: Option<u64>,
+    /// Expirations since the timer was last polled
+    expirations: u32,
+}
+
+impl Timer {
+    /// Returns `true` if the timer expired
+    fn expire(&mut self, now: u64) -> bool {
+        match self.deadline {
+            Some(deadline) if deadline <= now => {
+                let times = match self.period {
+                    Some(period) => {
+                        // Catch up on expirations that were missed
+                        let times = (now - deadline) / period + 1;
+                        self.deadline = Some(deadline + times * period);
+                        times
+                    }
+                    None => {
+                        self.deadline = None;
+                        1
+                    }
+                };
+                self.expirations = self
+                    .expirations
+                    .saturating_add(times.try_into().unwrap_or(u32::MAX));
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// Kernel threads are scheduled in `kernel_thread`, and there is only 1 user space process, so this can just be an `Option` instead of a list of processes
 #[derive(Debug)]
 pub struct UserSpaceState {
-    // TODO: Don't use a fixed size vec
-    pub stack_of_saved_contexts: Vec<AnyContext>,
-    // /// During a syscall, this is set to the stack pointer of the user space stack so that user space interrupt handlers can be called on their own stack instead of the kernel's sycall handler stack
-    // pub stack_pointer: Option<VirtAddr>,
-    pub keyboard_interrupt_queued: bool,
-    pub in_keyboard_interrupt_handler: bool,
+    /// Handlers can stack on top of each other, but only on top of handlers with a lower priority.
+    /// So there is at most 1 context for each signal and event source, and 1 for waiting for an event.
+    pub stack_of_saved_contexts: Vec<SavedContext>,
+    signal_handlers: [Option<VirtAddr>; Signal::COUNT],
+    event_handlers: [Option<VirtAddr>; EventSource::COUNT],
+    /// Signals that were sent while they couldn't be handled
+    pending_signals: [bool; Signal::COUNT],
+    /// Events that happened while they couldn't be handled. Only set for events that have a handler.
+    pending_events: [bool; EventSource::COUNT],
+    /// Only affects events. Signals are never deferred.
     pub interrupts_enabled: bool,
+    pub handles: HandleTable,
+    timers: [Option<Timer>; MAX_TIMERS],
+    /// The monotonic time that the `Sleep` syscall returns at. While sleeping, events are deferred.
+    sleep_deadline: Option<u64>,
+    /// The monotonic time that [`Signal::Alarm`] is sent at
+    alarm_deadline: Option<u64>,
+}
+
+impl UserSpaceState {
+    pub fn new(handles: HandleTable) -> Self {
+        Self {
+            stack_of_saved_contexts: Vec::with_capacity(Signal::COUNT + EventSource::COUNT + 1),
+            signal_handlers: Default::default(),
+            event_handlers: Default::default(),
+            pending_signals: Default::default(),
+            pending_events: Default::default(),
+            interrupts_enabled: true,
+            handles,
+            timers: Default::default(),
+            sleep_deadline: None,
+            alarm_deadline: None,
+        }
+    }
+
+    pub fn set_event_handler(&mut self, source: EventSource, handler: Option<VirtAddr>) {
+        self.event_handlers[source.index()] = handler;
+        if handler.is_none() {
+            self.pending_events[source.index()] = false;
+        }
+    }
+
+    pub fn set_signal_handler(&mut self, signal: Signal, handler: Option<VirtAddr>) {
+        self.signal_handlers[signal.index()] = handler;
+    }
+
+    fn handler_code(&self, handler: Handler) -> Option<VirtAddr> {
+        match handler {
+            Handler::Signal(signal) => self.signal_handlers[signal.index()],
+            Handler::Event(source) => self.event_handlers[source.index()],
+        }
+    }
+
+    fn pending(&mut self, handler: Handler) -> &mut bool {
+        match handler {
+            Handler::Signal(signal) => &mut self.pending_signals[signal.index()],
+            Handler::Event(source) => &mut self.pending_events[source.index()],
+        }
+    }
+
+    /// The running handler with the highest priority
+    fn running_handler(&self) -> Option<Handler> {
+        self.stack_of_saved_contexts
+            .iter()
+            .rev()
+            .find_map(|saved_context| saved_context.handler)
+    }
+
+    /// `true` if the kernel is waiting for an event in a syscall, and the event should return from the syscall
+    fn waiting_for_event(&self) -> bool {
+        self.sleep_deadline.is_none()
+            && matches!(
+                self.stack_of_saved_contexts.last(),
+                Some(SavedContext { handler: None, .. })
+            )
+    }
+
+    fn can_handle_now(&self, handler: Handler) -> bool {
+        self.sleep_deadline.is_none()
+            && (self.interrupts_enabled || matches!(handler, Handler::Signal(_)))
+            && self
+                .running_handler()
+                .is_none_or(|running_handler| handler < running_handler)
+    }
+
+    /// Takes the pending signal or event with the highest priority that can be handled now
+    fn take_pending(&mut self) -> Option<(Handler, VirtAddr)> {
+        let handler = Handler::all().find(|&handler| {
+            *self.pending(handler)
+                && self.handler_code(handler).is_some()
+                && self.can_handle_now(handler)
+        })?;
+        *self.pending(handler) = false;
+        Some((handler, self.handler_code(handler).unwrap()))
+    }
+
+    /// Saves `context` so that it is restored when the handler is done, and enters the handler on the same stack
+    fn enter(&mut self, context: AnyContext, handler: Handler, code: VirtAddr) -> JmpTo {
+        self.stack_of_saved_contexts
+            .push_within_capacity(SavedContext {
+                context,
+                handler: Some(handler),
+            })
+            .unwrap();
+        // Continue the stack
+        JmpTo::UserMode(code, VirtAddr::new(context.rsp()), handler.input())
+    }
+
+    /// Enters the pending handler with the highest priority that can be handled now on top of `context`, or restores `context` if there is none
+    fn enter_pending_or_restore(&mut self, context: AnyContext) -> JmpTo {
+        match self.take_pending() {
+            Some((handler, code)) => self.enter(context, handler, code),
+            None => JmpTo::RestoreContext(context),
+        }
+    }
+
+    /// Forget about all handlers so that nothing in the process runs anymore
+    pub fn exit(&mut self) {
+        self.stack_of_saved_contexts.clear();
+        self.signal_handlers = Default::default();
+        self.event_handlers = Default::default();
+        self.pending_signals = Default::default();
+        self.pending_events = Default::default();
+        self.timers = Default::default();
+        self.sleep_deadline = None;
+        self.alarm_deadline = None;
+    }
+
+    fn terminate(&mut self, signal: Signal) -> JmpTo {
+        log::info!("Process terminated by {:?} signal", signal);
+        self.exit();
+        JmpTo::HltLoop
+    }
+
+    /// Queue an event that happened during a syscall. It will be handled after the syscall, when it can be handled.
+    pub fn queue_event(&mut self, source: EventSource) {
+        if self.event_handlers[source.index()].is_some() {
+            self.pending_events[source.index()] = true;
+        }
+    }
+
+    /// Call this from an interrupt handler. `interrupted` is the context that the interrupt happened in.
+    pub fn on_event(&mut self, source: EventSource, interrupted: FullContext) -> JmpTo {
+        // This interrupt interrupted one of two things
+        // - A hlt loop from a syscall handler that is waiting for an event. The event handler returns from the syscall when it's done.
+        // - The user space process. We save this context and enter the handler.
+        // While sleeping, the kernel is also in a hlt loop, but events don't end the sleep
+        let waiting = self.waiting_for_event();
+        let handler = Handler::Event(source);
+        match self.event_handlers[source.index()] {
+            Some(code) => {
+                if self.can_handle_now(handler) {
+                    if waiting {
+                        let saved_context = self.stack_of_saved_contexts.pop().unwrap();
+                        self.enter(saved_context.context, handler, code)
+                    } else {
+                        self.enter(AnyContext::Full(interrupted), handler, code)
+                    }
+                } else {
+                    self.pending_events[source.index()] = true;
+                    JmpTo::RestoreContext(AnyContext::Full(interrupted))
+                }
+            }
+            None => {
+                if waiting {
+                    // Consider the event to have happened (cuz there is no handler)
+                    JmpTo::RestoreContext(self.stack_of_saved_contexts.pop().unwrap().context)
+                } else {
+                    // Just exit this interrupt handler
+                    JmpTo::RestoreContext(AnyContext::Full(interrupted))
+                }
+            }
+        }
+    }
+
+    /// `interrupted` is the context that continues after the signal is handled.
+    /// It must be a user space context, so this can't be called while waiting for an event.
+    pub fn on_signal(&mut self, signal: Signal, interrupted: AnyContext) -> JmpTo {
+        let handler = Handler::Signal(signal);
+        match self.signal_handlers[signal.index()] {
+            Some(code) => {
+                if self.can_handle_now(handler) {
+                    self.enter(interrupted, handler, code)
+                } else if signal == Signal::Segfault {
+                    // Continuing would just segfault again
+                    log::info!("Process terminated because of a segfault in the segfault handler");
+                    self.exit();
+                    JmpTo::HltLoop
+                } else {
+                    self.pending_signals[signal.index()] = true;
+                    JmpTo::RestoreContext(interrupted)
+                }
+            }
+            None => self.terminate(signal),
+        }
+    }
+
+    /// Call this from an interrupt handler to send a signal. `interrupted` is the context that the interrupt happened in.
+    pub fn on_signal_interrupt(&mut self, signal: Signal, interrupted: FullContext) -> JmpTo {
+        if interrupted.privilege_level() == PrivilegeLevel::Ring3 {
+            self.on_signal(signal, AnyContext::Full(interrupted))
+        } else if self.stack_of_saved_contexts.is_empty() {
+            // The process exited, or hasn't started yet
+            JmpTo::RestoreContext(AnyContext::Full(interrupted))
+        } else if self.signal_handlers[signal.index()].is_none() {
+            self.terminate(signal)
+        } else if self.waiting_for_event() && self.can_handle_now(Handler::Signal(signal)) {
+            // Like an event, the signal returns from the syscall that is waiting when the handler is done
+            let saved_context = self.stack_of_saved_contexts.pop().unwrap();
+            self.on_signal(signal, saved_context.context)
+        } else {
+            // Sleeping, or waiting in a handler with a higher priority. It's handled when the sleep or handler is done.
+            self.pending_signals[signal.index()] = true;
+            JmpTo::RestoreContext(AnyContext::Full(interrupted))
+        }
+    }
+
+    /// Returns `None` if the running handler is not the type of handler that `is_handler` is looking for
+    fn done_with_handler(&mut self, is_handler: impl FnOnce(Handler) -> bool) -> Option<JmpTo> {
+        match self.stack_of_saved_contexts.last()?.handler {
+            Some(handler) if is_handler(handler) => {}
+            // `None` is impossible because user space can't make syscalls while the kernel is waiting for an event
+            _ => return None,
+        }
+        let saved_context = self.stack_of_saved_contexts.pop().unwrap();
+        // Instead of returning to the interrupted context and then getting interrupted by a pending signal or event, go straight to its handler
+        Some(self.enter_pending_or_restore(saved_context.context))
+    }
+
+    /// Returns `None` if this was called outside of an event handler
+    pub fn done_with_event_handler(&mut self) -> Option<JmpTo> {
+        self.done_with_handler(|handler| matches!(handler, Handler::Event(_)))
+    }
+
+    /// Returns `None` if this was called outside of a signal handler
+    pub fn signal_return(&mut self) -> Option<JmpTo> {
+        self.done_with_handler(|handler| matches!(handler, Handler::Signal(_)))
+    }
+
+    /// Call this from a syscall that could make pending signals or events able to be handled.
+    /// If a handler is entered, the syscall returns with `syscall_context` when the handler is done.
+    pub fn handle_pending(&mut self, syscall_context: SyscallContext) -> Option<JmpTo> {
+        let (handler, code) = self.take_pending()?;
+        Some(self.enter(AnyContext::Syscall(syscall_context), handler, code))
+    }
+
+    /// The syscall returns with `syscall_context` after an event is handled
+    pub fn wait_for_event(&mut self, syscall_context: SyscallContext) {
+        self.stack_of_saved_contexts
+            .push_within_capacity(SavedContext {
+                context: AnyContext::Syscall(syscall_context),
+                handler: None,
+            })
+            .unwrap();
+    }
+
+    pub fn create_timer(
+        &mut self,
+        now: u64,
+        SyscallCreateTimerInput { duration, period }: SyscallCreateTimerInput,
+    ) -> Result<TimerId, TimerError> {
+        if period == Some(0) {
+            return Err(TimerError::PeriodIsZero);
+        }
+        let index = self
+            .timers
+            .iter()
+            .position(Option::is_none)
+            .ok_or(TimerError::TooManyTimers)?;
+        self.timers[index] = Some(Timer {
+            deadline: Some(now.saturating_add(duration)),
+            period,
+            expirations: 0,
+        });
+        Ok(TimerId(index as u8))
+    }
+
+    fn timer(&mut self, timer: TimerId) -> Result<&mut Option<Timer>, TimerError> {
+        self.timers
+            .get_mut(timer.index())
+            .filter(|timer| timer.is_some())
+            .ok_or(TimerError::InvalidTimer)
+    }
+
+    /// Returns the number of expirations since the last poll
+    pub fn poll_timer(&mut self, timer: TimerId) -> Result<u32, TimerError> {
+        let timer = self.timer(timer)?.as_mut().unwrap();
+        Ok(core::mem::take(&mut timer.expirations))
+    }
+
+    pub fn cancel_timer(&mut self, timer: TimerId) -> Result<(), TimerError> {
+        *self.timer(timer)? = None;
+        Ok(())
+    }
+
+    /// The `Sleep` syscall returns with `syscall_context` once a timer interrupt happens at or after `deadline`
+    pub fn sleep(&mut self, deadline: u64, syscall_context: SyscallContext) {
+        self.wait_for_event(syscall_context);
+        self.sleep_deadline = Some(deadline);
+    }
+
+    /// Replaces the previous alarm. `None` cancels it.
+    pub fn set_alarm(&mut self, deadline: Option<u64>) {
+        self.alarm_deadline = deadline;
+    }
+
+    /// Call this from the timer interrupt handler. `interrupted` is the context that the interrupt happened in.
+    pub fn on_timer_interrupt(&mut self, now: u64, interrupted: FullContext) -> JmpTo {
+        let mut expired = false;
+        for timer in self.timers.iter_mut().flatten() {
+            expired |= timer.expire(now);
+        }
+        let alarm = self
+            .alarm_deadline
+            .is_some_and(|alarm_deadline| alarm_deadline <= now);
+        if alarm {
+            self.alarm_deadline = None;
+        }
+        if self
+            .sleep_deadline
+            .is_some_and(|sleep_deadline| sleep_deadline <= now)
+        {
+            self.sleep_deadline = None;
+            if expired {
+                self.queue_event(EventSource::Timer);
+            }
+            if alarm {
+                if self.signal_handlers[Signal::Alarm.index()].is_none() {
+                    return self.terminate(Signal::Alarm);
+                }
+                self.pending_signals[Signal::Alarm.index()] = true;
+            }
+            // Return from the `Sleep` syscall, handling the signals and events that were deferred while sleeping first
+            let saved_context = self.stack_of_saved_contexts.pop().unwrap();
+            self.enter_pending_or_restore(saved_context.context)
+        } else if alarm {
+            if expired {
+                // Handled after the signal handler
+                self.queue_event(EventSource::Timer);
+            }
+            self.on_signal_interrupt(Signal::Alarm, interrupted)
+        } else if expired {
+            self.on_event(EventSource::Timer, interrupted)
+        } else {
+            JmpTo::RestoreContext(AnyContext::Full(interrupted))
+        }
+    }
 }
 
 pub type State = Option<UserSpaceState>;
+
+pub fn test_signal_interrupts() {
+    fn entered(jmp_to: JmpTo) -> (VirtAddr, u64) {
+        match jmp_to {
+            JmpTo::UserMode(code, _, input) => (code, input),
+            jmp_to => panic!("expected a handler to be entered, got {jmp_to:?}"),
+        }
+    }
+    fn restored_rip(jmp_to: JmpTo) -> u64 {
+        match jmp_to {
+            JmpTo::RestoreContext(AnyContext::Full(context)) => context.rip,
+            jmp_to => panic!("expected a full context to be restored, got {jmp_to:?}"),
+        }
+    }
+    let user = FullContext {
+        rip: 1,
+        cs: 0x1B,
+        rsp: 0x1000,
+        ..Default::default()
+    };
+    let kernel = FullContext {
+        rip: 2,
+        cs: 0x08,
+        ..Default::default()
+    };
+    let handler = VirtAddr::new(0x2000);
+    let mut state = UserSpaceState::new(HandleTable::new_with_initial_handles());
+
+    // Nothing to send it to before the process runs or after it exits
+    assert_eq!(
+        restored_rip(state.on_signal_interrupt(Signal::Terminate, kernel)),
+        kernel.rip
+    );
+
+    // User space is interrupted by the handler
+    state.set_signal_handler(Signal::Interrupt, Some(handler));
+    assert_eq!(
+        entered(state.on_signal_interrupt(Signal::Interrupt, user)),
+        (handler, Signal::Interrupt.number().into())
+    );
+    assert_eq!(restored_rip(state.signal_return().unwrap()), user.rip);
+
+    // The handler returns from a syscall that is waiting for an event
+    state.wait_for_event(Default::default());
+    assert_eq!(
+        entered(state.on_signal_interrupt(Signal::Interrupt, kernel)),
+        (handler, Signal::Interrupt.number().into())
+    );
+    assert!(matches!(
+        state.signal_return(),
+        Some(JmpTo::RestoreContext(AnyContext::Syscall(_)))
+    ));
+
+    // The alarm is sent by the timer interrupt
+    state.set_signal_handler(Signal::Alarm, Some(handler));
+    state.set_alarm(Some(10));
+    assert_eq!(restored_rip(state.on_timer_interrupt(9, user)), user.rip);
+    assert_eq!(
+        entered(state.on_timer_interrupt(10, user)),
+        (handler, Signal::Alarm.number().into())
+    );
+    assert_eq!(restored_rip(state.signal_return().unwrap()), user.rip);
+    // It is only sent once
+    assert_eq!(restored_rip(state.on_timer_interrupt(20, user)), user.rip);
+
+    // Without a handler, the process is terminated
+    assert!(matches!(
+        state.on_signal_interrupt(Signal::Terminate, user),
+        JmpTo::HltLoop
+    ));
+    assert!(state.stack_of_saved_contexts.is_empty());
+}
@@ -37,7 +37,16 @@ pub fn test_virt_addr_from_indexes_4_kib() {
             virt_addr.page_offset(),
         )
     };
-    log::info!("{:?}", info);
+    assert_eq!(
+        info,
+        (
+            PageTableIndex::new(1),
+            PageTableIndex::new(2),
+            PageTableIndex::new(3),
+            PageTableIndex::new(5),
+            PageOffset::new(7),
+        )
+    );
 }
 
 /// Get a virtual address that point to a 2MiB physical region of 512 frames
@@ -72,7 +81,15 @@ pub fn test_virt_addr_from_indexes_2_mib() {
             virt_addr.page_offset(),
         )
     };
-    log::info!("{:?}", info);
+    assert_eq!(
+        info,
+        (
+            PageTableIndex::new(1),
+            PageTableIndex::new(2),
+            PageTableIndex::new(3),
+            PageOffset::new(0),
+        )
+    );
 }
 
 /// Get a virtual address that point to a 1GiB physical region of 512 * 512 frames
@@ -100,5 +117,12 @@ pub fn test_virt_addr_from_indexes_1_gib() {
             virt_addr.page_offset(),
         )
     };
-    log::info!("{:?}", info);
+    assert_eq!(
+        info,
+        (
+            PageTableIndex::new(1),
+            PageTableIndex::new(2),
+            PageOffset::new(0),
+        )
+    );
 }
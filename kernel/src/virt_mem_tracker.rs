@@ -3,9 +3,10 @@ use core::{
     ops::{DerefMut, Range},
 };
 
+use alloc::vec::Vec;
 use util::{continuous_bool_vec::ContinuousBoolVec, remove::Remove};
 use x86_64::{
-    structures::paging::{page::PageRange, Page, PageSize},
+    structures::paging::{page::PageRange, Page, PageSize, Size4KiB},
     VirtAddr,
 };
 
@@ -95,6 +96,35 @@ impl VirtMemTracker {
     pub fn deallocate_pages_unchecked<S: PageSize>(&mut self, pages: Range<Page<S>>) {
         self.deallocate_bytes_unchecked(pages.start.start_address()..pages.end.start_address());
     }
+
+    pub fn used_ranges(&self) -> impl Iterator<Item = Range<VirtAddr>> + '_ {
+        self.used_addresses
+            .segments()
+            .filter(|(used, _)| *used)
+            .map(|(_, range)| {
+                self.starting_addr + range.start as u64..self.starting_addr + range.end as u64
+            })
+    }
+}
+
+pub fn test_virt_mem_tracker() {
+    let start = VirtAddr::new(0x1000_0000);
+    let mut tracker = VirtMemTracker::new(start..start + 0x10_0000);
+    let pages = tracker.allocate_pages::<Size4KiB>(2).unwrap();
+    assert_eq!(pages.start_address(), start);
+    assert!(tracker
+        .allocate_specific_bytes_checked(start + 0x1000..start + 0x3000)
+        .is_err());
+    let next = tracker.allocate_pages::<Size4KiB>(1).unwrap();
+    assert_eq!(next, pages + 2);
+    tracker.deallocate_pages_unchecked(pages..pages + 2);
+    let used_ranges = tracker.used_ranges().collect::<Vec<_>>();
+    assert_eq!(used_ranges.len(), 1);
+    assert_eq!(
+        used_ranges[0],
+        next.start_address()..(next + 1).start_address()
+    );
+    assert_eq!(tracker.allocate_bytes(0x2000), Some(start));
 }
 
 // TODO: Tests (very ez and very important for this)
@@ -0,0 +1,320 @@
+//! Runs QEMU without a window for tests. The serial output (COM1) is captured, bytes can be sent to
+//! COM1, and keys are pressed and screenshots are taken through the QEMU monitor.
+
+use std::{
+    env,
+    error::Error,
+    fmt::{self, Display},
+    fs,
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    process::{self, Child, ChildStdin, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{add_drive, BootType};
+
+const MONITOR_PROMPT: &[u8] = b"(qemu) ";
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long to wait for QEMU to create the monitor socket
+const MONITOR_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum HarnessError {
+    Io(io::Error),
+    /// Waited too long for something. Includes the serial output so far.
+    Timeout {
+        waiting_for: String,
+        serial_output: String,
+    },
+    /// QEMU exited while waiting for something else
+    Exited(ExitStatus),
+    InvalidScreenshot,
+}
+
+impl From<io::Error> for HarnessError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl Display for HarnessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Timeout {
+                waiting_for,
+                serial_output,
+            } => write!(
+                f,
+                "timed out waiting for {waiting_for}. Serial output:\n{serial_output}"
+            ),
+            Self::Exited(status) => write!(f, "QEMU exited unexpectedly with {status}"),
+            Self::InvalidScreenshot => write!(f, "QEMU's screenshot is not a binary PPM"),
+        }
+    }
+}
+
+impl Error for HarnessError {}
+
+/// A screenshot of QEMU's display
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screenshot {
+    pub width: usize,
+    pub height: usize,
+    /// RGB, row by row
+    pixels: Vec<u8>,
+}
+
+impl Screenshot {
+    /// Parses a binary PPM (P6) with 8 bit colors, which is what QEMU's `screendump` makes
+    pub fn from_ppm(bytes: &[u8]) -> Option<Self> {
+        let mut rest = bytes;
+        let mut next_field = || {
+            let start = rest.iter().position(|byte| !byte.is_ascii_whitespace())?;
+            let len = rest[start..]
+                .iter()
+                .position(|byte| byte.is_ascii_whitespace())?;
+            let field = std::str::from_utf8(&rest[start..start + len]).ok()?;
+            // Exactly one whitespace character comes after the header
+            rest = &rest[start + len + 1..];
+            Some(field)
+        };
+        if next_field()? != "P6" {
+            return None;
+        }
+        let width = next_field()?.parse().ok()?;
+        let height = next_field()?.parse().ok()?;
+        if next_field()? != "255" {
+            return None;
+        }
+        let pixels = rest.get(..width * height * 3)?.to_vec();
+        Some(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Returns the `[r, g, b]` of a pixel
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        let i = (y * self.width + x) * 3;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+}
+
+/// A running QEMU, which is killed when this is dropped
+pub struct Qemu {
+    child: Child,
+    /// COM1's input
+    serial_input: ChildStdin,
+    monitor: UnixStream,
+    serial_output: Arc<Mutex<Vec<u8>>>,
+    /// For the monitor socket and screenshots
+    dir: PathBuf,
+}
+
+impl Qemu {
+    /// Boots `image` with COM1 captured and without a window. `args` are passed to QEMU.
+    pub fn start(boot_type: BootType, image: &str, args: &[&str]) -> Result<Self, HarnessError> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let dir = env::temp_dir().join(format!(
+            "code-runner-{}-{}",
+            process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)?;
+        let monitor_path = dir.join("monitor.sock");
+
+        let mut qemu = Command::new("qemu-system-x86_64");
+        add_drive(&mut qemu, &boot_type, image);
+        qemu.arg("-serial").arg("stdio");
+        qemu.arg("-display").arg("none");
+        qemu.arg("-monitor").arg(format!(
+            "unix:{},server=on,wait=off",
+            monitor_path.display()
+        ));
+        qemu.args(args);
+        let mut child = qemu.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let serial_input = child.stdin.take().unwrap();
+
+        let serial_output = Arc::new(Mutex::new(Vec::new()));
+        thread::spawn({
+            let mut stdout = child.stdout.take().unwrap();
+            let serial_output = serial_output.clone();
+            move || {
+                let mut buffer = [0; 4096];
+                while let Ok(len @ 1..) = stdout.read(&mut buffer) {
+                    serial_output
+                        .lock()
+                        .unwrap()
+                        .extend_from_slice(&buffer[..len]);
+                }
+            }
+        });
+
+        let start = Instant::now();
+        let monitor = loop {
+            match UnixStream::connect(&monitor_path) {
+                Ok(monitor) => break monitor,
+                Err(e) if start.elapsed() > MONITOR_CONNECT_TIMEOUT => {
+                    let _ = child.kill();
+                    return Err(e.into());
+                }
+                Err(_) => thread::sleep(POLL_INTERVAL),
+            }
+        };
+        let mut qemu = Self {
+            child,
+            serial_input,
+            monitor,
+            serial_output,
+            dir,
+        };
+        // The monitor starts with a banner and a prompt
+        qemu.read_until_prompt()?;
+        Ok(qemu)
+    }
+
+    fn read_until_prompt(&mut self) -> io::Result<String> {
+        let mut output = Vec::new();
+        let mut buffer = [0; 1024];
+        while !output.ends_with(MONITOR_PROMPT) {
+            let len = self.monitor.read(&mut buffer)?;
+            if len == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            output.extend_from_slice(&buffer[..len]);
+        }
+        Ok(String::from_utf8_lossy(&output[..output.len() - MONITOR_PROMPT.len()]).into_owned())
+    }
+
+    /// Runs a QEMU monitor command and returns its output
+    pub fn monitor_command(&mut self, command: &str) -> io::Result<String> {
+        writeln!(self.monitor, "{command}")?;
+        self.read_until_prompt()
+    }
+
+    /// Presses and releases keys, using QEMU's key names such as `a`, `ret`, `up` or `ctrl-c`
+    pub fn send_keys(&mut self, keys: &[&str]) -> io::Result<()> {
+        for key in keys {
+            self.monitor_command(&format!("sendkey {key}"))?;
+        }
+        Ok(())
+    }
+
+    /// Sends bytes to COM1, like typing in the serial console
+    pub fn send_serial(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.serial_input.write_all(bytes)?;
+        self.serial_input.flush()
+    }
+
+    pub fn screenshot(&mut self) -> Result<Screenshot, HarnessError> {
+        let path = self.dir.join("screenshot.ppm");
+        self.monitor_command(&format!("screendump {}", path.display()))?;
+        let bytes = fs::read(&path)?;
+        fs::remove_file(&path)?;
+        Screenshot::from_ppm(&bytes).ok_or(HarnessError::InvalidScreenshot)
+    }
+
+    /// Everything written to COM1 so far
+    pub fn serial_output(&self) -> String {
+        String::from_utf8_lossy(&self.serial_output.lock().unwrap()).into_owned()
+    }
+
+    fn wait_for<T>(
+        &mut self,
+        waiting_for: impl Display,
+        timeout: Duration,
+        mut f: impl FnMut(&mut Self) -> Result<Option<T>, HarnessError>,
+    ) -> Result<T, HarnessError> {
+        let start = Instant::now();
+        loop {
+            if let Some(value) = f(self)? {
+                return Ok(value);
+            }
+            if let Some(status) = self.child.try_wait()? {
+                return Err(HarnessError::Exited(status));
+            }
+            if start.elapsed() > timeout {
+                return Err(HarnessError::Timeout {
+                    waiting_for: waiting_for.to_string(),
+                    serial_output: self.serial_output(),
+                });
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    pub fn wait_for_serial(&mut self, text: &str, timeout: Duration) -> Result<(), HarnessError> {
+        self.wait_for(format!("{text:?} on serial"), timeout, |qemu| {
+            Ok(qemu.serial_output().contains(text).then_some(()))
+        })
+    }
+
+    /// Takes screenshots until one matches `f`
+    pub fn wait_for_screen(
+        &mut self,
+        description: &str,
+        timeout: Duration,
+        f: impl Fn(&Screenshot) -> bool,
+    ) -> Result<Screenshot, HarnessError> {
+        self.wait_for(description, timeout, |qemu| {
+            let screenshot = qemu.screenshot()?;
+            Ok(f(&screenshot).then_some(screenshot))
+        })
+    }
+
+    pub fn wait_for_exit(&mut self, timeout: Duration) -> Result<ExitStatus, HarnessError> {
+        let start = Instant::now();
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                // Let the serial thread read the rest of the output
+                thread::sleep(POLL_INTERVAL);
+                return Ok(status);
+            }
+            if start.elapsed() > timeout {
+                return Err(HarnessError::Timeout {
+                    waiting_for: "QEMU to exit".into(),
+                    serial_output: self.serial_output(),
+                });
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for Qemu {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Screenshot;
+
+    #[test]
+    fn parse_ppm() {
+        let mut ppm = b"P6\n2 1\n255\n".to_vec();
+        ppm.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+        let screenshot = Screenshot::from_ppm(&ppm).unwrap();
+        assert_eq!((screenshot.width, screenshot.height), (2, 1));
+        assert_eq!(screenshot.pixel(0, 0), [1, 2, 3]);
+        assert_eq!(screenshot.pixel(1, 0), [4, 5, 6]);
+    }
+
+    #[test]
+    fn parse_ppm_too_short() {
+        assert_eq!(Screenshot::from_ppm(b"P6\n2 1\n255\n\x01\x02"), None);
+    }
+}
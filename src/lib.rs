@@ -1,17 +1,117 @@
 use std::{
-    env,
+    fs,
+    path::{Path, PathBuf},
     process::{self, Command},
 };
 
+use bootloader::DiskImageBuilder;
+use common::ramdisk::{ramdisk_len, write_ramdisk, FileKind, RamdiskFile};
+use runner::RunOptions;
+
+pub mod harness;
+pub mod runner;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BootType {
     Bios,
     Uefi,
 }
 
-pub fn run_qemu(boot_type: BootType) {
-    println!("{}", env!("UEFI_IMAGE"));
-    println!("{}", env!("CARGO_BIN_FILE_KERNEL"));
-    println!("{}", env!("USER_SPACE"));
+/// The device that the kernel tests write their result to
+pub const ISA_DEBUG_EXIT_DEVICE: &str = "isa-debug-exit,iobase=0xf4,iosize=0x04";
+/// QEMU's exit code when all kernel tests pass. See `kernel/src/qemu_exit.rs`.
+pub const KERNEL_TESTS_SUCCESS: i32 = 33;
+/// QEMU's exit code when a kernel test fails
+pub const KERNEL_TESTS_FAILED: i32 = 35;
+
+fn add_drive(qemu: &mut Command, boot_type: &BootType, image: &str) {
+    qemu.arg("-drive");
+    qemu.arg(format!("format=raw,file={image}"));
+    if let BootType::Uefi = boot_type {
+        qemu.arg("-bios").arg(ovmf_prebuilt::ovmf_pure_efi());
+    }
+}
+
+/// A disk image that the build made from `images.toml`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Image {
+    pub name: &'static str,
+    /// The ELF of the init program
+    pub init: &'static str,
+    pub uefi: &'static str,
+    pub bios: &'static str,
+}
+
+impl Image {
+    pub fn disk_image(&self, boot_type: &BootType) -> &'static str {
+        match boot_type {
+            BootType::Bios => self.bios,
+            BootType::Uefi => self.uefi,
+        }
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/images.rs"));
+
+pub fn image(name: &str) -> Option<&'static Image> {
+    IMAGES.iter().find(|image| image.name == name)
+}
+
+/// The image with the kernel that runs the kernel tests, which should be run with [`ISA_DEBUG_EXIT_DEVICE`].
+/// QEMU exits with [`KERNEL_TESTS_SUCCESS`] or [`KERNEL_TESTS_FAILED`].
+pub fn kernel_tests_disk_image(boot_type: &BootType) -> &'static str {
+    match boot_type {
+        BootType::Bios => env!("KERNEL_TESTS_BIOS_IMAGE"),
+        BootType::Uefi => env!("KERNEL_TESTS_UEFI_IMAGE"),
+    }
+}
+
+/// Makes a disk image like the ones from the build, but with only `user_program` in the ramdisk
+fn build_disk_image(boot_type: &BootType, user_program: &Path) -> PathBuf {
+    let name = user_program
+        .file_stem()
+        .map_or("user-program".into(), |name| name.to_string_lossy());
+    let contents = fs::read(user_program).expect("unable to read user program");
+    let files = [RamdiskFile {
+        name: &name,
+        kind: FileKind::Program,
+        contents: &contents,
+    }];
+    let mut ramdisk = vec![0; ramdisk_len(&files)];
+    write_ramdisk(&files, 0, &mut ramdisk).unwrap();
+    let ramdisk_path = Path::new(IMAGES[0].disk_image(boot_type))
+        .with_file_name(format!("code-runner-{name}.ramdisk"));
+    fs::write(&ramdisk_path, ramdisk).expect("unable to write ramdisk");
+
+    let image = ramdisk_path.with_file_name(match boot_type {
+        BootType::Bios => format!("code-runner-{name}-bios.img"),
+        BootType::Uefi => format!("code-runner-{name}-uefi.img"),
+    });
+    let mut disk_builder = DiskImageBuilder::new(PathBuf::from(env!("CARGO_BIN_FILE_KERNEL")));
+    disk_builder.set_ramdisk(ramdisk_path);
+    match boot_type {
+        BootType::Bios => disk_builder.create_bios_image(&image),
+        BootType::Uefi => disk_builder.create_uefi_image(&image),
+    }
+    .expect("unable to create disk image");
+    image
+}
+
+pub fn run_qemu(options: &RunOptions) {
+    let image = match &options.image {
+        Some(name) => image(name).unwrap_or_else(|| {
+            let names = IMAGES.iter().map(|image| image.name).collect::<Vec<_>>();
+            eprintln!("There is no image named {name}. The images are {names:?}.");
+            process::exit(2);
+        }),
+        None => &IMAGES[0],
+    };
+    let user_program = options
+        .user_program
+        .as_ref()
+        .map_or(image.init.into(), |user_program| {
+            user_program.display().to_string()
+        });
 
     #[cfg(debug_assertions)]
     {
@@ -29,48 +129,34 @@ pub fn run_qemu(boot_type: BootType) {
         )
         .expect("unable to create debug file");
 
-        let user_space_binary = env!("USER_SPACE");
         let user_space_debug_file = "debug_userspace.lldb";
         std::fs::write(
             user_space_debug_file,
             [
-                format!("target create {user_space_binary}"),
-                format!("target modules load --file {user_space_binary} --slide 0x0"),
+                format!("target create {user_program}"),
+                format!("target modules load --file {user_program} --slide 0x0"),
                 "gdb-remote localhost:1234".into(),
             ]
             .join("\n"),
         )
         .expect("unable to create debug file");
 
-        println!("debug file is ready, run `lldb -s {}` to start debugging the kernel, or `lldb -s {}` to start debugging the user space program.", kernel_debug_file, user_space_debug_file);
+        if options.gdb || options.gdb_wait {
+            println!("debug file is ready, run `lldb -s {}` to start debugging the kernel, or `lldb -s {}` to start debugging the user space program.", kernel_debug_file, user_space_debug_file);
+        }
     }
 
+    let disk_image = match &options.user_program {
+        Some(user_program) => build_disk_image(&options.boot_type, user_program),
+        None => image.disk_image(&options.boot_type).into(),
+    };
     let mut qemu = Command::new("qemu-system-x86_64");
-    // To increase memory
-    // qemu.arg("-m");
-    // qemu.arg("4G");
-    // To show serial port in terminal
-    // qemu.arg("-serial").arg("stdio");
-    // To enable debugging and pause
-    // qemu.arg("-s").arg("-S");
-    qemu.arg("-drive");
-    qemu.arg(format!(
-        "format=raw,file={}",
-        match boot_type {
-            BootType::Bios => {
-                env!("BIOS_IMAGE")
-            }
-            BootType::Uefi => {
-                env!("UEFI_IMAGE")
-            }
-        }
-    ));
-    if let BootType::Uefi = boot_type {
-        qemu.arg("-bios").arg(ovmf_prebuilt::ovmf_pure_efi());
-    }
-    env::args().skip(1).for_each(|arg| {
-        qemu.arg(arg);
-    });
+    add_drive(
+        &mut qemu,
+        &options.boot_type,
+        &disk_image.display().to_string(),
+    );
+    qemu.args(options.qemu_args());
     let exit_status = qemu.status().unwrap();
     process::exit(exit_status.code().unwrap_or(-1));
 }
@@ -0,0 +1,18 @@
+use std::{env, process};
+
+use code_runner::{
+    run_qemu,
+    runner::{RunOptions, USAGE},
+};
+
+fn main() {
+    let options = RunOptions::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{e}\n\n{USAGE}");
+        process::exit(2);
+    });
+    if options.help {
+        println!("{USAGE}");
+        return;
+    }
+    run_qemu(&options);
+}
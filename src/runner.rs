@@ -0,0 +1,239 @@
+//! Options for running the kernel in QEMU, parsed from the command line of `cargo r`
+
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    path::PathBuf,
+};
+
+use crate::BootType;
+
+pub const USAGE: &str = "\
+Usage: cargo r -- [OPTIONS] [-- QEMU ARGS...]
+
+Options:
+  --bios                  Boot with BIOS instead of UEFI
+  --uefi                  Boot with UEFI (the default)
+  -m, --memory <SIZE>     Memory size, such as 512M or 4G
+  --smp <CPUS>            Number of CPUs
+  --kvm                   Use KVM acceleration
+  --headless              Don't open a window
+  --serial-file <FILE>    Write COM1 to a file instead of the terminal
+  --gdb                   Start QEMU's GDB server on localhost:1234
+  --gdb-wait              Like --gdb, but wait for GDB to continue before booting
+  --image <NAME>          Boot one of the images in images.toml instead of the first one
+  --user-program <ELF>    Boot an image with only this user space program
+  -h, --help              Show this
+
+Anything after -- is passed to QEMU.";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunOptions {
+    pub boot_type: BootType,
+    pub memory: Option<String>,
+    pub smp: Option<u32>,
+    pub kvm: bool,
+    pub headless: bool,
+    /// If this is `None`, COM1 is shown in the terminal
+    pub serial_file: Option<PathBuf>,
+    pub gdb: bool,
+    /// Pause until GDB continues. Implies `gdb`.
+    pub gdb_wait: bool,
+    /// The name of an image in `images.toml`. If this is `None`, the first image is used.
+    pub image: Option<String>,
+    /// Boot an image with only this program, instead of one made at build time
+    pub user_program: Option<PathBuf>,
+    pub qemu_args: Vec<String>,
+    pub help: bool,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            boot_type: BootType::Uefi,
+            memory: None,
+            smp: None,
+            kvm: false,
+            headless: false,
+            serial_file: None,
+            gdb: false,
+            gdb_wait: false,
+            image: None,
+            user_program: None,
+            qemu_args: Vec::new(),
+            help: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue { option: String, value: String },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownOption(option) => write!(f, "unknown option {option:?}"),
+            Self::MissingValue(option) => write!(f, "{option} needs a value"),
+            Self::InvalidValue { option, value } => {
+                write!(f, "invalid value {value:?} for {option}")
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl RunOptions {
+    /// Parses the arguments, without the program name
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ParseError> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| ParseError::MissingValue(arg.clone()))
+            };
+            match arg.as_str() {
+                "--bios" => options.boot_type = BootType::Bios,
+                "--uefi" => options.boot_type = BootType::Uefi,
+                "-m" | "--memory" => options.memory = Some(value()?),
+                "--smp" => {
+                    let value = value()?;
+                    options.smp = Some(match value.parse() {
+                        Ok(cpus @ 1..) => cpus,
+                        _ => return Err(ParseError::InvalidValue { option: arg, value }),
+                    });
+                }
+                "--kvm" => options.kvm = true,
+                "--headless" => options.headless = true,
+                "--serial-file" => options.serial_file = Some(value()?.into()),
+                "--gdb" => options.gdb = true,
+                "--gdb-wait" => options.gdb_wait = true,
+                "--image" => options.image = Some(value()?),
+                "--user-program" => options.user_program = Some(value()?.into()),
+                "-h" | "--help" => options.help = true,
+                "--" => {
+                    options.qemu_args.extend(args);
+                    break;
+                }
+                _ => return Err(ParseError::UnknownOption(arg)),
+            }
+        }
+        Ok(options)
+    }
+
+    /// The QEMU arguments for everything except the drive
+    pub fn qemu_args(&self) -> Vec<String> {
+        let mut qemu_args = Vec::new();
+        if let Some(memory) = &self.memory {
+            qemu_args.extend(["-m".into(), memory.clone()]);
+        }
+        if let Some(smp) = self.smp {
+            qemu_args.extend(["-smp".into(), smp.to_string()]);
+        }
+        if self.kvm {
+            qemu_args.extend(["-accel".into(), "kvm".into(), "-cpu".into(), "host".into()]);
+        }
+        if self.headless {
+            qemu_args.extend(["-display".into(), "none".into()]);
+        }
+        qemu_args.push("-serial".into());
+        qemu_args.push(match &self.serial_file {
+            Some(serial_file) => format!("file:{}", serial_file.display()),
+            None => "stdio".into(),
+        });
+        if self.gdb || self.gdb_wait {
+            qemu_args.push("-s".into());
+        }
+        if self.gdb_wait {
+            qemu_args.push("-S".into());
+        }
+        qemu_args.extend(self.qemu_args.iter().cloned());
+        qemu_args
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ParseError, RunOptions};
+    use crate::BootType;
+
+    fn parse(args: &[&str]) -> Result<RunOptions, ParseError> {
+        RunOptions::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn defaults() {
+        let options = parse(&[]).unwrap();
+        assert_eq!(options, RunOptions::default());
+        assert_eq!(options.qemu_args(), ["-serial", "stdio"]);
+    }
+
+    #[test]
+    fn all_options() {
+        let options = parse(&[
+            "--bios",
+            "-m",
+            "4G",
+            "--smp",
+            "4",
+            "--kvm",
+            "--headless",
+            "--serial-file",
+            "serial.log",
+            "--gdb-wait",
+            "--image",
+            "demos",
+            "--",
+            "-serial",
+            "tcp::1235,server,nowait",
+        ])
+        .unwrap();
+        assert_eq!(options.boot_type, BootType::Bios);
+        assert_eq!(options.image.as_deref(), Some("demos"));
+        assert_eq!(
+            options.qemu_args(),
+            [
+                "-m",
+                "4G",
+                "-smp",
+                "4",
+                "-accel",
+                "kvm",
+                "-cpu",
+                "host",
+                "-display",
+                "none",
+                "-serial",
+                "file:serial.log",
+                "-s",
+                "-S",
+                "-serial",
+                "tcp::1235,server,nowait"
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse(&["--memory"]),
+            Err(ParseError::MissingValue("--memory".into()))
+        );
+        assert_eq!(
+            parse(&["--smp", "0"]),
+            Err(ParseError::InvalidValue {
+                option: "--smp".into(),
+                value: "0".into()
+            })
+        );
+        assert_eq!(
+            parse(&["-serial", "stdio"]),
+            Err(ParseError::UnknownOption("-serial".into()))
+        );
+    }
+}
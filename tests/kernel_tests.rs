@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use code_runner::{
+    harness::Qemu, kernel_tests_disk_image, BootType, ISA_DEBUG_EXIT_DEVICE, KERNEL_TESTS_SUCCESS,
+};
+
+fn run_kernel_tests(boot_type: BootType) {
+    let mut qemu = Qemu::start(
+        boot_type,
+        kernel_tests_disk_image(&boot_type),
+        // A triple fault should fail the tests instead of running them again
+        &["-device", ISA_DEBUG_EXIT_DEVICE, "-no-reboot"],
+    )
+    .unwrap();
+    let status = qemu.wait_for_exit(Duration::from_secs(120)).unwrap();
+    assert_eq!(
+        status.code(),
+        Some(KERNEL_TESTS_SUCCESS),
+        "kernel tests failed. Serial output:\n{}",
+        qemu.serial_output()
+    );
+}
+
+#[test]
+fn kernel_tests_bios() {
+    run_kernel_tests(BootType::Bios);
+}
+
+#[test]
+fn kernel_tests_uefi() {
+    run_kernel_tests(BootType::Uefi);
+}
@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use code_runner::{
+    harness::{Qemu, Screenshot},
+    image, BootType,
+};
+
+const BALL_COLOR: [u8; 3] = [0, 0, 255];
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Checks the center of a cell in a level that is `level_size` cells wide and tall
+fn is_ball_at(screenshot: &Screenshot, level_size: usize, x: usize, y: usize) -> bool {
+    let cell_size = (screenshot.width / level_size).min(screenshot.height / level_size);
+    screenshot.pixel(x * cell_size + cell_size / 2, y * cell_size + cell_size / 2) == BALL_COLOR
+}
+
+fn play_maze_game(boot_type: BootType) {
+    let mut qemu = Qemu::start(
+        boot_type,
+        image("game").unwrap().disk_image(&boot_type),
+        &[],
+    )
+    .unwrap();
+    qemu.wait_for_serial("Playing Maze Roller Game!", TIMEOUT)
+        .unwrap();
+    qemu.wait_for_screen("the ball at the start of level 1", TIMEOUT, |screenshot| {
+        is_ball_at(screenshot, 4, 0, 2)
+    })
+    .unwrap();
+
+    qemu.send_keys(&["right", "up", "up", "right", "right"])
+        .unwrap();
+    qemu.wait_for_screen("the ball at the end of level 1", TIMEOUT, |screenshot| {
+        is_ball_at(screenshot, 4, 3, 0)
+    })
+    .unwrap();
+
+    qemu.send_keys(&["ret"]).unwrap();
+    qemu.wait_for_screen("the ball at the start of level 2", TIMEOUT, |screenshot| {
+        is_ball_at(screenshot, 8, 6, 6)
+    })
+    .unwrap();
+}
+
+#[test]
+fn maze_game_bios() {
+    play_maze_game(BootType::Bios);
+}
+
+#[test]
+fn maze_game_uefi() {
+    play_maze_game(BootType::Uefi);
+}
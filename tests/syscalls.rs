@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+use code_runner::{harness::Qemu, image, BootType};
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+/// Ctrl+], which opens the kernel debug monitor
+const MONITOR_KEY: u8 = 0x1D;
+
+fn run_syscall_tests(boot_type: BootType) {
+    let mut qemu = Qemu::start(
+        boot_type,
+        image("syscalls").unwrap().disk_image(&boot_type),
+        &[],
+    )
+    .unwrap();
+    qemu.wait_for_serial("All syscall tests passed", TIMEOUT)
+        .unwrap();
+    qemu.wait_for_serial("Process exited", TIMEOUT).unwrap();
+
+    // The debug monitor is opened by a serial interrupt, so this only works if the kernel still
+    // handles interrupts after the process exited
+    qemu.send_serial(&[MONITOR_KEY]).unwrap();
+    qemu.wait_for_serial("Kernel debug monitor", TIMEOUT)
+        .unwrap();
+    qemu.send_serial(b"help\r").unwrap();
+    qemu.wait_for_serial("Commands:", TIMEOUT).unwrap();
+    qemu.send_serial(b"continue\r").unwrap();
+}
+
+#[test]
+fn syscalls_bios() {
+    run_syscall_tests(BootType::Bios);
+}
+
+#[test]
+fn syscalls_uefi() {
+    run_syscall_tests(BootType::Uefi);
+}
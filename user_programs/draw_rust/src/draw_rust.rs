@@ -0,0 +1,44 @@
+use core::{cell::RefCell, fmt::Debug};
+
+use alloc::{rc::Rc, vec::Vec};
+use embedded_graphics::{
+    image::Image,
+    pixelcolor::{Gray8, Rgb555, Rgb888},
+    prelude::{DrawTarget, Drawable, OriginDimensions, PixelColor, Point},
+};
+use tinytga::Tga;
+use user_runtime::executor::{yield_now, Spawner};
+
+/// Each row of logos is drawn by its own task. The tasks take turns drawing a logo, so the rows fill in at the same time.
+pub async fn draw_rust<D: DrawTarget + OriginDimensions + 'static>(
+    spawner: &Spawner,
+    display: Rc<RefCell<D>>,
+) where
+    D::Error: Debug,
+    D::Color: PixelColor + From<Gray8> + From<Rgb555> + From<Rgb888>,
+{
+    let data = include_bytes!("../../../rust-pride.tga");
+    let image_size = 64;
+    let size = display.borrow().size();
+
+    let rows = (0..size.height.div_ceil(image_size))
+        .map(|pos_y| {
+            let display = display.clone();
+            spawner.spawn(async move {
+                let tga: Tga<D::Color> = Tga::from_slice(data).unwrap();
+                for pos_x in 0..size.width.div_ceil(image_size) {
+                    Image::new(
+                        &tga,
+                        Point::new((pos_x * image_size) as i32, (pos_y * image_size) as i32),
+                    )
+                    .draw(&mut *display.borrow_mut())
+                    .unwrap();
+                    yield_now().await;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for row in rows {
+        row.await;
+    }
+}
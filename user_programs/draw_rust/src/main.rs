@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+mod draw_rust;
+
+use core::cell::RefCell;
+
+use alloc::{boxed::Box, rc::Rc};
+use common::handle::initial_handles;
+use draw_rust::draw_rust;
+use user_runtime::{
+    embedded_graphics_frame_buffer::FrameBufferDisplay,
+    entry,
+    executor::Spawner,
+    syscall::{syscall_print, syscall_take_frame_buffer},
+};
+
+#[entry]
+async fn main(spawner: Spawner) {
+    // The tasks that draw need the frame buffer for as long as they run
+    let frame_buffer = Box::leak(Box::new(
+        syscall_take_frame_buffer(initial_handles::FRAME_BUFFER).unwrap(),
+    ));
+    syscall_print("Drawing Rust logos").unwrap();
+    draw_rust(
+        &spawner,
+        Rc::new(RefCell::new(FrameBufferDisplay::new(frame_buffer))),
+    )
+    .await;
+}
@@ -1,5 +1,6 @@
 use core::fmt::Debug;
 
+use common::key_event::{KeyCode, KeyEvent, KeyState};
 use embedded_graphics::{
     mono_font::{iso_8859_16::FONT_10X20, MonoTextStyleBuilder},
     pixelcolor::Rgb888,
@@ -9,9 +10,8 @@ use embedded_graphics::{
 };
 use futures::{pin_mut, Stream, StreamExt};
 // use futures_util::StreamExt;
-use pc_keyboard::{layouts, HandleControl, KeyCode, KeyState, Keyboard, ScancodeSet1};
 
-use crate::embedded_graphics_frame_buffer::Position;
+use user_runtime::embedded_graphics_frame_buffer::Position;
 
 // use crate::{
 //     frame_buffer::{Display, Position},
@@ -186,8 +186,8 @@ const LEVELS: &[&[&[Cell]]] = &[
     ],
 ];
 
-/// A game which just needs a draw target and async keyboard input
-pub async fn demo_maze_roller_game<D: DrawTarget, K: Stream<Item = u8>>(
+/// A game which just needs a draw target and async key events
+pub async fn demo_maze_roller_game<D: DrawTarget, K: Stream<Item = KeyEvent>>(
     display: &mut D,
     async_keyboard: K,
 ) where
@@ -196,25 +196,13 @@ pub async fn demo_maze_roller_game<D: DrawTarget, K: Stream<Item = u8>>(
 {
     display.clear(Rgb888::CSS_GRAY.into()).unwrap();
     let mut current_level = 0;
-    let stream = async_keyboard
-        .filter_map({
-            let mut keyboard = Keyboard::new(
-                ScancodeSet1::new(),
-                layouts::Us104Key,
-                HandleControl::Ignore,
-            );
-            move |scancode| {
-                let output = keyboard.add_byte(scancode).ok().flatten();
-                async { output }
-            }
-        })
-        .filter(|key_event| {
-            let output = match key_event.state {
-                KeyState::Down | KeyState::SingleShot => true,
-                KeyState::Up => false,
-            };
-            async move { output }
-        });
+    let stream = async_keyboard.filter(|key_event| {
+        let output = match key_event.state {
+            KeyState::Down | KeyState::SingleShot => true,
+            KeyState::Up => false,
+        };
+        async move { output }
+    });
     pin_mut!(stream);
     loop {
         let level = LEVELS[current_level];
@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+#![feature(int_roundings)]
+
+mod demo_maze_roller_game;
+
+use common::{
+    handle::initial_handles, key_event::KeyboardLayout,
+    syscall_start_recording_keyboard::FullQueueBehavior,
+};
+use demo_maze_roller_game::demo_maze_roller_game;
+use futures::{stream, StreamExt};
+use user_runtime::{
+    async_keyboard::AsyncKeyEvents,
+    embedded_graphics_frame_buffer::FrameBufferDisplay,
+    entry,
+    syscall::{syscall_print, syscall_take_frame_buffer},
+};
+
+#[entry]
+async fn main() {
+    let mut frame_buffer = syscall_take_frame_buffer(initial_handles::FRAME_BUFFER).unwrap();
+    syscall_print("Playing Maze Roller Game!").unwrap();
+    demo_maze_roller_game(
+        &mut FrameBufferDisplay::new(&mut frame_buffer),
+        AsyncKeyEvents::<64>::new(
+            initial_handles::KEYBOARD,
+            FullQueueBehavior::DropNewest,
+            KeyboardLayout::Us,
+        )
+        .unwrap()
+        .flat_map(stream::iter),
+    )
+    .await;
+}
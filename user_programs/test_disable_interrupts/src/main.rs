@@ -0,0 +1,12 @@
+#![no_std]
+#![no_main]
+
+mod test_disable_interrupts;
+
+use test_disable_interrupts::test_disable_interrupts;
+use user_runtime::entry;
+
+#[entry]
+fn main() -> ! {
+    test_disable_interrupts()
+}
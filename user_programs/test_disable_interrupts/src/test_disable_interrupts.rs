@@ -1,22 +1,29 @@
-use common::syscall_start_recording_keyboard::{
-    FullQueueBehavior, SyscallStartRecordingKeyboardInput,
+use common::{
+    event::EventSource,
+    handle::initial_handles,
+    syscall_start_recording_keyboard::{
+        FullQueueBehavior, KeyboardMode, SyscallStartRecordingKeyboardInput,
+    },
 };
 
-use crate::syscall::{
+use user_runtime::syscall::{
     syscall_disable_and_defer_my_interrupts, syscall_done_with_interrupt_handler,
     syscall_enable_and_catch_up_on_my_interrupts,
     syscall_enable_my_interrupts_and_wait_until_one_happens, syscall_print,
-    syscall_set_keyboard_interrupt_handler, syscall_start_recording_keyboard,
+    syscall_set_event_handler, syscall_start_recording_keyboard,
 };
 
 /// This is used to make sure that enabling and disabling interrupt works
 pub fn test_disable_interrupts() -> ! {
     syscall_disable_and_defer_my_interrupts();
     syscall_start_recording_keyboard(SyscallStartRecordingKeyboardInput {
+        keyboard: initial_handles::KEYBOARD,
         queue_size: 256,
         behavior_on_full_queue: FullQueueBehavior::DropNewest,
-    });
-    syscall_set_keyboard_interrupt_handler(Some(keyboard_interrupt_handler));
+        mode: KeyboardMode::ScanCodes,
+    })
+    .unwrap();
+    syscall_set_event_handler(EventSource::Keyboard, Some(keyboard_interrupt_handler));
     syscall_print("Interrupts disabled").unwrap();
     for _ in 0..50_000_000 {}
     syscall_enable_and_catch_up_on_my_interrupts();
@@ -0,0 +1,12 @@
+#![no_std]
+#![no_main]
+
+mod test_syscalls;
+
+use test_syscalls::test_syscalls;
+use user_runtime::entry;
+
+#[entry]
+fn main() {
+    test_syscalls()
+}
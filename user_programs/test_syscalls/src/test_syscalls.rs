@@ -0,0 +1,111 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use common::{
+    handle::initial_handles,
+    mem::USER_SPACE_MMIO_START,
+    signal::Signal,
+    syscall_allocate_pages::{AllocatePagesError, MAX_HEAP_PAGES},
+    syscall_shared_memory::SharedMemoryError,
+    syscall_take_frame_buffer::TakeFrameBufferError,
+};
+use user_runtime::{
+    allocator::INITIAL_HEAP_PAGES,
+    syscall::{
+        syscall_allocate_pages, syscall_create_shared_memory, syscall_map_shared_memory,
+        syscall_print, syscall_send_signal, syscall_set_signal_handler, syscall_signal_return,
+        syscall_take_frame_buffer,
+    },
+};
+use x86_64::{
+    structures::paging::{PageSize, Size4KiB},
+    VirtAddr,
+};
+
+/// The QEMU tests wait for this, and then check that the kernel keeps handling interrupts after the program exits
+const PASSED: &str = "All syscall tests passed";
+
+static SIGNAL_HANDLED: AtomicBool = AtomicBool::new(false);
+
+/// Makes syscalls with valid and invalid inputs. A failed check panics, which exits without printing [`PASSED`].
+pub fn test_syscalls() {
+    test_allocate_pages();
+    test_map_shared_memory();
+    test_take_frame_buffer();
+    test_signals();
+    syscall_print(PASSED).unwrap();
+}
+
+fn test_allocate_pages() {
+    let heap_start = syscall_allocate_pages(INITIAL_HEAP_PAGES).unwrap();
+    assert_eq!(
+        syscall_allocate_pages(MAX_HEAP_PAGES + 1),
+        Err(AllocatePagesError::TooBig)
+    );
+    // The heap grows in place
+    assert_eq!(
+        syscall_allocate_pages(INITIAL_HEAP_PAGES + 1),
+        Ok(heap_start)
+    );
+    let new_page = heap_start + INITIAL_HEAP_PAGES * Size4KiB::SIZE;
+    unsafe { new_page.as_mut_ptr::<u8>().write_volatile(1) };
+    assert_eq!(unsafe { new_page.as_ptr::<u8>().read_volatile() }, 1);
+}
+
+fn test_map_shared_memory() {
+    let heap_start = syscall_allocate_pages(INITIAL_HEAP_PAGES + 1).unwrap();
+    let heap_end = heap_start + MAX_HEAP_PAGES * Size4KiB::SIZE;
+    let shared_memory = syscall_create_shared_memory(1).unwrap();
+
+    // The kernel maps pages in these later, so shared memory can't be put there
+    for address in [
+        heap_start,
+        heap_end - Size4KiB::SIZE,
+        VirtAddr::new_truncate(USER_SPACE_MMIO_START),
+    ] {
+        assert_eq!(
+            unsafe { syscall_map_shared_memory(shared_memory, address) },
+            Err(SharedMemoryError::Reserved)
+        );
+    }
+    assert_eq!(
+        unsafe { syscall_map_shared_memory(shared_memory, heap_end + 1) },
+        Err(SharedMemoryError::PointerNotAligned)
+    );
+
+    unsafe { syscall_map_shared_memory(shared_memory, heap_end) }.unwrap();
+    assert_eq!(
+        unsafe { syscall_map_shared_memory(shared_memory, heap_end) },
+        Err(SharedMemoryError::AlreadyMapped)
+    );
+    let value = heap_end.as_mut_ptr::<u64>();
+    assert_eq!(unsafe { value.read_volatile() }, 0);
+    unsafe { value.write_volatile(u64::MAX) };
+    assert_eq!(unsafe { value.read_volatile() }, u64::MAX);
+
+    // Shared memory doesn't get in the way of the heap
+    syscall_allocate_pages(INITIAL_HEAP_PAGES + 2).unwrap();
+}
+
+fn test_take_frame_buffer() {
+    // The frame buffer can't always be given to user space, but if it can, it can only be taken once
+    if syscall_take_frame_buffer(initial_handles::FRAME_BUFFER).is_ok() {
+        assert_eq!(
+            syscall_take_frame_buffer(initial_handles::FRAME_BUFFER).err(),
+            Some(TakeFrameBufferError::AlreadyMapped)
+        );
+    }
+}
+
+fn test_signals() {
+    syscall_set_signal_handler(Signal::User1, Some(user1_handler));
+    syscall_send_signal(initial_handles::PROCESS, Signal::User1).unwrap();
+    // This is the only process, so the handler runs before the syscall returns
+    assert!(SIGNAL_HANDLED.load(Ordering::Relaxed));
+    syscall_set_signal_handler(Signal::User1, None);
+}
+
+unsafe extern "sysv64" fn user1_handler(number: u64) -> ! {
+    assert_eq!(Signal::from_number(number), Some(Signal::User1));
+    SIGNAL_HANDLED.store(true, Ordering::Relaxed);
+    syscall_signal_return()
+}
@@ -6,12 +6,14 @@ use crate::syscall::syscall_allocate_pages;
 #[global_allocator]
 static ALLOCATOR: LockedHeap = LockedHeap::empty();
 
-/// This function should only be called once
-pub fn init() {
+/// The number of pages that the heap starts with
+pub const INITIAL_HEAP_PAGES: u64 = 100;
+
+/// This function should only be called once, which [`start`](crate::start) does
+pub(crate) fn init() {
     // TODO: Allocate more pages if no pages
-    let total_pages = 100;
-    let start = syscall_allocate_pages(total_pages);
-    let heap_size = Size4KiB::SIZE * total_pages;
+    let start = syscall_allocate_pages(INITIAL_HEAP_PAGES).unwrap();
+    let heap_size = Size4KiB::SIZE * INITIAL_HEAP_PAGES;
     unsafe {
         ALLOCATOR
             .lock()
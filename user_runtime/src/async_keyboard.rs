@@ -0,0 +1,154 @@
+use core::{mem::MaybeUninit, task::Poll};
+
+use common::{
+    event::EventSource,
+    handle::{Handle, HandleError},
+    key_event::{KeyEvent, KeyboardLayout},
+    syscall_start_recording_keyboard::{
+        FullQueueBehavior, KeyboardMode, SyscallStartRecordingKeyboardInput,
+    },
+};
+use futures::{task::AtomicWaker, Stream};
+use postcard::experimental::max_size::MaxSize;
+
+use crate::{
+    event_handler::EventHandlerGuard,
+    syscall::{
+        syscall_done_with_interrupt_handler, syscall_poll_key_events, syscall_poll_keyboard,
+        syscall_start_recording_keyboard, syscall_stop_recording_keyboard,
+    },
+};
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+/// [`AsyncKeyEvents`] polls key events in chunks of this many
+const KEY_EVENTS_CHUNK_SIZE: usize = 16;
+
+pub struct AsyncKeyboard<const T: usize> {
+    keyboard: Handle,
+    _event_handler: EventHandlerGuard,
+}
+
+impl<const N: usize> AsyncKeyboard<N> {
+    const QUEUE_SIZE: usize = N;
+    pub fn new(
+        keyboard: Handle,
+        full_queue_behavior: FullQueueBehavior,
+    ) -> Result<Self, HandleError> {
+        syscall_start_recording_keyboard(SyscallStartRecordingKeyboardInput {
+            keyboard,
+            queue_size: Self::QUEUE_SIZE as u64,
+            behavior_on_full_queue: full_queue_behavior,
+            mode: KeyboardMode::ScanCodes,
+        })?;
+        Ok(Self {
+            keyboard,
+            _event_handler: EventHandlerGuard::new(
+                EventSource::Keyboard,
+                keyboard_interrupt_handler,
+            ),
+        })
+    }
+}
+
+impl<const N: usize> Drop for AsyncKeyboard<N> {
+    fn drop(&mut self) {
+        syscall_stop_recording_keyboard(self.keyboard).unwrap();
+    }
+}
+
+impl<const N: usize> Stream for AsyncKeyboard<N> {
+    type Item = heapless::Vec<u8, N>;
+
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        WAKER.register(cx.waker());
+        let mut buffer = [MaybeUninit::uninit(); N];
+        let scan_codes = syscall_poll_keyboard(self.keyboard, &mut buffer);
+        if !scan_codes.is_empty() {
+            Poll::Ready(Some(heapless::Vec::from_slice(scan_codes).unwrap()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Like [`AsyncKeyboard`], but the kernel decodes the scan codes
+pub struct AsyncKeyEvents<const N: usize> {
+    keyboard: Handle,
+    _event_handler: EventHandlerGuard,
+}
+
+impl<const N: usize> AsyncKeyEvents<N> {
+    const QUEUE_SIZE: usize = N;
+
+    pub fn new(
+        keyboard: Handle,
+        full_queue_behavior: FullQueueBehavior,
+        layout: KeyboardLayout,
+    ) -> Result<Self, HandleError> {
+        syscall_start_recording_keyboard(SyscallStartRecordingKeyboardInput {
+            keyboard,
+            queue_size: Self::QUEUE_SIZE as u64,
+            behavior_on_full_queue: full_queue_behavior,
+            mode: KeyboardMode::KeyEvents(layout),
+        })?;
+        Ok(Self {
+            keyboard,
+            _event_handler: EventHandlerGuard::new(
+                EventSource::Keyboard,
+                keyboard_interrupt_handler,
+            ),
+        })
+    }
+}
+
+impl<const N: usize> Drop for AsyncKeyEvents<N> {
+    fn drop(&mut self) {
+        syscall_stop_recording_keyboard(self.keyboard).unwrap();
+    }
+}
+
+impl<const N: usize> Stream for AsyncKeyEvents<N> {
+    type Item = heapless::Vec<KeyEvent, N>;
+
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        WAKER.register(cx.waker());
+        let mut key_events = heapless::Vec::new();
+        let mut buffer =
+            [MaybeUninit::uninit(); KEY_EVENTS_CHUNK_SIZE * KeyEvent::POSTCARD_MAX_SIZE];
+        loop {
+            // Only ask for as many key events as there is room for
+            let chunk_size = (N - key_events.len()).min(KEY_EVENTS_CHUNK_SIZE);
+            let bytes = syscall_poll_key_events(
+                self.keyboard,
+                &mut buffer[..chunk_size * KeyEvent::POSTCARD_MAX_SIZE],
+            );
+            if bytes.is_empty() {
+                break;
+            }
+            key_events.extend(KeyEvent::decode_all(bytes));
+            if key_events.is_full() {
+                break;
+            }
+        }
+        if !key_events.is_empty() {
+            Poll::Ready(Some(key_events))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+unsafe extern "sysv64" fn keyboard_interrupt_handler() -> ! {
+    // We cannot allocate during the interrupt handler because that would cause a lock forever.
+    // Same reason why we can't allocate in kernel interrupt handlers
+    // for _ in 0..1_000_000 {}
+    // syscall_print("Keyboard interrupt handler").unwrap();
+    WAKER.wake();
+    syscall_done_with_interrupt_handler();
+}
@@ -0,0 +1,69 @@
+use core::{mem::MaybeUninit, task::Poll};
+
+use common::{
+    event::EventSource,
+    handle::{Handle, HandleError},
+    mouse::MouseEvent,
+    syscall_start_recording_keyboard::FullQueueBehavior,
+    syscall_start_recording_mouse::SyscallStartRecordingMouseInput,
+};
+use futures::{task::AtomicWaker, Stream};
+
+use crate::{
+    event_handler::EventHandlerGuard,
+    syscall::{
+        syscall_done_with_interrupt_handler, syscall_poll_mouse, syscall_start_recording_mouse,
+        syscall_stop_recording_mouse,
+    },
+};
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+pub struct AsyncMouse<const N: usize> {
+    mouse: Handle,
+    _event_handler: EventHandlerGuard,
+}
+
+impl<const N: usize> AsyncMouse<N> {
+    const QUEUE_SIZE: usize = N;
+    pub fn new(mouse: Handle, full_queue_behavior: FullQueueBehavior) -> Result<Self, HandleError> {
+        syscall_start_recording_mouse(SyscallStartRecordingMouseInput {
+            mouse,
+            queue_size: Self::QUEUE_SIZE as u64,
+            behavior_on_full_queue: full_queue_behavior,
+        })?;
+        Ok(Self {
+            mouse,
+            _event_handler: EventHandlerGuard::new(EventSource::Mouse, mouse_interrupt_handler),
+        })
+    }
+}
+
+impl<const N: usize> Drop for AsyncMouse<N> {
+    fn drop(&mut self) {
+        syscall_stop_recording_mouse(self.mouse).unwrap();
+    }
+}
+
+impl<const N: usize> Stream for AsyncMouse<N> {
+    type Item = heapless::Vec<MouseEvent, N>;
+
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        WAKER.register(cx.waker());
+        let mut buffer = [MaybeUninit::uninit(); N];
+        let events = syscall_poll_mouse(self.mouse, &mut buffer);
+        if !events.is_empty() {
+            Poll::Ready(Some(heapless::Vec::from_slice(events).unwrap()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+unsafe extern "sysv64" fn mouse_interrupt_handler() -> ! {
+    WAKER.wake();
+    syscall_done_with_interrupt_handler();
+}
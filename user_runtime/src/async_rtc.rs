@@ -0,0 +1,61 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use common::{event::EventSource, handle::Handle, syscall_real_time::RtcTicksError};
+use futures::{task::AtomicWaker, Stream};
+
+use crate::{
+    event_handler::EventHandlerGuard,
+    syscall::{
+        syscall_done_with_interrupt_handler, syscall_poll_rtc_ticks, syscall_start_rtc_ticks,
+        syscall_stop_rtc_ticks,
+    },
+};
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// A stream of the number of RTC ticks since the last item
+pub struct AsyncRtc {
+    clock: Handle,
+    _event_handler: EventHandlerGuard,
+}
+
+impl AsyncRtc {
+    /// Ticks at `32768 >> (divider_value - 1)` Hz. `divider_value` must be between 3 (8192 Hz) and 15 (2 Hz).
+    pub fn new(clock: Handle, divider_value: u8) -> Result<Self, RtcTicksError> {
+        syscall_start_rtc_ticks(clock, divider_value)?;
+        Ok(Self {
+            clock,
+            _event_handler: EventHandlerGuard::new(EventSource::Rtc, rtc_interrupt_handler),
+        })
+    }
+
+    pub fn set_divider_value(&mut self, divider_value: u8) -> Result<(), RtcTicksError> {
+        syscall_start_rtc_ticks(self.clock, divider_value)
+    }
+}
+
+impl Drop for AsyncRtc {
+    fn drop(&mut self) {
+        syscall_stop_rtc_ticks(self.clock).unwrap();
+    }
+}
+
+impl Stream for AsyncRtc {
+    type Item = u32;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        WAKER.register(cx.waker());
+        match syscall_poll_rtc_ticks(self.clock).unwrap() {
+            0 => Poll::Pending,
+            ticks => Poll::Ready(Some(ticks)),
+        }
+    }
+}
+
+unsafe extern "sysv64" fn rtc_interrupt_handler() -> ! {
+    WAKER.wake();
+    syscall_done_with_interrupt_handler();
+}
@@ -0,0 +1,50 @@
+use core::{mem::MaybeUninit, task::Poll};
+
+use common::{event::EventSource, handle::Handle};
+use futures::{task::AtomicWaker, Stream};
+
+use crate::{
+    event_handler::EventHandlerGuard,
+    syscall::{syscall_done_with_interrupt_handler, syscall_read_serial},
+};
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// A stream of chunks of bytes received on the serial port.
+/// The kernel always buffers serial input, so this includes bytes received before it was created.
+pub struct AsyncSerial<const N: usize> {
+    serial: Handle,
+    _event_handler: EventHandlerGuard,
+}
+
+impl<const N: usize> AsyncSerial<N> {
+    pub fn new(serial: Handle) -> Self {
+        Self {
+            serial,
+            _event_handler: EventHandlerGuard::new(EventSource::Serial, serial_interrupt_handler),
+        }
+    }
+}
+
+impl<const N: usize> Stream for AsyncSerial<N> {
+    type Item = heapless::Vec<u8, N>;
+
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        WAKER.register(cx.waker());
+        let mut buffer = [MaybeUninit::uninit(); N];
+        let bytes = syscall_read_serial(self.serial, &mut buffer);
+        if !bytes.is_empty() {
+            Poll::Ready(Some(heapless::Vec::from_slice(bytes).unwrap()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+unsafe extern "sysv64" fn serial_interrupt_handler() -> ! {
+    WAKER.wake();
+    syscall_done_with_interrupt_handler();
+}
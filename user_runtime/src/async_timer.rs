@@ -0,0 +1,86 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use common::{
+    event::EventSource,
+    syscall_timer::{TimerError, TimerId, MAX_TIMERS},
+};
+use futures::{task::AtomicWaker, Stream, StreamExt};
+
+use crate::{
+    event_handler::EventHandlerGuard,
+    syscall::{
+        syscall_cancel_timer, syscall_create_timer, syscall_done_with_interrupt_handler,
+        syscall_poll_timer,
+    },
+};
+
+static WAKERS: [AtomicWaker; MAX_TIMERS] = [const { AtomicWaker::new() }; MAX_TIMERS];
+
+/// A stream of the number of times the timer expired since the last item.
+/// A one-shot timer ends after it expires once.
+pub struct AsyncTimer {
+    timer: TimerId,
+    periodic: bool,
+    done: bool,
+    /// All timers share the handler, which is removed when the last timer is dropped
+    _event_handler: EventHandlerGuard,
+}
+
+impl AsyncTimer {
+    pub fn new(duration: Duration, period: Option<Duration>) -> Result<Self, TimerError> {
+        let timer = syscall_create_timer(duration, period)?;
+        Ok(Self {
+            timer,
+            periodic: period.is_some(),
+            done: false,
+            _event_handler: EventHandlerGuard::new(EventSource::Timer, timer_interrupt_handler),
+        })
+    }
+
+    /// Expires every `period`, starting after the first `period`
+    pub fn interval(period: Duration) -> Result<Self, TimerError> {
+        Self::new(period, Some(period))
+    }
+}
+
+impl Drop for AsyncTimer {
+    fn drop(&mut self) {
+        syscall_cancel_timer(self.timer).unwrap();
+    }
+}
+
+impl Stream for AsyncTimer {
+    type Item = u32;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        WAKERS[self.timer.index()].register(cx.waker());
+        match syscall_poll_timer(self.timer).unwrap() {
+            0 => Poll::Pending,
+            expirations => {
+                self.done = !self.periodic;
+                Poll::Ready(Some(expirations))
+            }
+        }
+    }
+}
+
+/// Waits without blocking other futures
+pub async fn sleep(duration: Duration) {
+    let mut timer = AsyncTimer::new(duration, None).unwrap();
+    timer.next().await;
+}
+
+unsafe extern "sysv64" fn timer_interrupt_handler() -> ! {
+    // The kernel doesn't say which timer expired, so poll all of them
+    for waker in &WAKERS {
+        waker.wake();
+    }
+    syscall_done_with_interrupt_handler();
+}
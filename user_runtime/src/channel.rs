@@ -0,0 +1,107 @@
+use core::marker::PhantomData;
+
+use alloc::vec::Vec;
+use common::{
+    channel_message::ChannelMessage,
+    handle::Handle,
+    syscall_channel::{ChannelError, MessageKind, TransactionId},
+};
+
+use crate::syscall::{
+    syscall_channel_call, syscall_channel_receive, syscall_channel_reply, syscall_channel_send,
+    syscall_close_handle, syscall_create_channel,
+};
+
+#[derive(Debug)]
+pub enum TypedChannelError {
+    Channel(ChannelError),
+    /// The message couldn't be encoded, or the received message wasn't a valid `R`
+    Postcard(postcard::Error),
+}
+
+impl From<ChannelError> for TypedChannelError {
+    fn from(value: ChannelError) -> Self {
+        Self::Channel(value)
+    }
+}
+
+impl From<postcard::Error> for TypedChannelError {
+    fn from(value: postcard::Error) -> Self {
+        Self::Postcard(value)
+    }
+}
+
+/// An endpoint that sends `S`s and receives `R`s. The handle to the endpoint is closed when this is dropped.
+#[derive(Debug)]
+pub struct TypedEndpoint<S, R> {
+    endpoint: Handle,
+    phantom: PhantomData<(S, R)>,
+}
+
+/// Both endpoints of a channel where one side sends `A`s and the other side sends `B`s
+pub type TypedChannel<A, B> = (TypedEndpoint<A, B>, TypedEndpoint<B, A>);
+
+pub fn typed_channel<A: ChannelMessage, B: ChannelMessage>(
+) -> Result<TypedChannel<A, B>, ChannelError> {
+    let [a, b] = syscall_create_channel()?;
+    Ok((TypedEndpoint::new(a), TypedEndpoint::new(b)))
+}
+
+impl<S: ChannelMessage, R: ChannelMessage> TypedEndpoint<S, R> {
+    /// The other endpoint must be sending `R`s and receiving `S`s
+    pub fn new(endpoint: Handle) -> Self {
+        Self {
+            endpoint,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn endpoint(&self) -> Handle {
+        self.endpoint
+    }
+
+    fn encode(
+        message: &S,
+        f: impl FnOnce(&[u8]) -> Result<(), TypedChannelError>,
+    ) -> Result<(), TypedChannelError> {
+        let mut buffer = alloc::vec![0; S::POSTCARD_MAX_SIZE];
+        f(message.to_message(&mut buffer)?)
+    }
+
+    pub fn send(&self, message: &S) -> Result<(), TypedChannelError> {
+        Self::encode(message, |message| {
+            Ok(syscall_channel_send(self.endpoint, message)?)
+        })
+    }
+
+    /// The reply will be received as a `MessageKind::Reply` with the returned transaction id
+    pub fn call(&self, request: &S) -> Result<TransactionId, TypedChannelError> {
+        let mut transaction = None;
+        Self::encode(request, |request| {
+            transaction = Some(syscall_channel_call(self.endpoint, request)?);
+            Ok(())
+        })?;
+        Ok(transaction.unwrap())
+    }
+
+    pub fn reply(&self, transaction: TransactionId, reply: &S) -> Result<(), TypedChannelError> {
+        Self::encode(reply, |reply| {
+            Ok(syscall_channel_reply(self.endpoint, transaction, reply)?)
+        })
+    }
+
+    /// Does not block. Returns `ChannelError::Empty` if there are no messages.
+    pub fn try_receive(&self) -> Result<(MessageKind, R), TypedChannelError> {
+        let mut buffer = Vec::<u8>::with_capacity(R::POSTCARD_MAX_SIZE);
+        let (received_message, message) =
+            syscall_channel_receive(self.endpoint, buffer.spare_capacity_mut())?;
+        Ok((received_message.kind, R::from_message(message)?))
+    }
+}
+
+impl<S, R> Drop for TypedEndpoint<S, R> {
+    fn drop(&mut self) {
+        // The handle can only be invalid if someone else closed it
+        let _ = syscall_close_handle(self.endpoint);
+    }
+}
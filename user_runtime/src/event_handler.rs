@@ -0,0 +1,37 @@
+//! The async sources set their event handler with an [`EventHandlerGuard`], so that the handler is
+//! removed the same way for all of them once nothing needs it anymore.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use common::event::EventSource;
+
+use crate::syscall::{syscall_set_event_handler, EventHandler};
+
+/// The number of guards of each event source
+static GUARDS: [AtomicUsize; EventSource::COUNT] =
+    [const { AtomicUsize::new(0) }; EventSource::COUNT];
+
+/// Sets the event handler of a source while there is a guard for it. Sources can have more than
+/// one guard at a time, like one for each timer, and the handler is removed when the last one is dropped.
+#[derive(Debug)]
+pub struct EventHandlerGuard {
+    source: EventSource,
+}
+
+impl EventHandlerGuard {
+    /// All guards of a source must use the same handler
+    pub fn new(source: EventSource, handler: EventHandler) -> Self {
+        if GUARDS[source.index()].fetch_add(1, Ordering::Relaxed) == 0 {
+            syscall_set_event_handler(source, Some(handler));
+        }
+        Self { source }
+    }
+}
+
+impl Drop for EventHandlerGuard {
+    fn drop(&mut self) {
+        if GUARDS[self.source.index()].fetch_sub(1, Ordering::Relaxed) == 1 {
+            syscall_set_event_handler(self.source, None);
+        }
+    }
+}
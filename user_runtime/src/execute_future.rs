@@ -0,0 +1,8 @@
+use core::future::Future;
+
+use crate::executor::Executor;
+
+/// Execute a single future. Use an [`Executor`] to spawn more tasks.
+pub fn execute_future<T>(future: impl Future<Output = T>) -> T {
+    Executor::new().block_on(future)
+}
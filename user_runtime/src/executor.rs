@@ -0,0 +1,31 @@
+//! Runs many tasks at once, such as input handling and animation, with the executor from `common`.
+//! Tasks are usually woken by event handlers, so the executor waits for events when no task is woken.
+
+pub use common::executor::{yield_now, JoinHandle, Priority, Spawner, Wait};
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::syscall::{
+    syscall_disable_and_defer_my_interrupts, syscall_enable_and_catch_up_on_my_interrupts,
+    syscall_enable_my_interrupts_and_wait_until_one_happens,
+};
+
+pub type Executor = common::executor::Executor<WaitForEvent>;
+
+/// Waits until one of this process's event handlers runs
+#[derive(Debug, Default)]
+pub struct WaitForEvent;
+
+impl Wait for WaitForEvent {
+    fn wait(&mut self, woken: &AtomicBool) {
+        // Disable interrupts here so that an interrupt doesn't happen in between checking if we woke up and getting woken up
+        syscall_disable_and_defer_my_interrupts();
+        if !woken.load(Ordering::Relaxed) {
+            // Wait for an interrupt to happen
+            syscall_enable_my_interrupts_and_wait_until_one_happens();
+        } else {
+            // We got woken up. Don't forget to enable interrupts again.
+            syscall_enable_and_catch_up_on_my_interrupts();
+        }
+    }
+}
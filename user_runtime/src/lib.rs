@@ -0,0 +1,46 @@
+//! What user programs need to run on the kernel: syscalls, an allocator, an async executor,
+//! async streams of events and a panic handler. A program is a binary crate with an [`entry`]:
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! use user_runtime::{entry, syscall::syscall_print};
+//!
+//! #[entry]
+//! fn main() {
+//!     syscall_print("Hello").unwrap();
+//! }
+//! ```
+#![no_std]
+#![deny(unsafe_op_in_unsafe_fn)]
+#![feature(maybe_uninit_slice)]
+extern crate alloc;
+
+pub mod allocator;
+pub mod async_keyboard;
+pub mod async_mouse;
+pub mod async_rtc;
+pub mod async_serial;
+pub mod async_timer;
+pub mod channel;
+pub mod embedded_graphics_frame_buffer;
+pub mod event_handler;
+pub mod execute_future;
+pub mod executor;
+pub mod panic_handler;
+pub mod syscall;
+pub mod time;
+
+/// Makes a function the entry point of the program. The function can be `async`, in which case it
+/// is run by an [`Executor`](executor::Executor), and it can take a [`Spawner`](executor::Spawner)
+/// to run more tasks. The heap can be used in it, and the program exits when it returns.
+pub use user_runtime_macros::entry;
+
+/// Called by the `_start` that [`entry`] makes
+#[doc(hidden)]
+pub fn start(main: impl FnOnce()) -> ! {
+    allocator::init();
+    main();
+    syscall::syscall_exit()
+}
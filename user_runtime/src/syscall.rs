@@ -0,0 +1,482 @@
+use core::{arch::asm, mem::MaybeUninit, time::Duration};
+
+use common::{
+    event::EventSource,
+    handle::{Handle, HandleError, Rights},
+    mouse::MouseEvent,
+    signal::Signal,
+    syscall::Syscall,
+    syscall_allocate_pages::{
+        AllocatePagesError, SyscallAllocatePagesInput, SyscallAllocatePagesOutput,
+    },
+    syscall_channel::{
+        ChannelError, CreateChannelOutputData, ReceivedMessage, SyscallChannelCallOutput,
+        SyscallChannelOutput, SyscallChannelReceiveHandleOutput, SyscallChannelReceiveInput,
+        SyscallChannelReplyInput, SyscallChannelSendInput, TransactionId,
+    },
+    syscall_configure_keyboard::{
+        ConfigureKeyboardError, KeyboardCommand, KeyboardLocks, SyscallConfigureKeyboardInput,
+        SyscallConfigureKeyboardOutput, SyscallKeyboardLocksOutput,
+    },
+    syscall_handle::{
+        SyscallDuplicateHandleInput, SyscallHandleOutput, SyscallNewHandleOutput,
+        SyscallTransferHandleInput,
+    },
+    syscall_output::SyscallOutput,
+    syscall_print::{SyscallPrintError, SyscallPrintOutput},
+    syscall_real_time::{
+        RtcTicksError, SyscallPollRtcTicksOutput, SyscallRtcTicksOutput, SyscallSetRealTimeInput,
+        SyscallSetRealTimeOutput, SyscallStartRtcTicksInput,
+    },
+    syscall_set_event_handler::SyscallSetEventHandlerInput,
+    syscall_shared_memory::{
+        SharedMemoryError, SyscallCreateSharedMemoryOutput, SyscallMapSharedMemoryInput,
+        SyscallMapSharedMemoryOutput,
+    },
+    syscall_signal::{
+        SyscallSendSignalInput, SyscallSendSignalOutput, SyscallSetSignalHandlerInput,
+    },
+    syscall_start_recording_keyboard::SyscallStartRecordingKeyboardInput,
+    syscall_start_recording_mouse::SyscallStartRecordingMouseInput,
+    syscall_take_frame_buffer::{
+        SyscallTakeFrameBufferInput, TakeFrameBufferError, TakeFrameBufferOutput,
+        TakeFrameBufferOutputData,
+    },
+    syscall_timer::{
+        SyscallCreateTimerInput, SyscallCreateTimerOutput, SyscallPollTimerOutput,
+        SyscallTimerOutput, TimerError, TimerId,
+    },
+};
+use x86_64::VirtAddr;
+
+/// # Safety
+/// The inputs must be valid. Invalid inputs can lead to undefined behavior or the program being terminated.
+unsafe fn syscall_internal(
+    input0: u64,
+    input1: u64,
+    input2: u64,
+    input3: u64,
+    input4: u64,
+    input5: u64,
+    input6: u64,
+) -> u64 {
+    let return_value: u64;
+    unsafe {
+        asm!("\
+            mov rdi, {0}
+            mov rsi, {1}
+            mov rdx, {2}
+            mov r10, {3}
+            mov r8,  {4}
+            mov r9,  {5}
+            mov rax, {6}
+            syscall
+            ",
+            in(reg) input0,
+            in(reg) input1,
+            in(reg) input2,
+            in(reg) input3,
+            in(reg) input4,
+            in(reg) input5,
+            in(reg) input6,
+            lateout("rax") return_value
+        );
+    }
+    return_value
+}
+
+fn syscall(syscall: &Syscall) -> u64 {
+    let [input0, input1, input2, input3, input4, input5, input6] =
+        syscall.serialize_to_input().unwrap();
+    // We know the inputs are valid
+    unsafe { syscall_internal(input0, input1, input2, input3, input4, input5, input6) }
+}
+
+pub fn syscall_take_frame_buffer(
+    frame_buffer: Handle,
+) -> Result<TakeFrameBufferOutputData, TakeFrameBufferError> {
+    let mut output = MaybeUninit::<TakeFrameBufferOutputData>::uninit();
+    TakeFrameBufferOutput::from_syscall_output(syscall(&Syscall::TakeFrameBuffer(
+        SyscallTakeFrameBufferInput {
+            frame_buffer,
+            output: output.as_mut_ptr().into(),
+        },
+    )))
+    .unwrap()
+    .0?;
+    // Because the kernel returned `Ok` we can trust the kernel to have initialized the pointer
+    let dest = unsafe { output.assume_init() };
+    Ok(dest)
+}
+
+pub fn syscall_print(string: &str) -> Result<(), SyscallPrintError> {
+    SyscallPrintOutput::from_syscall_output(syscall(&Syscall::Print(string.as_bytes().into())))
+        .unwrap()
+        .0
+}
+
+pub fn syscall_exit() -> ! {
+    syscall(&Syscall::Exit);
+    unreachable!()
+}
+
+pub fn syscall_start_recording_keyboard(
+    input: SyscallStartRecordingKeyboardInput,
+) -> Result<(), HandleError> {
+    SyscallHandleOutput::from_syscall_output(syscall(&Syscall::StartRecordingKeyboard(input)))
+        .unwrap()
+        .0
+}
+
+pub fn syscall_poll_keyboard(keyboard: Handle, buffer: &mut [MaybeUninit<u8>]) -> &mut [u8] {
+    let count = syscall(&Syscall::PollKeyboard(keyboard, buffer.into())) as usize;
+    unsafe { buffer[..count].assume_init_mut() }
+}
+
+/// Returns postcard encoded key events, which can be decoded with [`KeyEvent::decode_all`](common::key_event::KeyEvent::decode_all)
+pub fn syscall_poll_key_events(keyboard: Handle, buffer: &mut [MaybeUninit<u8>]) -> &mut [u8] {
+    let len = syscall(&Syscall::PollKeyEvents(keyboard, buffer.into())) as usize;
+    unsafe { buffer[..len].assume_init_mut() }
+}
+
+/// Returns the start of the heap
+pub fn syscall_allocate_pages(total_pages: u64) -> Result<VirtAddr, AllocatePagesError> {
+    let mut heap_start = MaybeUninit::<u64>::uninit();
+    SyscallAllocatePagesOutput::from_syscall_output(syscall(&Syscall::AllocatePages(
+        SyscallAllocatePagesInput {
+            total_pages,
+            heap_start: heap_start.as_mut_ptr().into(),
+        },
+    )))
+    .unwrap()
+    .0?;
+    // Because the kernel returned `Ok` we can trust the kernel to have initialized the pointer
+    Ok(VirtAddr::new(unsafe { heap_start.assume_init() }))
+}
+
+/// Set your handler to `unsafe` to avoid accidentally calling it in your code.
+/// Call [`syscall_done_with_interrupt_handler`](syscall_done_with_interrupt_handler) at the end of your handler.
+pub type EventHandler = unsafe extern "sysv64" fn() -> !;
+
+pub fn syscall_set_event_handler(source: EventSource, handler: Option<EventHandler>) {
+    syscall(&Syscall::SetEventHandler(SyscallSetEventHandlerInput {
+        source,
+        handler: handler.map(|handler| (handler as *const ()).into()),
+    }));
+}
+
+pub fn syscall_done_with_interrupt_handler() -> ! {
+    syscall(&Syscall::DoneWithInterruptHandler);
+    unreachable!()
+}
+
+pub fn syscall_disable_and_defer_my_interrupts() {
+    syscall(&Syscall::DisableAndDeferMyInterrupts);
+}
+
+pub fn syscall_enable_and_catch_up_on_my_interrupts() {
+    syscall(&Syscall::EnableAndCatchUpOnMyInterrupts);
+}
+
+pub fn syscall_enable_my_interrupts_and_wait_until_one_happens() {
+    syscall(&Syscall::EnableMyInterruptsAndWaitUntilOneHappens);
+}
+
+pub fn syscall_create_channel() -> Result<[Handle; 2], ChannelError> {
+    let mut output = MaybeUninit::<CreateChannelOutputData>::uninit();
+    SyscallChannelOutput::from_syscall_output(syscall(&Syscall::CreateChannel(
+        output.as_mut_ptr().into(),
+    )))
+    .unwrap()
+    .0?;
+    // Because the kernel returned `Ok` we can trust the kernel to have initialized the pointer
+    let output = unsafe { output.assume_init() };
+    Ok(output.endpoints)
+}
+
+pub fn syscall_channel_send(endpoint: Handle, message: &[u8]) -> Result<(), ChannelError> {
+    SyscallChannelOutput::from_syscall_output(syscall(&Syscall::ChannelSend(
+        SyscallChannelSendInput {
+            endpoint,
+            message: message.into(),
+        },
+    )))
+    .unwrap()
+    .0
+}
+
+pub fn syscall_channel_call(
+    endpoint: Handle,
+    message: &[u8],
+) -> Result<TransactionId, ChannelError> {
+    SyscallChannelCallOutput::from_syscall_output(syscall(&Syscall::ChannelCall(
+        SyscallChannelSendInput {
+            endpoint,
+            message: message.into(),
+        },
+    )))
+    .unwrap()
+    .0
+}
+
+pub fn syscall_channel_reply(
+    endpoint: Handle,
+    transaction: TransactionId,
+    message: &[u8],
+) -> Result<(), ChannelError> {
+    SyscallChannelOutput::from_syscall_output(syscall(&Syscall::ChannelReply(
+        SyscallChannelReplyInput {
+            endpoint,
+            transaction,
+            message: message.into(),
+        },
+    )))
+    .unwrap()
+    .0
+}
+
+/// Returns the received message info and the part of the buffer that the message was written to
+pub fn syscall_channel_receive(
+    endpoint: Handle,
+    buffer: &mut [MaybeUninit<u8>],
+) -> Result<(ReceivedMessage, &mut [u8]), ChannelError> {
+    let mut output = MaybeUninit::<ReceivedMessage>::uninit();
+    SyscallChannelOutput::from_syscall_output(syscall(&Syscall::ChannelReceive(
+        SyscallChannelReceiveInput {
+            endpoint,
+            buffer: (&mut *buffer).into(),
+            output: output.as_mut_ptr().into(),
+        },
+    )))
+    .unwrap()
+    .0?;
+    let received_message = unsafe { output.assume_init() };
+    let message = unsafe { buffer[..received_message.len as usize].assume_init_mut() };
+    Ok((received_message, message))
+}
+
+pub fn syscall_duplicate_handle(handle: Handle, rights: Rights) -> Result<Handle, HandleError> {
+    SyscallNewHandleOutput::from_syscall_output(syscall(&Syscall::DuplicateHandle(
+        SyscallDuplicateHandleInput { handle, rights },
+    )))
+    .unwrap()
+    .0
+}
+
+pub fn syscall_close_handle(handle: Handle) -> Result<(), HandleError> {
+    SyscallHandleOutput::from_syscall_output(syscall(&Syscall::CloseHandle(handle)))
+        .unwrap()
+        .0
+}
+
+/// On success, `handle` is no longer valid in this process
+pub fn syscall_transfer_handle(channel: Handle, handle: Handle) -> Result<(), ChannelError> {
+    SyscallChannelOutput::from_syscall_output(syscall(&Syscall::TransferHandle(
+        SyscallTransferHandleInput { channel, handle },
+    )))
+    .unwrap()
+    .0
+}
+
+pub fn syscall_receive_handle(channel: Handle) -> Result<Handle, ChannelError> {
+    SyscallChannelReceiveHandleOutput::from_syscall_output(syscall(&Syscall::ReceiveHandle(
+        channel,
+    )))
+    .unwrap()
+    .0
+}
+
+pub fn syscall_create_shared_memory(pages: u64) -> Result<Handle, SharedMemoryError> {
+    SyscallCreateSharedMemoryOutput::from_syscall_output(syscall(&Syscall::CreateSharedMemory(
+        pages,
+    )))
+    .unwrap()
+    .0
+}
+
+/// # Safety
+/// Nothing else can be mapped in the address range. The memory can be changed by other processes at any time.
+pub unsafe fn syscall_map_shared_memory(
+    shared_memory: Handle,
+    address: VirtAddr,
+) -> Result<(), SharedMemoryError> {
+    SyscallMapSharedMemoryOutput::from_syscall_output(syscall(&Syscall::MapSharedMemory(
+        SyscallMapSharedMemoryInput {
+            shared_memory,
+            address: address.as_ptr::<u8>().into(),
+        },
+    )))
+    .unwrap()
+    .0
+}
+
+/// Set your handler to `unsafe` to avoid accidentally calling it in your code.
+/// The input is the signal's number, which can be converted with [`Signal::from_number`].
+/// Call [`syscall_signal_return`](syscall_signal_return) at the end of your handler.
+pub type SignalHandler = unsafe extern "sysv64" fn(u64) -> !;
+
+/// Without a handler, the signal terminates the process
+pub fn syscall_set_signal_handler(signal: Signal, handler: Option<SignalHandler>) {
+    syscall(&Syscall::SetSignalHandler(SyscallSetSignalHandlerInput {
+        signal,
+        handler: handler.map(|handler| (handler as *const ()).into()),
+    }));
+}
+
+pub fn syscall_send_signal(process: Handle, signal: Signal) -> Result<(), HandleError> {
+    SyscallSendSignalOutput::from_syscall_output(syscall(&Syscall::SendSignal(
+        SyscallSendSignalInput { process, signal },
+    )))
+    .unwrap()
+    .0
+}
+
+pub fn syscall_signal_return() -> ! {
+    syscall(&Syscall::SignalReturn);
+    unreachable!()
+}
+
+fn duration_to_nanos(duration: Duration) -> u64 {
+    duration.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+/// Events that happen while sleeping are handled after the sleep
+pub fn syscall_sleep(duration: Duration) {
+    syscall(&Syscall::Sleep(duration_to_nanos(duration)));
+}
+
+/// Sends [`Signal::Alarm`] once after `duration`, replacing the previous alarm. [`None`] cancels the alarm.
+pub fn syscall_set_alarm(duration: Option<Duration>) {
+    syscall(&Syscall::SetAlarm(duration.map_or(0, |duration| {
+        // 0 would cancel the alarm
+        duration_to_nanos(duration).max(1)
+    })));
+}
+
+/// The time since the kernel started. It never goes backwards.
+pub fn syscall_get_monotonic_time() -> Duration {
+    Duration::from_nanos(syscall(&Syscall::GetMonotonicTime))
+}
+
+/// A timer expires after `duration`, and then every `period` if it is periodic.
+/// Every expiration is a [`EventSource::Timer`] event.
+pub fn syscall_create_timer(
+    duration: Duration,
+    period: Option<Duration>,
+) -> Result<TimerId, TimerError> {
+    SyscallCreateTimerOutput::from_syscall_output(syscall(&Syscall::CreateTimer(
+        SyscallCreateTimerInput {
+            duration: duration_to_nanos(duration),
+            period: period.map(duration_to_nanos),
+        },
+    )))
+    .unwrap()
+    .0
+}
+
+/// Returns the number of times the timer expired since the last poll
+pub fn syscall_poll_timer(timer: TimerId) -> Result<u32, TimerError> {
+    SyscallPollTimerOutput::from_syscall_output(syscall(&Syscall::PollTimer(timer)))
+        .unwrap()
+        .0
+}
+
+pub fn syscall_cancel_timer(timer: TimerId) -> Result<(), TimerError> {
+    SyscallTimerOutput::from_syscall_output(syscall(&Syscall::CancelTimer(timer)))
+        .unwrap()
+        .0
+}
+
+/// The time since the Unix epoch
+pub fn syscall_get_real_time() -> Duration {
+    Duration::from_nanos(syscall(&Syscall::GetRealTime))
+}
+
+/// `since_unix_epoch` is stored in the CMOS RTC with a precision of 1 second
+pub fn syscall_set_real_time(clock: Handle, since_unix_epoch: Duration) -> Result<(), HandleError> {
+    SyscallSetRealTimeOutput::from_syscall_output(syscall(&Syscall::SetRealTime(
+        SyscallSetRealTimeInput {
+            clock,
+            unix_nanos: duration_to_nanos(since_unix_epoch),
+        },
+    )))
+    .unwrap()
+    .0
+}
+
+/// Ticks at `32768 >> (divider_value - 1)` Hz. Every tick is a [`EventSource::Rtc`] event.
+pub fn syscall_start_rtc_ticks(clock: Handle, divider_value: u8) -> Result<(), RtcTicksError> {
+    SyscallRtcTicksOutput::from_syscall_output(syscall(&Syscall::StartRtcTicks(
+        SyscallStartRtcTicksInput {
+            clock,
+            divider_value,
+        },
+    )))
+    .unwrap()
+    .0
+}
+
+pub fn syscall_stop_rtc_ticks(clock: Handle) -> Result<(), RtcTicksError> {
+    SyscallRtcTicksOutput::from_syscall_output(syscall(&Syscall::StopRtcTicks(clock)))
+        .unwrap()
+        .0
+}
+
+/// Returns the number of ticks since the last poll
+pub fn syscall_poll_rtc_ticks(clock: Handle) -> Result<u32, HandleError> {
+    SyscallPollRtcTicksOutput::from_syscall_output(syscall(&Syscall::PollRtcTicks(clock)))
+        .unwrap()
+        .0
+}
+
+pub fn syscall_start_recording_mouse(
+    input: SyscallStartRecordingMouseInput,
+) -> Result<(), HandleError> {
+    SyscallHandleOutput::from_syscall_output(syscall(&Syscall::StartRecordingMouse(input)))
+        .unwrap()
+        .0
+}
+
+/// Also removes the [`EventSource::Keyboard`] handler
+pub fn syscall_stop_recording_keyboard(keyboard: Handle) -> Result<(), HandleError> {
+    SyscallHandleOutput::from_syscall_output(syscall(&Syscall::StopRecordingKeyboard(keyboard)))
+        .unwrap()
+        .0
+}
+
+pub fn syscall_configure_keyboard(
+    keyboard: Handle,
+    command: KeyboardCommand,
+) -> Result<(), ConfigureKeyboardError> {
+    SyscallConfigureKeyboardOutput::from_syscall_output(syscall(&Syscall::ConfigureKeyboard(
+        SyscallConfigureKeyboardInput { keyboard, command },
+    )))
+    .unwrap()
+    .0
+}
+
+pub fn syscall_get_keyboard_locks(keyboard: Handle) -> Result<KeyboardLocks, HandleError> {
+    SyscallKeyboardLocksOutput::from_syscall_output(syscall(&Syscall::GetKeyboardLocks(keyboard)))
+        .unwrap()
+        .0
+}
+
+/// Does not block. Returns the bytes that were received since the last read.
+pub fn syscall_read_serial(serial: Handle, buffer: &mut [MaybeUninit<u8>]) -> &mut [u8] {
+    let count = syscall(&Syscall::ReadSerial(serial, buffer.into())) as usize;
+    unsafe { buffer[..count].assume_init_mut() }
+}
+
+pub fn syscall_stop_recording_mouse(mouse: Handle) -> Result<(), HandleError> {
+    SyscallHandleOutput::from_syscall_output(syscall(&Syscall::StopRecordingMouse(mouse)))
+        .unwrap()
+        .0
+}
+
+pub fn syscall_poll_mouse(
+    mouse: Handle,
+    buffer: &mut [MaybeUninit<MouseEvent>],
+) -> &mut [MouseEvent] {
+    let count = syscall(&Syscall::PollMouse(mouse, buffer.into())) as usize;
+    unsafe { buffer[..count].assume_init_mut() }
+}
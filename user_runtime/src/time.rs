@@ -0,0 +1,31 @@
+use core::{
+    arch::x86_64::_rdtsc,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use common::{mem::USER_SPACE_TIME_PAGE, time_page::TimePage};
+
+/// Used to make sure that the time never goes backwards, because the kernel's time base can be a little behind the TSC's estimate
+static LAST_MONOTONIC_NANOS: AtomicU64 = AtomicU64::new(0);
+
+fn time_page() -> &'static TimePage {
+    // The kernel maps this page in every process and never unmaps it
+    unsafe { &*(USER_SPACE_TIME_PAGE as *const TimePage) }
+}
+
+/// Like [`syscall_get_monotonic_time`](crate::syscall::syscall_get_monotonic_time), but without making a syscall
+pub fn monotonic_time() -> Duration {
+    let time_base = time_page().read();
+    let nanos = time_base.monotonic_nanos(unsafe { _rdtsc() });
+    let nanos = LAST_MONOTONIC_NANOS
+        .fetch_max(nanos, Ordering::Relaxed)
+        .max(nanos);
+    Duration::from_nanos(nanos)
+}
+
+/// Like [`syscall_get_real_time`](crate::syscall::syscall_get_real_time), but without making a syscall
+pub fn real_time() -> Duration {
+    let time_base = time_page().read();
+    Duration::from_nanos(time_base.real_time_nanos(unsafe { _rdtsc() }))
+}
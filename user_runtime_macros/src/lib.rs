@@ -0,0 +1,59 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, spanned::Spanned, Error, ItemFn, ReturnType};
+
+/// Makes a function the entry point of a user program. See `user_runtime::entry`.
+#[proc_macro_attribute]
+pub fn entry(args: TokenStream, item: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return Error::new(
+            proc_macro2::TokenStream::from(args).span(),
+            "#[entry] doesn't take arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let main = parse_macro_input!(item as ItemFn);
+    let signature = &main.sig;
+    let max_inputs = if signature.asyncness.is_some() { 1 } else { 0 };
+    if signature.inputs.len() > max_inputs || !signature.generics.params.is_empty() {
+        return Error::new(
+            signature.span(),
+            "the #[entry] function can't have generics or arguments, except for a Spawner if it is async",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if let ReturnType::Type(_, return_type) = &signature.output {
+        if !matches!(**return_type, syn::Type::Never(_)) {
+            return Error::new(
+                return_type.span(),
+                "the #[entry] function must return () or !",
+            )
+            .to_compile_error()
+            .into();
+        }
+    }
+
+    let name = &signature.ident;
+    let call = match (signature.asyncness, signature.inputs.len()) {
+        (Some(_), 0) => quote!(::user_runtime::executor::Executor::new().block_on(#name())),
+        (Some(_), _) => quote! {{
+            let executor = ::user_runtime::executor::Executor::new();
+            let spawner = executor.spawner();
+            executor.block_on(#name(spawner))
+        }},
+        (None, _) => quote!(#name()),
+    };
+    quote! {
+        #[unsafe(no_mangle)]
+        extern "C" fn _start() -> ! {
+            ::user_runtime::start(|| {
+                #call;
+            })
+        }
+
+        #main
+    }
+    .into()
+}
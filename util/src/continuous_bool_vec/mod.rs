@@ -1,6 +1,7 @@
 pub mod get_continuous_range;
 pub mod is_range_available;
 pub mod new;
+pub mod segments;
 pub mod set;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
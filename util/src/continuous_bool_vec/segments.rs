@@ -0,0 +1,63 @@
+use core::ops::{Deref, Range};
+
+use super::ContinuousBoolVec;
+
+impl<T: Deref<Target = [usize]>> ContinuousBoolVec<T> {
+    /// Iterates over the ranges that have the same value, in order
+    pub fn segments(&self) -> impl Iterator<Item = (bool, Range<usize>)> + '_ {
+        self.len_vec
+            .iter()
+            .scan(
+                (self.start_value, 0),
+                |(current_segment_value, current_segment_start_pos), len| {
+                    let segment = (
+                        *current_segment_value,
+                        *current_segment_start_pos..*current_segment_start_pos + len,
+                    );
+                    *current_segment_value = !*current_segment_value;
+                    *current_segment_start_pos += len;
+                    Some(segment)
+                },
+            )
+            // Segments can have a length of 0
+            .filter(|(_, range)| !range.is_empty())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::ContinuousBoolVec;
+
+    #[test]
+    fn blank() {
+        let c = ContinuousBoolVec {
+            start_value: false,
+            len_vec: vec![100],
+        };
+        assert_eq!(c.segments().collect::<Vec<_>>(), vec![(false, 0..100)]);
+    }
+
+    #[test]
+    fn alternating() {
+        let c = ContinuousBoolVec {
+            start_value: true,
+            len_vec: vec![10, 20, 30],
+        };
+        assert_eq!(
+            c.segments().collect::<Vec<_>>(),
+            vec![(true, 0..10), (false, 10..30), (true, 30..60)]
+        );
+    }
+
+    #[test]
+    fn skips_empty_segments() {
+        let c = ContinuousBoolVec {
+            start_value: false,
+            len_vec: vec![0, 50, 50],
+        };
+        assert_eq!(
+            c.segments().collect::<Vec<_>>(),
+            vec![(true, 0..50), (false, 50..100)]
+        );
+    }
+}